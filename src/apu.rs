@@ -0,0 +1,319 @@
+#[cfg(test)]
+mod tests;
+mod dmc;
+pub mod envelope;
+mod filter;
+mod frame_counter;
+pub mod length_counter;
+pub mod mixer;
+mod noise;
+mod output;
+pub mod pulse;
+mod resampler;
+mod triangle;
+
+use crate::apu::dmc::Dmc;
+use crate::apu::frame_counter::FrameCounter;
+use crate::apu::noise::Noise;
+use crate::apu::output::OutputStage;
+use crate::apu::pulse::{Pulse, PulseChannel};
+use crate::apu::triangle::Triangle;
+use crate::region::Region;
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+//Audio sources that can be muted or captured on their own. Expansion is whatever sound
+//hardware the cartridge adds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    Expansion,
+}
+impl Channel {
+    pub const ALL: [Channel; 6] = [
+        Channel::Pulse1,
+        Channel::Pulse2,
+        Channel::Triangle,
+        Channel::Noise,
+        Channel::Dmc,
+        Channel::Expansion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "pulse1",
+            Channel::Pulse2 => "pulse2",
+            Channel::Triangle => "triangle",
+            Channel::Noise => "noise",
+            Channel::Dmc => "dmc",
+            Channel::Expansion => "expansion",
+        }
+    }
+}
+
+//2A03 audio. Registers live at $4000-$4013, $4015 and $4017
+pub struct Apu {
+    pulse_1: Pulse,
+    pulse_2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+
+    region: Region,
+    //CPU cycles since power on. Pulse and noise timers tick on every other one
+    cycle: u64,
+
+    //Mixed output plus optional isolated streams, one per Channel. Captures ignore muting
+    sample_rate: u32,
+    output: OutputStage,
+    captures: [Option<OutputStage>; 6],
+    muted: [bool; 6],
+    //Cartridge audio, already scaled relative to the 2A03 channels
+    expansion_level: f32,
+}
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse_1: Pulse::new(PulseChannel::One),
+            pulse_2: Pulse::new(PulseChannel::Two),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            region: Region::Ntsc,
+            cycle: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            output: OutputStage::new(Region::Ntsc.cpu_clock_hz(), DEFAULT_SAMPLE_RATE),
+            captures: Default::default(),
+            muted: [false; 6],
+            expansion_level: 0.0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.set_sample_rate(self.sample_rate);
+    }
+
+    //Host output rate, usually 44100 or 48000. Drops any samples not yet taken
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        let clock_rate = self.region.cpu_clock_hz();
+        self.sample_rate = sample_rate;
+        self.output = OutputStage::new(clock_rate, sample_rate);
+        for capture in self.captures.iter_mut().flatten() {
+            *capture = OutputStage::new(clock_rate, sample_rate);
+        }
+    }
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    //Every sample generated since the last call, mono in -1.0 to 1.0. Frontends call this
+    //once per frame and hand the buffer to their audio backend
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.output.take_samples()
+    }
+    //Same as take_samples as signed 16 bit PCM
+    pub fn take_samples_i16(&mut self) -> Vec<i16> {
+        output::to_i16(&self.take_samples())
+    }
+
+    //Muted channels drop out of the mix but still run, so unmuting is seamless
+    pub fn set_muted(&mut self, channel: Channel, muted: bool) {
+        self.muted[channel as usize] = muted;
+    }
+    pub fn is_muted(&self, channel: Channel) -> bool {
+        self.muted[channel as usize]
+    }
+    //Mute everything except one channel
+    pub fn solo(&mut self, channel: Channel) {
+        for other in Channel::ALL {
+            self.muted[other as usize] = other != channel;
+        }
+    }
+    pub fn unmute_all(&mut self) {
+        self.muted = [false; 6];
+    }
+
+    //Record a channel on its own, at the level it has in the full mix
+    pub fn set_capture(&mut self, channel: Channel, enabled: bool) {
+        self.captures[channel as usize] = if enabled {
+            Some(OutputStage::new(self.region.cpu_clock_hz(), self.sample_rate))
+        } else {
+            None
+        };
+    }
+    //Samples captured for one channel since the last call. Empty if capture is off
+    pub fn take_channel_samples(&mut self, channel: Channel) -> Vec<f32> {
+        match &mut self.captures[channel as usize] {
+            Some(capture) => capture.take_samples(),
+            None => vec![],
+        }
+    }
+    pub fn take_channel_samples_i16(&mut self, channel: Channel) -> Vec<i16> {
+        output::to_i16(&self.take_channel_samples(channel))
+    }
+
+    //Level of the cartridge's sound chip, updated by the bus every CPU cycle
+    pub fn set_expansion_level(&mut self, level: f32) {
+        self.expansion_level = level;
+    }
+
+    //Reset silences every channel, same as writing $00 to $4015, and restarts the frame
+    //counter with whatever was last written to $4017
+    pub fn reset(&mut self) {
+        self.cpu_write(0x4015, 0x00);
+        self.frame_counter.reset(self.cycle);
+    }
+
+    //Level of the APU's IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_counter.irq() || self.dmc.irq_flag
+    }
+
+    //Address the DMC wants fetched. The bus performs the read, stalls the CPU and hands
+    //the byte back through dmc_load_sample
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        self.dmc.dma_request()
+    }
+    pub fn dmc_load_sample(&mut self, value: u8) {
+        self.dmc.load_sample(value);
+    }
+
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            //Status: one bit per channel whose length counter is still running, bit 4 while
+            //the DMC has bytes left and the two IRQ sources in bits 6-7
+            0x4015 => {
+                let mut status = 0x00;
+                if self.pulse_1.length_counter.active() {
+                    status |= 0x01;
+                }
+                if self.pulse_2.length_counter.active() {
+                    status |= 0x02;
+                }
+                if self.triangle.length_counter.active() {
+                    status |= 0x04;
+                }
+                if self.noise.length_counter.active() {
+                    status |= 0x08;
+                }
+                if self.dmc.active() {
+                    status |= 0x10;
+                }
+                if self.frame_counter.irq() {
+                    status |= 0x40;
+                }
+                if self.dmc.irq_flag {
+                    status |= 0x80;
+                }
+                //Reading the status acknowledges the frame IRQ
+                self.frame_counter.acknowledge_irq();
+                status
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse_1.write(address - 0x4000, value),
+            0x4004..=0x4007 => self.pulse_2.write(address - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write(address - 0x4008, value),
+            0x400C..=0x400F => self.noise.write(address - 0x400C, value, self.region),
+            0x4010..=0x4013 => self.dmc.write(address - 0x4010, value, self.region),
+            0x4015 => {
+                self.pulse_1.length_counter.set_enabled(value & 0x01 != 0);
+                self.pulse_2.length_counter.set_enabled(value & 0x02 != 0);
+                self.triangle.length_counter.set_enabled(value & 0x04 != 0);
+                self.noise.length_counter.set_enabled(value & 0x08 != 0);
+                self.dmc.set_enabled(value & 0x10 != 0);
+            }
+            0x4017 => self.frame_counter.write(value, self.cycle),
+            _ => {}
+        }
+    }
+
+    //Advance the APU by a single CPU cycle
+    pub fn clock(&mut self) {
+        let events = self.frame_counter.clock(self.region);
+        if events.quarter_frame {
+            self.quarter_frame();
+        }
+        if events.half_frame {
+            self.half_frame();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycle % 2 == 1 {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+
+        self.mix_output();
+        self.cycle += 1;
+    }
+
+    fn mix_output(&mut self) {
+        let levels = self.channel_outputs();
+
+        let mut audible = levels;
+        for (level, muted) in audible.iter_mut().zip(self.muted.iter()) {
+            if *muted {
+                *level = 0;
+            }
+        }
+        let mut amplitude = mixer::mix(audible);
+        if !self.muted[Channel::Expansion as usize] {
+            amplitude += self.expansion_level;
+        }
+        self.output.push(amplitude);
+
+        for (index, capture) in self.captures.iter_mut().enumerate() {
+            if let Some(capture) = capture {
+                let isolated = if index == Channel::Expansion as usize {
+                    self.expansion_level
+                } else {
+                    let mut alone = [0; 5];
+                    alone[index] = levels[index];
+                    mixer::mix(alone)
+                };
+                capture.push(isolated);
+            }
+        }
+    }
+
+    //Envelopes and the triangle's linear counter
+    fn quarter_frame(&mut self) {
+        self.pulse_1.clock_envelope();
+        self.pulse_2.clock_envelope();
+        self.triangle.clock_linear_counter();
+        self.noise.clock_envelope();
+    }
+
+    //Length counters and sweep units
+    fn half_frame(&mut self) {
+        self.pulse_1.clock_length_and_sweep();
+        self.pulse_2.clock_length_and_sweep();
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+
+    //Current level of each channel: pulse 1, pulse 2, triangle, noise (4 bit) and DMC (7 bit)
+    pub fn channel_outputs(&self) -> [u8; 5] {
+        [
+            self.pulse_1.output(),
+            self.pulse_2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+}
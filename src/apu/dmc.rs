@@ -0,0 +1,251 @@
+use crate::region::Region;
+
+//Output unit periods in CPU cycles
+const NTSC_DMC_RATES: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const PAL_DMC_RATES: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+//Delta modulation channel. Plays 1 bit deltas fetched from $8000-$FFFF by DMA
+pub struct Dmc {
+    irq_enable: bool,
+    loop_flag: bool,
+    timer_period: u16,
+    timer: u16,
+    pub irq_flag: bool,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    //Output unit
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    output_level: u8,
+}
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            irq_enable: false,
+            loop_flag: false,
+            timer_period: NTSC_DMC_RATES[0],
+            timer: 0,
+            irq_flag: false,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+        }
+    }
+    //Register offset 0-3 within $4010-$4013
+    pub fn write(&mut self, register: u16, value: u8, region: Region) {
+        match register {
+            0 => {
+                self.irq_enable = value & 0x80 != 0;
+                if !self.irq_enable {
+                    self.irq_flag = false;
+                }
+                self.loop_flag = value & 0x40 != 0;
+                let rates = match region {
+                    Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+                    Region::Pal => &PAL_DMC_RATES,
+                };
+                self.timer_period = rates[(value & 0x0F) as usize];
+            }
+            1 => self.output_level = value & 0x7F,
+            2 => self.sample_address = 0xC000 | ((value as u16) << 6),
+            3 => self.sample_length = ((value as u16) << 4) | 0x0001,
+            _ => {}
+        }
+    }
+    //$4015 bit 4. Enabling only restarts the sample if the previous one has finished
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    //Address the reader wants fetched, if the sample buffer is empty and bytes remain
+    pub fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+    //Result of the DMA fetch. Address wraps from $FFFF back to $8000
+    pub fn load_sample(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enable {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    //Periods are in CPU cycles, so this runs every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift_register & 0x01 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift_register = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+    //7 bit level, unlike the 4 bit channels
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_dmc(length: u8) -> Dmc {
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0x0F, Region::Ntsc);
+        dmc.write(2, 0x00, Region::Ntsc);
+        dmc.write(3, length, Region::Ntsc);
+        dmc.set_enabled(true);
+        dmc
+    }
+
+    #[test]
+    fn sample_address_and_length() {
+        let mut dmc = Dmc::new();
+        dmc.write(2, 0xFF, Region::Ntsc);
+        dmc.write(3, 0xFF, Region::Ntsc);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.dma_request(), Some(0xFFC0));
+        assert_eq!(dmc.bytes_remaining, 0xFF1);
+    }
+
+    #[test]
+    fn rate_table_per_region() {
+        let mut dmc = Dmc::new();
+
+        dmc.write(0, 0x0F, Region::Ntsc);
+        assert_eq!(dmc.timer_period, NTSC_DMC_RATES[15]);
+        dmc.write(0, 0x0F, Region::Pal);
+        assert_eq!(dmc.timer_period, PAL_DMC_RATES[15]);
+        dmc.write(0, 0x0F, Region::Dendy);
+        assert_eq!(dmc.timer_period, NTSC_DMC_RATES[15]);
+    }
+
+    #[test]
+    fn address_wraps_to_8000() {
+        let mut dmc = Dmc::new();
+        dmc.write(2, 0xFF, Region::Ntsc);
+        dmc.write(3, 0x04, Region::Ntsc);
+        dmc.set_enabled(true);
+
+        for _ in 0..0x40 {
+            dmc.load_sample(0x00);
+            dmc.sample_buffer = None;
+        }
+        assert_eq!(dmc.dma_request(), Some(0x8000));
+    }
+
+    #[test]
+    fn irq_at_end_of_sample() {
+        let mut dmc = playing_dmc(0x00);
+        dmc.write(0, 0x8F, Region::Ntsc);
+
+        dmc.load_sample(0x00);
+        assert!(dmc.irq_flag);
+        assert!(!dmc.active());
+        assert_eq!(dmc.dma_request(), None);
+
+        //Clearing the enable bit acknowledges it
+        dmc.write(0, 0x0F, Region::Ntsc);
+        assert!(!dmc.irq_flag);
+    }
+
+    #[test]
+    fn loop_restarts_without_irq() {
+        let mut dmc = playing_dmc(0x00);
+        dmc.write(0, 0xCF, Region::Ntsc);
+
+        dmc.load_sample(0x00);
+        assert!(!dmc.irq_flag);
+        assert!(dmc.active());
+    }
+
+    #[test]
+    fn output_follows_deltas() {
+        let mut dmc = playing_dmc(0x00);
+        dmc.write(1, 0x40, Region::Ntsc);
+        dmc.load_sample(0b0000_0011);
+
+        //First output cycle only loads the shift register
+        let period = NTSC_DMC_RATES[15] as usize;
+        for _ in 0..period * 8 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 0x40);
+
+        for _ in 0..period * 2 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 0x44);
+        for _ in 0..period {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 0x42);
+    }
+
+    #[test]
+    fn output_level_clamps() {
+        let mut dmc = playing_dmc(0x00);
+        dmc.write(1, 0x7F, Region::Ntsc);
+        dmc.silence = false;
+        dmc.shift_register = 0xFF;
+        dmc.clock_timer();
+        assert_eq!(dmc.output(), 0x7F);
+    }
+}
@@ -0,0 +1,83 @@
+use std::f32::consts::PI;
+
+//First order high-pass. The NES output stage has two of these (90Hz and 440Hz) that
+//remove the DC offset of the DAC
+pub struct HighPass {
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+impl HighPass {
+    pub fn new(cutoff_hz: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff_hz);
+        let dt = 1.0 / sample_rate;
+        HighPass {
+            alpha: rc / (rc + dt),
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.previous_output = self.alpha * (self.previous_output + input - self.previous_input);
+        self.previous_input = input;
+        self.previous_output
+    }
+}
+
+//First order low-pass, the 14kHz roll off of the output stage
+pub struct LowPass {
+    alpha: f32,
+    previous_output: f32,
+}
+impl LowPass {
+    pub fn new(cutoff_hz: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff_hz);
+        let dt = 1.0 / sample_rate;
+        LowPass {
+            alpha: dt / (rc + dt),
+            previous_output: 0.0,
+        }
+    }
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.previous_output += self.alpha * (input - self.previous_output);
+        self.previous_output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn high_pass_removes_dc() {
+        let mut filter = HighPass::new(90.0, 44100.0);
+        let mut output = 1.0;
+        for _ in 0..44100 {
+            output = filter.process(0.5);
+        }
+        assert!(output.abs() < 0.001);
+    }
+
+    #[test]
+    fn low_pass_passes_dc() {
+        let mut filter = LowPass::new(14000.0, 44100.0);
+        let mut output = 0.0;
+        for _ in 0..100 {
+            output = filter.process(0.5);
+        }
+        assert!((output - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn low_pass_attenuates_nyquist() {
+        let mut filter = LowPass::new(14000.0, 44100.0);
+        let mut peak: f32 = 0.0;
+        for n in 0..1000 {
+            let output = filter.process(if n % 2 == 0 { 1.0 } else { -1.0 });
+            if n > 100 {
+                peak = peak.max(output.abs());
+            }
+        }
+        assert!(peak < 0.7);
+    }
+}
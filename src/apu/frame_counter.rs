@@ -0,0 +1,276 @@
+use crate::region::Region;
+
+//CPU cycles after a reset of the sequencer at which each step fires. The last entry
+//doubles as cycle 0 of the next sequence
+const NTSC_FOUR_STEP: [u32; 6] = [7457, 14913, 22371, 29828, 29829, 29830];
+const NTSC_FIVE_STEP: [u32; 6] = [7457, 14913, 22371, 29829, 37281, 37282];
+const PAL_FOUR_STEP: [u32; 6] = [8313, 16627, 24939, 33252, 33253, 33254];
+const PAL_FIVE_STEP: [u32; 6] = [8313, 16627, 24939, 33253, 41565, 41566];
+
+//What the frame counter asks the channels to do on a given cycle
+#[derive(Default, PartialEq, Debug)]
+pub struct FrameEvents {
+    pub quarter_frame: bool,
+    pub half_frame: bool,
+}
+
+//$4017 frame sequencer. Generates quarter/half frame clocks and the frame IRQ
+pub struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    irq_flag: bool,
+    cycles: u32,
+    step: usize,
+
+    //$4017 writes only restart the sequencer 3 or 4 CPU cycles later
+    write_delay: u8,
+    pending_five_step: bool,
+    last_value: u8,
+}
+impl FrameCounter {
+    pub fn new() -> Self {
+        let mut frame_counter = FrameCounter {
+            five_step: false,
+            irq_inhibit: false,
+            irq_flag: false,
+            cycles: 0,
+            step: 0,
+            write_delay: 0,
+            pending_five_step: false,
+            last_value: 0x00,
+        };
+        //Power on behaves as if $00 was written just before the CPU starts
+        frame_counter.write(0x00, 0);
+        frame_counter
+    }
+
+    //Reset writes the last value written to $4017 again and clears the IRQ flag
+    pub fn reset(&mut self, cpu_cycle: u64) {
+        self.irq_flag = false;
+        self.write(self.last_value, cpu_cycle);
+    }
+
+    pub fn write(&mut self, value: u8, cpu_cycle: u64) {
+        self.last_value = value;
+        self.irq_inhibit = value & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_flag = false;
+        }
+
+        //Writes landing on an APU cycle take effect 3 CPU cycles later, writes between
+        //APU cycles take 4
+        self.pending_five_step = value & 0x80 != 0;
+        self.write_delay = if cpu_cycle % 2 == 1 { 4 } else { 3 };
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq_flag
+    }
+
+    //$4015 reads acknowledge the frame IRQ
+    pub fn acknowledge_irq(&mut self) {
+        self.irq_flag = false;
+    }
+
+    //Advance by one CPU cycle
+    pub fn clock(&mut self, region: Region) -> FrameEvents {
+        let mut events = FrameEvents::default();
+
+        if self.write_delay > 0 {
+            self.write_delay -= 1;
+            if self.write_delay == 0 {
+                self.five_step = self.pending_five_step;
+                self.cycles = 0;
+                self.step = 0;
+                //Switching to 5 step mode clocks everything straight away
+                if self.five_step {
+                    events.quarter_frame = true;
+                    events.half_frame = true;
+                }
+                return events;
+            }
+        }
+
+        self.cycles += 1;
+        //The Dendy's clone APU counts NTSC steps, only its CPU and PPU dividers differ
+        let steps = match (region, self.five_step) {
+            (Region::Ntsc | Region::Dendy, false) => &NTSC_FOUR_STEP,
+            (Region::Ntsc | Region::Dendy, true) => &NTSC_FIVE_STEP,
+            (Region::Pal, false) => &PAL_FOUR_STEP,
+            (Region::Pal, true) => &PAL_FIVE_STEP,
+        };
+
+        if self.cycles == steps[self.step] {
+            match (self.five_step, self.step) {
+                (_, 0) | (_, 2) => events.quarter_frame = true,
+                (_, 1) => {
+                    events.quarter_frame = true;
+                    events.half_frame = true;
+                }
+                (false, 3) | (false, 5) => self.set_irq(),
+                (false, 4) => {
+                    events.quarter_frame = true;
+                    events.half_frame = true;
+                    self.set_irq();
+                }
+                (true, 4) => {
+                    events.quarter_frame = true;
+                    events.half_frame = true;
+                }
+                _ => {}
+            }
+
+            self.step += 1;
+            if self.step == steps.len() {
+                self.step = 0;
+                self.cycles = 0;
+            }
+        }
+
+        events
+    }
+
+    fn set_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.irq_flag = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Run until the pending $4017 write has landed
+    fn settle(frame_counter: &mut FrameCounter) {
+        while frame_counter.write_delay > 0 {
+            frame_counter.clock(Region::Ntsc);
+        }
+    }
+
+    //CPU cycles after the write lands at which each kind of event fired
+    fn run(frame_counter: &mut FrameCounter, region: Region, cycles: u32) -> (Vec<u32>, Vec<u32>, Option<u32>) {
+        let mut quarters = vec![];
+        let mut halves = vec![];
+        let mut first_irq = None;
+        for cycle in 1..=cycles {
+            let events = frame_counter.clock(region);
+            if events.quarter_frame {
+                quarters.push(cycle);
+            }
+            if events.half_frame {
+                halves.push(cycle);
+            }
+            if first_irq.is_none() && frame_counter.irq() {
+                first_irq = Some(cycle);
+            }
+        }
+        (quarters, halves, first_irq)
+    }
+
+    #[test]
+    fn four_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        settle(&mut frame_counter);
+
+        let (quarters, halves, first_irq) = run(&mut frame_counter, Region::Ntsc, 29830);
+        assert_eq!(quarters, vec![7457, 14913, 22371, 29829]);
+        assert_eq!(halves, vec![14913, 29829]);
+        assert_eq!(first_irq, Some(29828));
+    }
+
+    #[test]
+    fn four_step_repeats() {
+        let mut frame_counter = FrameCounter::new();
+        settle(&mut frame_counter);
+
+        run(&mut frame_counter, Region::Ntsc, 29830);
+        let (quarters, _, _) = run(&mut frame_counter, Region::Ntsc, 29830);
+        assert_eq!(quarters, vec![7457, 14913, 22371, 29829]);
+    }
+
+    #[test]
+    fn five_step_sequence_has_no_irq() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0x80, 0);
+
+        //Landing the write clocks quarter and half frame immediately
+        let mut landed = FrameEvents::default();
+        while frame_counter.write_delay > 0 {
+            landed = frame_counter.clock(Region::Ntsc);
+        }
+        assert!(landed.quarter_frame && landed.half_frame);
+
+        let (quarters, halves, first_irq) = run(&mut frame_counter, Region::Ntsc, 37282);
+        assert_eq!(quarters, vec![7457, 14913, 22371, 37281]);
+        assert_eq!(halves, vec![14913, 37281]);
+        assert_eq!(first_irq, None);
+    }
+
+    #[test]
+    fn irq_inhibit_clears_flag() {
+        let mut frame_counter = FrameCounter::new();
+        settle(&mut frame_counter);
+        run(&mut frame_counter, Region::Ntsc, 29830);
+        assert!(frame_counter.irq());
+
+        frame_counter.write(0x40, 0);
+        assert!(!frame_counter.irq());
+        settle(&mut frame_counter);
+
+        let (_, _, first_irq) = run(&mut frame_counter, Region::Ntsc, 29830 * 2);
+        assert_eq!(first_irq, None);
+    }
+
+    #[test]
+    fn write_delay_depends_on_cycle_parity() {
+        let mut frame_counter = FrameCounter::new();
+
+        frame_counter.write(0x00, 10);
+        assert_eq!(frame_counter.write_delay, 3);
+        frame_counter.write(0x00, 11);
+        assert_eq!(frame_counter.write_delay, 4);
+    }
+
+    #[test]
+    fn reset_rewrites_last_value() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0xC0, 0);
+        settle(&mut frame_counter);
+        frame_counter.irq_flag = true;
+
+        frame_counter.reset(0);
+        assert!(!frame_counter.irq());
+        settle(&mut frame_counter);
+        assert!(frame_counter.five_step);
+        assert!(frame_counter.irq_inhibit);
+    }
+
+    #[test]
+    fn pal_four_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        settle(&mut frame_counter);
+
+        let (quarters, _, first_irq) = run(&mut frame_counter, Region::Pal, 33254);
+        assert_eq!(quarters, vec![8313, 16627, 24939, 33253]);
+        assert_eq!(first_irq, Some(33252));
+    }
+
+    #[test]
+    fn dendy_uses_ntsc_step_timings() {
+        let mut frame_counter = FrameCounter::new();
+        settle(&mut frame_counter);
+
+        let (quarters, halves, first_irq) = run(&mut frame_counter, Region::Dendy, 29830);
+        assert_eq!(quarters, vec![7457, 14913, 22371, 29829]);
+        assert_eq!(halves, vec![14913, 29829]);
+        assert_eq!(first_irq, Some(29828));
+
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0x80, 0);
+        settle(&mut frame_counter);
+        let (quarters, _, first_irq) = run(&mut frame_counter, Region::Dendy, 37282);
+        assert_eq!(quarters, vec![7457, 14913, 22371, 37281]);
+        assert_eq!(first_irq, None);
+    }
+}
@@ -0,0 +1,47 @@
+use once_cell::sync::Lazy;
+
+//Lookup tables for the 2A03's non-linear DAC, from the nesdev approximation
+//pulse_out = 95.52 / (8128 / (pulse1 + pulse2) + 100)
+//tnd_out = 163.67 / (24329 / (3 * triangle + 2 * noise + dmc) + 100)
+static PULSE_TABLE: Lazy<[f32; 31]> = Lazy::new(|| {
+    let mut table = [0.0; 31];
+    for (n, entry) in table.iter_mut().enumerate().skip(1) {
+        *entry = 95.52 / (8128.0 / n as f32 + 100.0);
+    }
+    table
+});
+static TND_TABLE: Lazy<[f32; 203]> = Lazy::new(|| {
+    let mut table = [0.0; 203];
+    for (n, entry) in table.iter_mut().enumerate().skip(1) {
+        *entry = 163.67 / (24329.0 / n as f32 + 100.0);
+    }
+    table
+});
+
+//Mix pulse 1, pulse 2, triangle, noise and DMC levels into a 0.0-1.0 amplitude
+pub fn mix(outputs: [u8; 5]) -> f32 {
+    let [pulse_1, pulse_2, triangle, noise, dmc] = outputs.map(|level| level as usize);
+    PULSE_TABLE[pulse_1 + pulse_2] + TND_TABLE[3 * triangle + 2 * noise + dmc]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence_is_zero() {
+        assert_eq!(mix([0, 0, 0, 0, 0]), 0.0);
+    }
+
+    #[test]
+    fn full_scale_is_about_one() {
+        let peak = mix([15, 15, 15, 15, 127]);
+        assert!(peak > 0.95 && peak < 1.0);
+    }
+
+    #[test]
+    fn mixing_is_non_linear() {
+        //Two pulses together are quieter than twice one pulse
+        assert!(mix([15, 15, 0, 0, 0]) < 2.0 * mix([15, 0, 0, 0, 0]));
+    }
+}
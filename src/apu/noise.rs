@@ -0,0 +1,129 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::region::Region;
+
+//Timer periods in CPU cycles
+const NTSC_NOISE_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const PAL_NOISE_PERIODS: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+pub struct Noise {
+    //15 bit linear feedback shift register. Mode 1 taps bit 6 instead of bit 1 for a
+    //short 93 step sequence that sounds metallic
+    shift_register: u16,
+    mode: bool,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            shift_register: 1,
+            mode: false,
+            timer_period: NTSC_NOISE_PERIODS[0],
+            timer: 0,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+    //Register offset 0-3 within $400C-$400F. $400D is unused
+    pub fn write(&mut self, register: u16, value: u8, region: Region) {
+        match register {
+            0 => {
+                self.length_counter.set_halt(value & 0x20 != 0);
+                self.envelope.write_control(value);
+            }
+            2 => {
+                self.mode = value & 0x80 != 0;
+                let periods = match region {
+                    Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+                    Region::Pal => &PAL_NOISE_PERIODS,
+                };
+                self.timer_period = periods[(value & 0x0F) as usize];
+            }
+            3 => {
+                self.length_counter.load(value >> 3);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+    //Periods are in CPU cycles, so this runs every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x01;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+    pub fn clock_length(&mut self) {
+        self.length_counter.clock();
+    }
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || self.shift_register & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence_length(noise: &mut Noise) -> usize {
+        let start = noise.shift_register;
+        let mut steps = 0;
+        loop {
+            noise.timer = 0;
+            noise.clock_timer();
+            steps += 1;
+            if noise.shift_register == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn lfsr_first_step() {
+        let mut noise = Noise::new();
+
+        noise.clock_timer();
+        //Bit 0 xor bit 1 of 1 is 1, shifted into bit 14
+        assert_eq!(noise.shift_register, 0x4000);
+    }
+
+    #[test]
+    fn lfsr_long_mode_sequence() {
+        let mut noise = Noise::new();
+        assert_eq!(sequence_length(&mut noise), 32767);
+    }
+
+    #[test]
+    fn lfsr_short_mode_sequence() {
+        let mut noise = Noise::new();
+        noise.write(2, 0x80, Region::Ntsc);
+
+        assert_eq!(sequence_length(&mut noise), 93);
+    }
+
+    #[test]
+    fn period_table_per_region() {
+        let mut noise = Noise::new();
+
+        noise.write(2, 0x02, Region::Ntsc);
+        assert_eq!(noise.timer_period, 16);
+        noise.write(2, 0x02, Region::Pal);
+        assert_eq!(noise.timer_period, 14);
+        noise.write(2, 0x02, Region::Dendy);
+        assert_eq!(noise.timer_period, 16);
+    }
+}
@@ -0,0 +1,42 @@
+use crate::apu::filter::{HighPass, LowPass};
+use crate::apu::resampler::Resampler;
+
+//One audio stream: amplitude per CPU cycle in, host rate samples out. Filtered like the
+//NES's own analog output (two high-passes and a low-pass)
+pub struct OutputStage {
+    resampler: Resampler,
+    high_pass_90: HighPass,
+    high_pass_440: HighPass,
+    low_pass_14k: LowPass,
+}
+impl OutputStage {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let rate = sample_rate as f32;
+        OutputStage {
+            resampler: Resampler::new(clock_rate, sample_rate as f64),
+            high_pass_90: HighPass::new(90.0, rate),
+            high_pass_440: HighPass::new(440.0, rate),
+            low_pass_14k: LowPass::new(14000.0, rate),
+        }
+    }
+    pub fn push(&mut self, amplitude: f32) {
+        self.resampler.push(amplitude);
+    }
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        let mut samples = vec![];
+        self.resampler.read_samples(&mut samples);
+        for sample in samples.iter_mut() {
+            let filtered = self.high_pass_90.process(*sample);
+            let filtered = self.high_pass_440.process(filtered);
+            *sample = self.low_pass_14k.process(filtered);
+        }
+        samples
+    }
+}
+
+pub fn to_i16(samples: &[f32]) -> Vec<i16> {
+    samples
+        .iter()
+        .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+        .collect()
+}
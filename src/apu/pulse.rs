@@ -0,0 +1,170 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+pub const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+//Which pulse channel this is. They differ only in how the sweep unit negates
+#[derive(Clone, Copy, PartialEq)]
+pub enum PulseChannel {
+    One,
+    Two,
+}
+
+pub struct Pulse {
+    channel: PulseChannel,
+    duty: u8,
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    pub length_counter: LengthCounter,
+
+    //Sweep unit
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+impl Pulse {
+    pub fn new(channel: PulseChannel) -> Self {
+        Pulse {
+            channel,
+            duty: 0,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_reload: false,
+            sweep_divider: 0,
+        }
+    }
+    //Register offset 0-3 within $4000-$4003 or $4004-$4007
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length_counter.set_halt(value & 0x20 != 0);
+                self.envelope.write_control(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length_counter.load(value >> 3);
+                //Restarts the duty cycle and the envelope, but not the timer
+                self.sequence_step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+    //Clocked every APU cycle (every other CPU cycle)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+    pub fn clock_length_and_sweep(&mut self) {
+        self.length_counter.clock();
+
+        //Period only updates when the divider hits 0, the unit is enabled, the shift is
+        //non zero and the channel is not muted by the sweep
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.sweep_muting() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            //Pulse 1 subtracts with ones' complement, pulse 2 with two's complement
+            match self.channel {
+                PulseChannel::One => self.timer_period.saturating_sub(change + 1),
+                PulseChannel::Two => self.timer_period.saturating_sub(change),
+            }
+        } else {
+            self.timer_period + change
+        }
+    }
+    //The sweep unit mutes the channel even when it is disabled
+    fn sweep_muting(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x07FF
+    }
+    //Current 4 bit output level
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || self.sweep_muting() || DUTY_TABLE[self.duty as usize][self.sequence_step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_negate_differs_per_channel() {
+        let mut pulse_1 = Pulse::new(PulseChannel::One);
+        let mut pulse_2 = Pulse::new(PulseChannel::Two);
+
+        for pulse in [&mut pulse_1, &mut pulse_2] {
+            pulse.length_counter.set_enabled(true);
+            pulse.write(1, 0x89); // enabled, period 0, negate, shift 1
+            pulse.write(2, 0x64);
+            pulse.write(3, 0x08);
+
+            //Divider starts at 0, so the first half frame updates the period
+            pulse.clock_length_and_sweep();
+        }
+
+        assert_eq!(pulse_1.timer_period, 0x64 - 0x32 - 1);
+        assert_eq!(pulse_2.timer_period, 0x64 - 0x32);
+    }
+
+    #[test]
+    fn sweep_raises_period() {
+        let mut pulse = Pulse::new(PulseChannel::One);
+
+        pulse.write(1, 0x92); // enabled, period 1, shift 2
+        pulse.write(2, 0x00);
+        pulse.write(3, 0x01); // period $100
+
+        pulse.clock_length_and_sweep(); // divider 0, update and reload
+        assert_eq!(pulse.timer_period, 0x140);
+        pulse.clock_length_and_sweep(); // divider 1 -> 0
+        assert_eq!(pulse.timer_period, 0x140);
+        pulse.clock_length_and_sweep();
+        assert_eq!(pulse.timer_period, 0x190);
+    }
+}
@@ -0,0 +1,137 @@
+use once_cell::sync::Lazy;
+use std::f64::consts::PI;
+
+//Band-limited step synthesis. Every change in amplitude is added to the output as a
+//windowed sinc impulse at its exact fractional output position. Integrating the impulses
+//gives band-limited steps, so the 1.79MHz square waves resample without aliasing
+const KERNEL_WIDTH: usize = 16;
+const KERNEL_PHASES: usize = 64;
+//Fraction of the output Nyquist frequency to keep
+const KERNEL_CUTOFF: f64 = 0.9;
+
+static KERNEL: Lazy<[[f32; KERNEL_WIDTH]; KERNEL_PHASES]> = Lazy::new(|| {
+    let mut kernel = [[0.0; KERNEL_WIDTH]; KERNEL_PHASES];
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        let offset = phase as f64 / KERNEL_PHASES as f64;
+        let mut weights = [0.0f64; KERNEL_WIDTH];
+
+        for (tap, weight) in weights.iter_mut().enumerate() {
+            //Distance from the impulse, which sits in the middle of the kernel
+            let t = tap as f64 - (KERNEL_WIDTH / 2) as f64 + 1.0 - offset;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t * KERNEL_CUTOFF).sin() / (PI * t * KERNEL_CUTOFF)
+            };
+            //Blackman window spanning the kernel
+            let x = (t + (KERNEL_WIDTH / 2) as f64) / KERNEL_WIDTH as f64;
+            let window = if (0.0..=1.0).contains(&x) {
+                0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()
+            } else {
+                0.0
+            };
+            *weight = sinc * window;
+        }
+
+        //Every phase must sum to 1 so a step settles at exactly its height
+        let sum: f64 = weights.iter().sum();
+        for (tap, weight) in taps.iter_mut().zip(weights.iter()) {
+            *tap = (weight / sum) as f32;
+        }
+    }
+    kernel
+});
+
+pub struct Resampler {
+    //Output samples per input clock
+    ratio: f64,
+    //Output position of the next input clock, relative to the start of the buffer
+    position: f64,
+    last_amplitude: f32,
+    //Impulses waiting to be integrated
+    buffer: Vec<f32>,
+    integrator: f32,
+}
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Resampler {
+            ratio: sample_rate / clock_rate,
+            position: 0.0,
+            last_amplitude: 0.0,
+            buffer: vec![0.0; KERNEL_WIDTH],
+            integrator: 0.0,
+        }
+    }
+
+    //Amplitude for one input clock
+    pub fn push(&mut self, amplitude: f32) {
+        let delta = amplitude - self.last_amplitude;
+        if delta != 0.0 {
+            self.last_amplitude = amplitude;
+
+            let index = self.position as usize;
+            let phase = ((self.position - index as f64) * KERNEL_PHASES as f64) as usize;
+            if self.buffer.len() < index + KERNEL_WIDTH {
+                self.buffer.resize(index + KERNEL_WIDTH, 0.0);
+            }
+            for (sample, tap) in self.buffer[index..index + KERNEL_WIDTH].iter_mut().zip(KERNEL[phase].iter()) {
+                *sample += delta * tap;
+            }
+        }
+        self.position += self.ratio;
+    }
+
+    //Append every output sample that no future impulse can touch any more
+    pub fn read_samples(&mut self, output: &mut Vec<f32>) {
+        let ready = self.position as usize;
+        if self.buffer.len() < ready + KERNEL_WIDTH {
+            self.buffer.resize(ready + KERNEL_WIDTH, 0.0);
+        }
+        for impulse in self.buffer.drain(..ready) {
+            self.integrator += impulse;
+            output.push(self.integrator);
+        }
+        self.position -= ready as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK_RATE: f64 = 1_789_773.0;
+
+    #[test]
+    fn output_rate() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 44100.0);
+        let mut output = vec![];
+        for _ in 0..CLOCK_RATE as usize {
+            resampler.push(0.0);
+        }
+        resampler.read_samples(&mut output);
+        assert!((44099..=44100).contains(&output.len()));
+    }
+
+    #[test]
+    fn step_settles_at_height() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 48000.0);
+        let mut output = vec![];
+        for _ in 0..10_000 {
+            resampler.push(0.5);
+        }
+        resampler.read_samples(&mut output);
+        assert!((output.last().unwrap() - 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn square_wave_stays_bounded() {
+        //Ringing from the sinc should stay within a few percent of the step
+        let mut resampler = Resampler::new(CLOCK_RATE, 44100.0);
+        let mut output = vec![];
+        for n in 0..100_000 {
+            resampler.push(if (n / 1000) % 2 == 0 { 1.0 } else { 0.0 });
+        }
+        resampler.read_samples(&mut output);
+        assert!(output.iter().all(|sample| (-0.15..=1.15).contains(sample)));
+    }
+}
@@ -0,0 +1,363 @@
+use super::*;
+
+fn clock_cpu_cycles(apu: &mut Apu, cycles: usize) {
+    for _ in 0..cycles {
+        apu.clock();
+    }
+}
+
+#[test]
+fn test_status_reports_length_counters() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x0F);
+    apu.cpu_write(0x4003, 0x08); // pulse 1, length index 1 = 254
+    apu.cpu_write(0x400F, 0x08); // noise
+
+    assert_eq!(apu.cpu_read(0x4015), 0x09);
+}
+
+#[test]
+fn test_length_load_ignored_when_disabled() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4003, 0x08);
+    assert_eq!(apu.cpu_read(0x4015), 0x00);
+}
+
+#[test]
+fn test_disabling_channel_clears_length() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x04);
+    apu.cpu_write(0x400B, 0x08);
+    assert_eq!(apu.cpu_read(0x4015), 0x04);
+
+    apu.cpu_write(0x4015, 0x00);
+    assert_eq!(apu.cpu_read(0x4015), 0x00);
+}
+
+#[test]
+fn test_length_counter_counts_half_frames() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4003, 0x18); // length index 3 = 2
+
+    apu.half_frame();
+    assert_eq!(apu.cpu_read(0x4015), 0x01);
+    apu.half_frame();
+    assert_eq!(apu.cpu_read(0x4015), 0x00);
+}
+
+#[test]
+fn test_length_counter_halt() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0x20); // halt
+    apu.cpu_write(0x4003, 0x18);
+
+    for _ in 0..4 {
+        apu.half_frame();
+    }
+    assert_eq!(apu.cpu_read(0x4015), 0x01);
+}
+
+#[test]
+fn test_envelope_constant_volume() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0xDA); // duty 3, constant volume 10
+    apu.cpu_write(0x4002, 0x08);
+    apu.cpu_write(0x4003, 0x08);
+
+    //Duty 3 is high on step 0
+    assert_eq!(apu.channel_outputs()[0], 10);
+}
+
+#[test]
+fn test_envelope_decays() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0xC0); // duty 3, envelope period 0
+    apu.cpu_write(0x4002, 0x08);
+    apu.cpu_write(0x4003, 0x08);
+
+    apu.quarter_frame();
+    assert_eq!(apu.channel_outputs()[0], 15);
+    apu.quarter_frame();
+    assert_eq!(apu.channel_outputs()[0], 14);
+    for _ in 0..20 {
+        apu.quarter_frame();
+    }
+    assert_eq!(apu.channel_outputs()[0], 0);
+}
+
+#[test]
+fn test_envelope_loops() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0xE0); // duty 3, loop, period 0
+    apu.cpu_write(0x4002, 0x08);
+    apu.cpu_write(0x4003, 0x08);
+
+    for _ in 0..16 {
+        apu.quarter_frame();
+    }
+    assert_eq!(apu.channel_outputs()[0], 0);
+    apu.quarter_frame();
+    assert_eq!(apu.channel_outputs()[0], 15);
+}
+
+#[test]
+fn test_pulse_timer_period() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0x5F); // duty 1 (steps 1-2 high), constant volume 15
+    apu.cpu_write(0x4002, 0x08); // period 8 = 9 APU cycles per step
+    apu.cpu_write(0x4003, 0x08);
+
+    assert_eq!(apu.channel_outputs()[0], 0);
+    //First step happens on the first APU cycle as the timer starts at 0
+    clock_cpu_cycles(&mut apu, 2);
+    assert_eq!(apu.channel_outputs()[0], 15);
+    clock_cpu_cycles(&mut apu, 18);
+    assert_eq!(apu.channel_outputs()[0], 15);
+    clock_cpu_cycles(&mut apu, 18);
+    assert_eq!(apu.channel_outputs()[0], 0);
+}
+
+#[test]
+fn test_sweep_mutes_low_periods() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0xDF);
+    apu.cpu_write(0x4002, 0x07); // period below 8
+    apu.cpu_write(0x4003, 0x08);
+
+    assert_eq!(apu.channel_outputs()[0], 0);
+}
+
+#[test]
+fn test_sweep_mutes_overflowing_target() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4000, 0xDF);
+    apu.cpu_write(0x4001, 0x01); // disabled, shift 1, still mutes
+    apu.cpu_write(0x4002, 0x00);
+    apu.cpu_write(0x4003, 0x0E); // period $600, target $900
+
+    assert_eq!(apu.channel_outputs()[0], 0);
+}
+
+#[test]
+fn test_triangle_needs_linear_counter() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x04);
+    apu.cpu_write(0x4008, 0x00); // linear reload 0
+    apu.cpu_write(0x400A, 0x02);
+    apu.cpu_write(0x400B, 0x08);
+    apu.quarter_frame();
+
+    clock_cpu_cycles(&mut apu, 30);
+    assert_eq!(apu.channel_outputs()[2], 15);
+
+    apu.cpu_write(0x4008, 0x7F);
+    apu.cpu_write(0x400B, 0x08);
+    apu.quarter_frame();
+
+    //Period 2 = 3 CPU cycles per step
+    clock_cpu_cycles(&mut apu, 1);
+    assert_eq!(apu.channel_outputs()[2], 14);
+    clock_cpu_cycles(&mut apu, 3);
+    assert_eq!(apu.channel_outputs()[2], 13);
+}
+
+#[test]
+fn test_frame_irq_in_status() {
+    let mut apu = Apu::new();
+
+    //Power on is 4 step mode with IRQs enabled
+    clock_cpu_cycles(&mut apu, 29833);
+    assert!(apu.irq());
+    assert_eq!(apu.cpu_read(0x4015), 0x40);
+    //Read acknowledged it
+    assert!(!apu.irq());
+    assert_eq!(apu.cpu_read(0x4015), 0x00);
+}
+
+#[test]
+fn test_frame_counter_clocks_length() {
+    let mut apu = Apu::new();
+    clock_cpu_cycles(&mut apu, 3);
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4003, 0x18); // length 2
+
+    //Half frames at 14913 and 29829 after the sequencer starts
+    clock_cpu_cycles(&mut apu, 14913);
+    assert_eq!(apu.cpu_read(0x4015) & 0x01, 0x01);
+    clock_cpu_cycles(&mut apu, 29829 - 14913);
+    assert_eq!(apu.cpu_read(0x4015) & 0x01, 0x00);
+}
+
+#[test]
+fn test_five_step_write_clocks_length_immediately() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x01);
+    apu.cpu_write(0x4003, 0x18); // length 2
+    apu.cpu_write(0x4017, 0x80);
+
+    clock_cpu_cycles(&mut apu, 4);
+    apu.cpu_write(0x4017, 0x80);
+    clock_cpu_cycles(&mut apu, 4);
+    assert_eq!(apu.cpu_read(0x4015) & 0x01, 0x00);
+}
+
+#[test]
+fn test_reset_clears_frame_irq_and_channels() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4015, 0x0F);
+    apu.cpu_write(0x4003, 0x08);
+    clock_cpu_cycles(&mut apu, 29833);
+    assert!(apu.irq());
+
+    apu.reset();
+    assert!(!apu.irq());
+    assert_eq!(apu.cpu_read(0x4015), 0x00);
+}
+
+#[test]
+fn test_dmc_status_and_fetch() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4012, 0x10);
+    apu.cpu_write(0x4013, 0x00);
+    apu.cpu_write(0x4015, 0x10);
+    assert_eq!(apu.cpu_read(0x4015), 0x10);
+    assert_eq!(apu.dmc_dma_request(), Some(0xC400));
+
+    apu.dmc_load_sample(0x00);
+    assert_eq!(apu.dmc_dma_request(), None);
+    assert_eq!(apu.cpu_read(0x4015), 0x00);
+}
+
+#[test]
+fn test_dmc_direct_load() {
+    let mut apu = Apu::new();
+
+    apu.cpu_write(0x4011, 0xC5);
+    assert_eq!(apu.channel_outputs()[4], 0x45);
+}
+
+
+#[test]
+fn test_sample_rate_and_silence() {
+    let mut apu = Apu::new();
+    apu.set_sample_rate(48000);
+    assert_eq!(apu.sample_rate(), 48000);
+
+    clock_cpu_cycles(&mut apu, 29830);
+    let samples = apu.take_samples_i16();
+    assert!((799..=800).contains(&samples.len()));
+
+    //The triangle's idle DC level is a pop at power on that the high-pass removes
+    clock_cpu_cycles(&mut apu, 29830);
+    let samples = apu.take_samples_i16();
+    assert!(samples.iter().all(|sample| sample.abs() <= 1));
+}
+
+//Pulse 1 and noise both playing at full volume
+fn two_channel_apu() -> Apu {
+    let mut apu = Apu::new();
+    apu.cpu_write(0x4015, 0x09);
+    apu.cpu_write(0x4000, 0xBF);
+    apu.cpu_write(0x4002, 0xFD);
+    apu.cpu_write(0x4003, 0x00);
+    apu.cpu_write(0x400C, 0x3F);
+    apu.cpu_write(0x400E, 0x04);
+    apu.cpu_write(0x400F, 0x00);
+    apu
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, sample| peak.max(sample.abs()))
+}
+
+#[test]
+fn test_mute_and_solo() {
+    let mut apu = two_channel_apu();
+    clock_cpu_cycles(&mut apu, 29830);
+    assert!(peak(&apu.take_samples()) > 0.05);
+
+    apu.set_muted(Channel::Pulse1, true);
+    apu.set_muted(Channel::Noise, true);
+    assert!(apu.is_muted(Channel::Noise));
+    clock_cpu_cycles(&mut apu, 29830 * 2);
+    apu.take_samples();
+    clock_cpu_cycles(&mut apu, 29830);
+    assert!(peak(&apu.take_samples()) < 0.001);
+
+    //Solo the idle triangle, still silent
+    apu.solo(Channel::Triangle);
+    assert!(apu.is_muted(Channel::Pulse1));
+    assert!(!apu.is_muted(Channel::Triangle));
+    clock_cpu_cycles(&mut apu, 29830);
+    assert!(peak(&apu.take_samples()) < 0.001);
+
+    apu.unmute_all();
+    clock_cpu_cycles(&mut apu, 29830);
+    assert!(peak(&apu.take_samples()) > 0.05);
+}
+
+#[test]
+fn test_channel_capture_matches_solo() {
+    let mut captured = two_channel_apu();
+    captured.set_capture(Channel::Pulse1, true);
+    let mut soloed = two_channel_apu();
+    soloed.solo(Channel::Pulse1);
+
+    clock_cpu_cycles(&mut captured, 29830);
+    clock_cpu_cycles(&mut soloed, 29830);
+
+    let isolated = captured.take_channel_samples(Channel::Pulse1);
+    assert!(peak(&isolated) > 0.05);
+    assert_eq!(isolated, soloed.take_samples());
+    //Capturing doesn't touch the full mix
+    assert_ne!(captured.take_samples(), isolated);
+    assert!(captured.take_channel_samples(Channel::Noise).is_empty());
+}
+
+#[test]
+fn test_expansion_channel() {
+    let mut apu = Apu::new();
+    apu.set_capture(Channel::Expansion, true);
+
+    //Square wave from the cartridge
+    for n in 0..29830 {
+        apu.set_expansion_level(if (n / 2000) % 2 == 0 { 0.2 } else { 0.0 });
+        apu.clock();
+    }
+    assert!(peak(&apu.take_samples()) > 0.05);
+    assert!(peak(&apu.take_channel_samples(Channel::Expansion)) > 0.05);
+
+    apu.set_muted(Channel::Expansion, true);
+    apu.set_expansion_level(0.0);
+    clock_cpu_cycles(&mut apu, 29830 * 3);
+    apu.take_samples();
+    for n in 0..29830 {
+        apu.set_expansion_level(if (n / 2000) % 2 == 0 { 0.2 } else { 0.0 });
+        apu.clock();
+    }
+    assert!(peak(&apu.take_samples()) < 0.001);
+}
@@ -0,0 +1,114 @@
+use crate::apu::length_counter::LengthCounter;
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+pub struct Triangle {
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+    pub length_counter: LengthCounter,
+
+    //Linear counter. The control flag doubles as the length counter halt
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+impl Triangle {
+    pub fn new() -> Self {
+        Triangle {
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            length_counter: LengthCounter::new(),
+            control: false,
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+        }
+    }
+    //Register offset 0-3 within $4008-$400B. $4009 is unused
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0x80 != 0;
+                self.length_counter.set_halt(self.control);
+                self.linear_reload_value = value & 0x7F;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length_counter.load(value >> 3);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+    //Unlike the other channels the triangle timer runs every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            //Sequencer only moves while both counters are non zero
+            if self.linear_counter > 0 && self.length_counter.active() {
+                self.sequence_step = (self.sequence_step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+    //Clocked on quarter frames
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+    pub fn clock_length(&mut self) {
+        self.length_counter.clock();
+    }
+    //Silencing the triangle freezes the sequencer rather than dropping to 0, so the
+    //output holds its last step
+    pub fn output(&self) -> u8 {
+        TRIANGLE_SEQUENCE[self.sequence_step as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_counter_reload_flag() {
+        let mut triangle = Triangle::new();
+
+        triangle.write(0, 0x02); // control clear, reload 2
+        triangle.write(3, 0x00);
+
+        triangle.clock_linear_counter(); // reload to 2, flag cleared
+        assert_eq!(triangle.linear_counter, 2);
+        triangle.clock_linear_counter();
+        assert_eq!(triangle.linear_counter, 1);
+        triangle.clock_linear_counter();
+        triangle.clock_linear_counter();
+        assert_eq!(triangle.linear_counter, 0);
+    }
+
+    #[test]
+    fn linear_counter_control_keeps_reloading() {
+        let mut triangle = Triangle::new();
+
+        triangle.write(0, 0x85); // control set, reload 5
+        triangle.write(3, 0x00);
+
+        for _ in 0..10 {
+            triangle.clock_linear_counter();
+        }
+        assert_eq!(triangle.linear_counter, 5);
+    }
+}
@@ -0,0 +1,73 @@
+//Standard controller. Buttons are shifted out A, B, Select, Start, Up, Down, Left, Right
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+pub struct Controller {
+    buttons: u8,
+    shift_register: u8,
+    strobe: bool,
+}
+impl Controller {
+    pub fn new() -> Self {
+        Controller {
+            buttons: 0x00,
+            shift_register: 0x00,
+            strobe: false,
+        }
+    }
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift_register = buttons;
+        }
+    }
+    //$4016 bit 0. While the strobe is high the shift register keeps reloading
+    pub fn write_strobe(&mut self, value: u8) {
+        self.strobe = value & 0x01 != 0;
+        if self.strobe {
+            self.shift_register = self.buttons;
+        }
+    }
+    //Serial data in bit 0. Once all 8 buttons are out the register reads back 1s
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+        let bit = self.shift_register & 0x01;
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+        bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_buttons_in_order() {
+        let mut controller = Controller::new();
+        controller.set_buttons(BUTTON_A | BUTTON_START | BUTTON_RIGHT);
+        controller.write_strobe(1);
+        controller.write_strobe(0);
+
+        let bits: Vec<u8> = (0..8).map(|_| controller.read()).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(controller.read(), 1);
+    }
+
+    #[test]
+    fn strobe_high_returns_a() {
+        let mut controller = Controller::new();
+        controller.set_buttons(BUTTON_A);
+        controller.write_strobe(1);
+
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+    }
+}
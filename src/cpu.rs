@@ -5,7 +5,7 @@ use std::ops::Add;
 
 use crate::{
     cpu::opcode_lookup::{AddressMode, Instruction, Operation},
-    cpu_bus::CpuBus,
+    cpu_bus::{CpuBus, NesBus},
 };
 
 enum WrapMode {
@@ -111,6 +111,7 @@ impl<B: CpuBus> CPU<B> {
     }
     //Reset function. Resets registers and gets PC from cartridge PRG ROM
     pub fn reset(&mut self) {
+        self.cpu_bus.reset();
         self.A = 0x00;
         self.X = 0x00;
         self.Y = 0x00;
@@ -121,6 +122,87 @@ impl<B: CpuBus> CPU<B> {
         self.halted = false;
     }
 
+    //NMI sequence. Pushes PC and status (Break clear) and jumps through the $FFFA vector
+    pub fn nmi(&mut self) {
+        self.push_to_stack((self.PC >> 8) as u8);
+        self.push_to_stack(self.PC as u8);
+        self.push_to_stack((self.P | Self::UNUSED) & !Self::BREAK);
+        self.set_flag(Self::INTERRUPT, true);
+        self.PC = self.read_u16(0xFFFA, WrapMode::Normal);
+        self.cycles_remaining = 7;
+    }
+
+    //IRQ sequence. Same as NMI but through the $FFFE vector
+    pub fn irq(&mut self) {
+        self.push_to_stack((self.PC >> 8) as u8);
+        self.push_to_stack(self.PC as u8);
+        self.push_to_stack((self.P | Self::UNUSED) & !Self::BREAK);
+        self.set_flag(Self::INTERRUPT, true);
+        self.PC = self.read_u16(0xFFFE, WrapMode::Normal);
+        self.cycles_remaining = 7;
+    }
+
+    //Advance the CPU by a single cycle. Interrupts are only checked between instructions and
+    //DMA stalls extend whatever the CPU is currently waiting on
+    pub fn clock(&mut self) {
+        self.cycles_remaining += self.cpu_bus.take_stall_cycles();
+        if self.cycles_remaining == 0 && !self.halted {
+            if self.cpu_bus.poll_nmi() {
+                self.nmi();
+            } else if self.cpu_bus.irq_line() && self.P & Self::INTERRUPT == 0 {
+                self.irq();
+            } else {
+                self.step();
+            }
+        }
+
+        self.cycles_remaining = self.cycles_remaining.saturating_sub(1);
+        self.cpu_bus.tick();
+    }
+
+    //Set once an unknown or jammed opcode is hit, cleared by reset
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+    pub fn program_counter(&self) -> u16 {
+        self.PC
+    }
+    pub fn bus(&self) -> &B {
+        &self.cpu_bus
+    }
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.cpu_bus
+    }
+
+    //Fetch, decode and execute one instruction. Unknown opcodes halt the CPU
+    pub fn step(&mut self) {
+        let opcode = self.fetch_pc_byte();
+        let instruction = match opcode_lookup::OPCODE_LOOKUP.get(&opcode) {
+            Some(instruction) => instruction,
+            None => {
+                self.halted = true;
+                return;
+            }
+        };
+
+        let operand = match instruction.addressing.operand_bytes() {
+            0 => 0,
+            1 => self.fetch_pc_byte() as u16,
+            _ => {
+                let lower_byte = self.fetch_pc_byte() as u16;
+                let upper_byte = self.fetch_pc_byte() as u16;
+                (upper_byte << 8) | lower_byte
+            }
+        };
+
+        //Handlers that care about extra cycles overwrite this
+        self.cycles_remaining = instruction.cycles;
+
+        if opcode_lookup::handler_dispatch(self, instruction, operand).is_err() {
+            self.halted = true;
+        }
+    }
+
     //Begin Opcode functionality.
     pub fn fetch_pc_byte(&mut self) -> u8 {
         let mem_byte = self.bus_read(self.PC);
@@ -787,4 +869,13 @@ impl<B: CpuBus> CPU<B> {
         Ok(())
     }
 }
-
+impl CPU<NesBus> {
+    //Run until the PPU finishes the current frame. Audio and video for the frame can then
+    //be collected from the bus
+    pub fn run_frame(&mut self) {
+        let frame = self.cpu_bus.frame();
+        while self.cpu_bus.frame() == frame && !self.halted {
+            self.clock();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{cpu::{CPU,CpuBus}, cpu_bus::NesBus};
+use crate::cpu::{CPU,CpuBus};
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
@@ -72,13 +72,27 @@ pub enum AddressMode {
     Indirect,
 }
 
+impl AddressMode {
+    //Number of operand bytes following the opcode
+    pub fn operand_bytes(&self) -> u16 {
+        match self {
+            AddressMode::Implicit | AddressMode::Accumulator => 0,
+            AddressMode::Absolute
+            | AddressMode::AbsoluteIndexedX
+            | AddressMode::AbsoluteIndexedY
+            | AddressMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
 pub struct Instruction {
     pub operation: Operation,
     pub addressing: AddressMode,
     pub cycles: usize,
 }
 
-pub fn handler_dispatch(cpu: &mut CPU<NesBus>, instruction: &mut Instruction, operand: u16) -> Result<(), &'static str> {
+pub fn handler_dispatch<B: CpuBus>(cpu: &mut CPU<B>, instruction: &Instruction, operand: u16) -> Result<(), &'static str> {
     //Dispatch to correct handler
     //match statement (or something similar) by op
     match instruction.operation {
@@ -104,7 +118,7 @@ pub fn handler_dispatch(cpu: &mut CPU<NesBus>, instruction: &mut Instruction, op
             }
         }
         Operation::CLC | Operation::CLD | Operation::CLI | Operation::CLV | Operation::SEC | Operation::SED | Operation::SEI  => {
-            let result = CPU::bitwise_logic(cpu, instruction, operand);
+            let result = CPU::set_flag_operation(cpu, instruction);
             match result {
                 Ok(v) => Ok(v),
                 Err(e) => Err(e),
@@ -510,6 +510,33 @@ fn test_clear_flag_operations() {
     assert!(!cpu.get_flag(CPU::<MockBus>::CARRY))
 }
 
+#[test]
+fn test_step_flag_instructions() {
+    //SEC, CLC, SEI, CLI, SED, CLD, then SEI again, fetched and dispatched through step()
+    let mut bus = MockBus::new();
+    bus.mem[0x0200..0x0207].copy_from_slice(&[0x38, 0x18, 0x78, 0x58, 0xF8, 0xD8, 0x78]);
+    let mut cpu = CPU::new(bus);
+    cpu.PC = 0x0200;
+    clear_all_flags(&mut cpu);
+
+    let expected = [
+        CPU::<MockBus>::CARRY,
+        0,
+        CPU::<MockBus>::INTERRUPT,
+        0,
+        CPU::<MockBus>::DECIMAL,
+        0,
+        CPU::<MockBus>::INTERRUPT,
+    ];
+    for flags in expected {
+        cpu.step();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.P, 0x20 | flags);
+        assert_eq!(cpu.cycles_remaining, 2);
+    }
+    assert_eq!(cpu.PC, 0x0207);
+}
+
 #[test]
 fn test_bne_not_taken() {
     let bus = MockBus::new();
@@ -1372,3 +1399,143 @@ fn test_nop_invalid_operation() {
     let result = cpu.nop_operation(&instruction);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_nmi_pushes_state_and_jumps_to_vector() {
+    let mut bus = MockBus::new();
+    bus.mem[0xFFFA] = 0x00;
+    bus.mem[0xFFFB] = 0x90;
+    let mut cpu = CPU::new(bus);
+
+    cpu.PC = 0x8123;
+    cpu.SP = 0xFD;
+    clear_all_flags(&mut cpu);
+    cpu.set_flag(CPU::<MockBus>::CARRY, true);
+
+    cpu.nmi();
+
+    assert_eq!(cpu.PC, 0x9000);
+    assert_eq!(cpu.SP, 0xFA);
+    assert_eq!(cpu.cpu_bus.mem[0x01FD], 0x81);
+    assert_eq!(cpu.cpu_bus.mem[0x01FC], 0x23);
+    //Break clear, unused set
+    assert_eq!(cpu.cpu_bus.mem[0x01FB], 0x21);
+    assert!(cpu.get_flag(CPU::<MockBus>::INTERRUPT));
+    assert_eq!(cpu.cycles_remaining, 7);
+}
+
+#[test]
+fn test_clock_executes_instruction_then_waits() {
+    let mut bus = MockBus::new();
+    bus.mem[0x8000] = 0xA9; // LDA #$42
+    bus.mem[0x8001] = 0x42;
+    bus.mem[0x8002] = 0xEA; // NOP
+    let mut cpu = CPU::new(bus);
+    cpu.PC = 0x8000;
+
+    cpu.clock();
+    assert_eq!(cpu.A, 0x42);
+    assert_eq!(cpu.PC, 0x8002);
+
+    //Remaining cycle of LDA immediate
+    cpu.clock();
+    assert_eq!(cpu.PC, 0x8002);
+
+    cpu.clock();
+    assert_eq!(cpu.PC, 0x8003);
+}
+
+#[test]
+fn test_clock_halts_on_unknown_opcode() {
+    let mut bus = MockBus::new();
+    bus.mem[0x8000] = 0x02; // KIL
+    let mut cpu = CPU::new(bus);
+    cpu.PC = 0x8000;
+
+    cpu.clock();
+    assert!(cpu.halted);
+}
+
+struct NmiBus {
+    mem: [u8; 65536],
+    nmi: bool,
+}
+impl CpuBus for NmiBus {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.mem[address as usize]
+    }
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        self.mem[address as usize] = value;
+    }
+    fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+}
+
+#[test]
+fn test_clock_services_nmi_between_instructions() {
+    let mut bus = NmiBus {
+        mem: [0xEA; 65536],
+        nmi: false,
+    };
+    bus.mem[0xFFFA] = 0x00;
+    bus.mem[0xFFFB] = 0x90;
+    let mut cpu = CPU::new(bus);
+    cpu.PC = 0x8000;
+
+    //NMI raised mid instruction is taken once the instruction finishes
+    cpu.clock();
+    cpu.cpu_bus.nmi = true;
+    assert_eq!(cpu.PC, 0x8001);
+
+    while cpu.cycles_remaining > 0 {
+        cpu.clock();
+    }
+    cpu.clock();
+    assert_eq!(cpu.PC, 0x9000);
+}
+
+struct IrqBus {
+    mem: [u8; 65536],
+    irq: bool,
+}
+impl CpuBus for IrqBus {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        self.mem[address as usize]
+    }
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        self.mem[address as usize] = value;
+    }
+    fn irq_line(&self) -> bool {
+        self.irq
+    }
+}
+
+#[test]
+fn test_clock_services_irq_when_enabled() {
+    let mut bus = IrqBus {
+        mem: [0xEA; 65536],
+        irq: true,
+    };
+    bus.mem[0xFFFE] = 0x00;
+    bus.mem[0xFFFF] = 0xA0;
+    let mut cpu = CPU::new(bus);
+    cpu.PC = 0x8000;
+    cpu.SP = 0xFD;
+    cpu.P = 0x24;
+
+    //I flag set, line is ignored
+    cpu.clock();
+    assert_eq!(cpu.PC, 0x8001);
+
+    cpu.P = 0x20;
+    while cpu.cycles_remaining > 0 {
+        cpu.clock();
+    }
+    cpu.clock();
+    assert_eq!(cpu.PC, 0xA000);
+    assert_eq!(cpu.SP, 0xFA);
+    assert_eq!(cpu.cpu_bus.mem[0x01FB], 0x20);
+    assert_eq!(cpu.P & CPU::<IrqBus>::INTERRUPT, CPU::<IrqBus>::INTERRUPT);
+}
+
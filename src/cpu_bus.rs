@@ -1,68 +1,311 @@
-use crate::mapper::Mapper;
+use crate::apu::Apu;
+use crate::controller::Controller;
+use crate::mapper::{self, Mapper};
+use crate::ppu::Ppu;
+use crate::ppu_bus::NesPpuBus;
+use crate::region::Region;
+use crate::rom_loader::{Cartridge, RomError};
 
 pub trait CpuBus {
     fn cpu_read(&mut self, addr: u16) -> u8;
     fn cpu_write(&mut self, addr: u16, value: u8);
+    //Called once per CPU cycle so the rest of the system keeps pace with the CPU
+    fn tick(&mut self) {}
+    //Returns true if an NMI edge has been latched since the last poll
+    fn poll_nmi(&mut self) -> bool {
+        false
+    }
+    //Level of the shared IRQ line. Stays asserted until the source is acknowledged
+    fn irq_line(&self) -> bool {
+        false
+    }
+    //Called when the console's reset button is pressed
+    fn reset(&mut self) {}
+    //Cycles the CPU loses to DMA since the last call
+    fn take_stall_cycles(&mut self) -> usize {
+        0
+    }
 }
 
 pub struct NesBus {
-    mapper: super::mapper::Mapper,
+    mapper: Box<dyn Mapper>,
     prg_rom: Vec<u8>,
     prg_ram: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_ram: Vec<u8>,
     ram: [u8; 0x800],
+    ppu: Ppu,
+    //CIRAM, plus the 2 KiB four screen cartridges add on top
+    vram: [u8; 0x1000],
+    apu: Apu,
+    region: Region,
+    //Master clocks the PPU still has to catch up on. Lets PAL run 3.2 dots per CPU cycle
+    ppu_master_clocks: u32,
+    controllers: [Controller; 2],
+
+    //DMA bookkeeping. Stalls are handed to the CPU, which adds them to its current wait
+    cpu_cycles: u64,
+    stall_cycles: usize,
+    oam_dma_cycles: usize,
+    //Controller port read by the CPU this cycle, for the DMC double clock glitch
+    controller_read: Option<usize>,
 }
 impl CpuBus for NesBus {
     fn cpu_read(&mut self, address: u16) -> u8 {
-        //Routes reads to appropriate memory component (Mapper, CPU mem)
-        //Currently, only routes to mapper, all other addresses are ignored
+        //Routes reads to appropriate memory component (Mapper, CPU mem, PPU/APU registers,
+        //controllers). Upper bits of the controller ports come from open bus, usually $40
 
         match address {
             0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize],
-            0x6000..=0xFFFF => self.mapper.cpu_read(&self.prg_rom, &self.prg_ram, address),
+            0x2000..=0x3FFF => {
+                let mut ppu_bus = NesPpuBus {
+                    mapper: self.mapper.as_mut(),
+                    chr_rom: &self.chr_rom,
+                    chr_ram: &mut self.chr_ram,
+                    vram: &mut self.vram,
+                };
+                self.ppu.cpu_read(&mut ppu_bus, address)
+            }
+            0x4015 => self.apu.cpu_read(address),
+            0x4016 | 0x4017 => {
+                let port = (address - 0x4016) as usize;
+                self.controller_read = Some(port);
+                self.controllers[port].read() | 0x40
+            }
+            0x4020..=0xFFFF => self.mapper.cpu_read(&self.prg_rom, &self.prg_ram, address),
             _ => 0,
         }
     }
 
     fn cpu_write(&mut self, address: u16, value: u8) {
-        //Routes writes to appropriate memory component (Mapper, CPU mem)
-        //Currently only routes to mapper, all other addresses are ignored
+        //Routes writes to appropriate memory component (Mapper, CPU mem, PPU/APU registers,
+        //OAM DMA, controller strobe)
 
         match address {
-            0x6000..=0xFFFF => self.mapper.cpu_write(&mut self.prg_ram, address, value),
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize] = value,
+            0x2000..=0x3FFF => {
+                self.mapper.ppu_register_write(address, value);
+                let mut ppu_bus = NesPpuBus {
+                    mapper: self.mapper.as_mut(),
+                    chr_rom: &self.chr_rom,
+                    chr_ram: &mut self.chr_ram,
+                    vram: &mut self.vram,
+                };
+                self.ppu.cpu_write(&mut ppu_bus, address, value)
+            }
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.cpu_write(address, value),
+            0x4014 => self.oam_dma(value),
+            0x4016 => {
+                for controller in self.controllers.iter_mut() {
+                    controller.write_strobe(value);
+                }
+            }
+            0x4020..=0xFFFF => self.mapper.cpu_write(&self.prg_rom, &mut self.prg_ram, address, value),
             _ => {}
         }
     }
+
+    fn tick(&mut self) {
+        let controller_read = self.controller_read.take();
+
+        //Both chips divide the same master clock. NTSC and Dendy work out to exactly 3 dots
+        //per CPU cycle, PAL to 3.2 so every fifth cycle gets an extra dot
+        self.ppu_master_clocks += self.region.cpu_divider();
+        let ppu_divider = self.region.ppu_divider();
+
+        let mut ppu_bus = NesPpuBus {
+            mapper: self.mapper.as_mut(),
+            chr_rom: &self.chr_rom,
+            chr_ram: &mut self.chr_ram,
+            vram: &mut self.vram,
+        };
+        while self.ppu_master_clocks >= ppu_divider {
+            self.ppu.clock(&mut ppu_bus);
+            self.ppu_master_clocks -= ppu_divider;
+        }
+
+        self.mapper.cpu_clock();
+        self.apu.set_expansion_level(self.mapper.audio_output());
+        self.apu.clock();
+
+        //DMC sample fetch. Halting the CPU normally costs 4 cycles but only 2 when it is
+        //already halted for OAM DMA. Write cycles that shorten the halt aren't visible at
+        //instruction granularity so they are not modelled
+        if let Some(address) = self.apu.dmc_dma_request() {
+            let value = self.cpu_read(address);
+            self.apu.dmc_load_sample(value);
+            self.stall_cycles += if self.oam_dma_cycles > 0 { 2 } else { 4 };
+
+            //On the 2A03 the halted CPU repeats its read, so a controller being read this
+            //cycle gets clocked twice and drops a bit. Fixed in the PAL 2A07
+            if let (Some(port), Region::Ntsc) = (controller_read, self.region) {
+                self.controllers[port].read();
+            }
+        }
+
+        self.oam_dma_cycles = self.oam_dma_cycles.saturating_sub(1);
+        self.cpu_cycles += 1;
+    }
+
+    fn poll_nmi(&mut self) -> bool {
+        self.ppu.poll_nmi()
+    }
+
+    fn irq_line(&self) -> bool {
+        self.apu.irq() || self.mapper.irq()
+    }
+
+    fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+    }
+
+    fn take_stall_cycles(&mut self) -> usize {
+        std::mem::take(&mut self.stall_cycles)
+    }
 }
 impl NesBus {
-    fn new(mapper: Mapper, prg_rom: Vec<u8>, prg_ram: Vec<u8>, ram: [u8; 0x800]) -> Self {
+    fn new(mapper: Box<dyn Mapper>, prg_rom: Vec<u8>, prg_ram: Vec<u8>, chr_rom: Vec<u8>, chr_ram: Vec<u8>, ram: [u8; 0x800]) -> Self {
         NesBus {
             mapper,
             prg_rom,
             prg_ram,
+            chr_rom,
+            chr_ram,
             ram,
+            ppu: Ppu::new(),
+            vram: [0x00; 0x1000],
+            apu: Apu::new(),
+            region: Region::Ntsc,
+            ppu_master_clocks: 0,
+            controllers: [Controller::new(), Controller::new()],
+            cpu_cycles: 0,
+            stall_cycles: 0,
+            oam_dma_cycles: 0,
+            controller_read: None,
+        }
+    }
+    //Power on with a loaded cartridge: its board, ROM, blank RAM sized from the header and
+    //the header's region
+    pub fn from_cartridge(cartridge: &Cartridge) -> Result<Self, RomError> {
+        let mapper = mapper::from_cartridge(cartridge)?;
+        let mut prg_ram = vec![0x00; cartridge.prg_ram_size() + cartridge.prg_nvram_size()];
+        //The trainer goes to $7000-$71FF, so a board carrying one gets the full 8K at $6000
+        if let Some(trainer) = cartridge.trainer() {
+            prg_ram.resize(prg_ram.len().max(0x2000), 0x00);
+            prg_ram[0x1000..0x1000 + trainer.len()].copy_from_slice(trainer);
+        }
+        let chr_ram = vec![0x00; cartridge.chr_ram_size() + cartridge.chr_nvram_size()];
+        let mut bus = NesBus::new(
+            mapper,
+            cartridge.prg_rom().to_vec(),
+            prg_ram,
+            cartridge.chr_rom().to_vec(),
+            chr_ram,
+            [0x00; 0x800],
+        );
+        bus.set_region(cartridge.region());
+        Ok(bus)
+    }
+    //Region defaults to NTSC. Frontends set this from the cartridge header or a user override
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+        self.ppu_master_clocks = 0;
+    }
+    pub fn region(&self) -> Region {
+        self.region
+    }
+    pub fn frame(&self) -> u64 {
+        self.ppu.frame()
+    }
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+    pub fn sample_rate(&self) -> u32 {
+        self.apu.sample_rate()
+    }
+    //Mixer controls: muting, solo and per-channel capture
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+    //Audio generated since the last call, see Apu::take_samples
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+    pub fn take_samples_i16(&mut self) -> Vec<i16> {
+        self.apu.take_samples_i16()
+    }
+    //Button bits for controller port 0 or 1, see the BUTTON_ constants
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        self.controllers[port].set_buttons(buttons);
+    }
+
+    //$4014 copies a 256 byte page into OAM through $2004. The CPU is halted for 513
+    //cycles, plus one to align when the write lands on an odd cycle
+    fn oam_dma(&mut self, page: u8) {
+        for offset in 0x00..=0xFF {
+            let value = self.cpu_read(((page as u16) << 8) | offset);
+            let mut ppu_bus = NesPpuBus {
+                mapper: self.mapper.as_mut(),
+                chr_rom: &self.chr_rom,
+                chr_ram: &mut self.chr_ram,
+                vram: &mut self.vram,
+            };
+            self.ppu.cpu_write(&mut ppu_bus, 0x2004, value);
         }
+
+        let stall = 513 + (self.cpu_cycles % 2) as usize;
+        self.stall_cycles += stall;
+        self.oam_dma_cycles = stall;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mapper::Mapper;
+    use crate::apu::Channel;
+    use crate::cpu::CPU;
+    use crate::controller::{BUTTON_A, BUTTON_B, BUTTON_START};
+    use crate::mapper::new_mapper;
+    use crate::rom_loader::Nametable;
 
-    fn new_test_bus(prg_rom_size: usize, mapper_number: usize, ram: [u8; 0x800]) -> NesBus {
+    fn new_test_bus(prg_rom_size: usize, mapper_number: u16, ram: [u8; 0x800]) -> NesBus {
         let prg_rom = vec![0xEA; prg_rom_size];
         let prg_ram = vec![0x00; 8 * 1024];
+        let chr_ram = vec![0x00; 8 * 1024];
 
-        let mapper = Mapper::new(mapper_number);
+        let mapper = new_mapper(mapper_number, 0, Nametable::Horizontal).unwrap();
 
         NesBus {
             mapper,
             prg_rom,
             prg_ram,
+            chr_rom: vec![],
+            chr_ram,
             ram,
+            ppu: Ppu::new(),
+            vram: [0x00; 0x1000],
+            apu: Apu::new(),
+            region: Region::Ntsc,
+            ppu_master_clocks: 0,
+            controllers: [Controller::new(), Controller::new()],
+            cpu_cycles: 0,
+            stall_cycles: 0,
+            oam_dma_cycles: 0,
+            controller_read: None,
         }
     }
 
+    //Queue a one byte DMC sample with the IRQ enabled, so the next tick fetches it
+    fn start_dmc_sample(cpu_bus: &mut NesBus) {
+        cpu_bus.cpu_write(0x4010, 0x8F);
+        cpu_bus.cpu_write(0x4012, 0x00);
+        cpu_bus.cpu_write(0x4013, 0x00);
+        cpu_bus.cpu_write(0x4015, 0x10);
+    }
+
     #[test]
     fn bus_rom_read() {
         //create CPU Bus with 16KiB of ROM
@@ -96,4 +339,417 @@ mod tests {
         //Check all bytes of RAM are still 0 after bad write
         assert!(cpu_bus.prg_ram.iter().all(|&x| x == 0))
     }
+
+    #[test]
+    fn bus_nmi_from_ppu() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+
+        //Enable NMI through PPUCTRL and run one frame worth of CPU cycles
+        cpu_bus.cpu_write(0x2000, 0x80);
+        let mut nmi_count = 0;
+        for _ in 0..(341 * 262 / 3) {
+            cpu_bus.tick();
+            if cpu_bus.poll_nmi() {
+                nmi_count += 1;
+            }
+        }
+
+        assert_eq!(nmi_count, 1);
+    }
+
+    #[test]
+    fn cpu_reset_resets_ppu() {
+        let mut cpu = CPU::new(new_test_bus(16 * 1024, 0, [0x00; 0x800]));
+
+        //NMI enabled and the first half of a $2006 address written
+        cpu.bus_mut().cpu_write(0x2000, 0x80);
+        cpu.bus_mut().cpu_write(0x2006, 0x21);
+        cpu.reset();
+
+        //PPUCTRL is cleared, so no NMI for the next frame
+        let mut nmi_count = 0;
+        for _ in 0..(341 * 262 / 3) {
+            cpu.bus_mut().tick();
+            if cpu.bus_mut().poll_nmi() {
+                nmi_count += 1;
+            }
+        }
+        assert_eq!(nmi_count, 0);
+
+        //The write toggle is cleared, so the next $2006 write is the high byte again
+        cpu.bus_mut().cpu_write(0x2006, 0x20);
+        cpu.bus_mut().cpu_write(0x2006, 0x10);
+        cpu.bus_mut().cpu_write(0x2007, 0x67);
+        assert_eq!(cpu.bus().vram[0x0010], 0x67);
+    }
+
+    #[test]
+    fn bus_ppu_vram_round_trip() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+
+        cpu_bus.cpu_write(0x2006, 0x20);
+        cpu_bus.cpu_write(0x2006, 0x10);
+        cpu_bus.cpu_write(0x2007, 0x67);
+        assert_eq!(cpu_bus.vram[0x0010], 0x67);
+
+        //Registers mirror every 8 bytes up to $3FFF
+        cpu_bus.cpu_write(0x3FFE, 0x20);
+        cpu_bus.cpu_write(0x3FFE, 0x10);
+        cpu_bus.cpu_read(0x3FFF);
+        assert_eq!(cpu_bus.cpu_read(0x3FFF), 0x67);
+    }
+
+    #[test]
+    fn bus_ppu_chr_ram_write() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+
+        cpu_bus.cpu_write(0x2006, 0x01);
+        cpu_bus.cpu_write(0x2006, 0x23);
+        cpu_bus.cpu_write(0x2007, 0x67);
+
+        assert_eq!(cpu_bus.chr_ram[0x0123], 0x67);
+    }
+
+    #[test]
+    fn bus_pal_ppu_clock_ratio() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        cpu_bus.set_region(Region::Pal);
+
+        //5 CPU cycles are 80 master clocks, which is 16 PAL dots
+        for _ in 0..5 {
+            cpu_bus.tick();
+        }
+        assert_eq!(cpu_bus.ppu.cycle(), 16);
+    }
+
+    #[test]
+    fn bus_pal_nmi_once_per_312_line_frame() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        cpu_bus.set_region(Region::Pal);
+
+        cpu_bus.cpu_write(0x2000, 0x80);
+        let mut nmi_count = 0;
+        //Two PAL frames are 33247.5 CPU cycles each
+        for _ in 0..(2 * 341 * 312 * 5 / 16) {
+            cpu_bus.tick();
+            if cpu_bus.poll_nmi() {
+                nmi_count += 1;
+            }
+        }
+
+        assert_eq!(nmi_count, 2);
+    }
+
+    #[test]
+    fn bus_apu_status() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+
+        cpu_bus.cpu_write(0x4015, 0x02);
+        cpu_bus.cpu_write(0x4007, 0x08);
+
+        assert_eq!(cpu_bus.cpu_read(0x4015), 0x02);
+    }
+
+    #[test]
+    fn bus_frame_irq_line() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+
+        for _ in 0..29833 {
+            cpu_bus.tick();
+        }
+        assert!(cpu_bus.irq_line());
+
+        //Acknowledged by reading $4015
+        cpu_bus.cpu_read(0x4015);
+        assert!(!cpu_bus.irq_line());
+
+        //Inhibited through $4017
+        cpu_bus.cpu_write(0x4017, 0x40);
+        for _ in 0..29833 {
+            cpu_bus.tick();
+        }
+        assert!(!cpu_bus.irq_line());
+    }
+
+    #[test]
+    fn bus_oam_dma() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        cpu_bus.ram[0x0200] = 0x12;
+        cpu_bus.ram[0x0201] = 0x34;
+
+        cpu_bus.cpu_write(0x2003, 0x00);
+        cpu_bus.cpu_write(0x4014, 0x02);
+        assert_eq!(cpu_bus.take_stall_cycles(), 513);
+
+        cpu_bus.cpu_write(0x2003, 0x00);
+        assert_eq!(cpu_bus.cpu_read(0x2004), 0x12);
+        cpu_bus.cpu_write(0x2003, 0x01);
+        assert_eq!(cpu_bus.cpu_read(0x2004), 0x34);
+
+        //Odd cycle needs an extra alignment cycle
+        cpu_bus.tick();
+        cpu_bus.cpu_write(0x4014, 0x02);
+        assert_eq!(cpu_bus.take_stall_cycles(), 514);
+    }
+
+    #[test]
+    fn bus_dmc_dma_stalls_and_raises_irq() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        start_dmc_sample(&mut cpu_bus);
+        assert_eq!(cpu_bus.cpu_read(0x4015) & 0x10, 0x10);
+
+        cpu_bus.tick();
+        assert_eq!(cpu_bus.take_stall_cycles(), 4);
+        assert!(cpu_bus.irq_line());
+        assert_eq!(cpu_bus.cpu_read(0x4015) & 0x90, 0x80);
+
+        //Writing $4015 acknowledges the DMC IRQ
+        cpu_bus.cpu_write(0x4015, 0x00);
+        assert!(!cpu_bus.irq_line());
+    }
+
+    #[test]
+    fn bus_dmc_dma_during_oam_dma() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        cpu_bus.cpu_write(0x4014, 0x02);
+        start_dmc_sample(&mut cpu_bus);
+
+        cpu_bus.tick();
+        assert_eq!(cpu_bus.take_stall_cycles(), 513 + 2);
+    }
+
+    #[test]
+    fn bus_controller_read() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        cpu_bus.set_buttons(0, BUTTON_A | BUTTON_START);
+        cpu_bus.set_buttons(1, BUTTON_B);
+
+        cpu_bus.cpu_write(0x4016, 0x01);
+        cpu_bus.cpu_write(0x4016, 0x00);
+
+        let port_0: Vec<u8> = (0..4).map(|_| cpu_bus.cpu_read(0x4016)).collect();
+        assert_eq!(port_0, vec![0x41, 0x40, 0x40, 0x41]);
+        let port_1: Vec<u8> = (0..2).map(|_| cpu_bus.cpu_read(0x4017)).collect();
+        assert_eq!(port_1, vec![0x40, 0x41]);
+    }
+
+    #[test]
+    fn bus_dmc_double_clocks_controller() {
+        for (region, second_bit) in [(Region::Ntsc, 0x40), (Region::Pal, 0x41)] {
+            let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+            cpu_bus.set_region(region);
+            cpu_bus.set_buttons(0, BUTTON_A | BUTTON_B);
+            cpu_bus.cpu_write(0x4016, 0x01);
+            cpu_bus.cpu_write(0x4016, 0x00);
+
+            //DMC fetch lands on the same cycle as the read of A
+            start_dmc_sample(&mut cpu_bus);
+            assert_eq!(cpu_bus.cpu_read(0x4016), 0x41);
+            cpu_bus.tick();
+
+            //NTSC skipped B
+            assert_eq!(cpu_bus.cpu_read(0x4016), second_bit);
+        }
+    }
+
+    #[test]
+    fn bus_audio_samples_per_frame() {
+        let mut cpu_bus = new_test_bus(16 * 1024, 0, [0x00; 0x800]);
+        cpu_bus.set_sample_rate(48000);
+
+        //Pulse 1 at about 440Hz, full volume
+        cpu_bus.cpu_write(0x4015, 0x01);
+        cpu_bus.cpu_write(0x4000, 0xBF);
+        cpu_bus.cpu_write(0x4002, 0xFD);
+        cpu_bus.cpu_write(0x4003, 0x00);
+
+        let frame = cpu_bus.frame();
+        while cpu_bus.frame() == frame {
+            cpu_bus.tick();
+        }
+        let samples = cpu_bus.take_samples();
+        //48000 / 60.1 is about 799
+        assert!((795..=802).contains(&samples.len()));
+        assert!(samples.iter().any(|sample| *sample > 0.05));
+        assert!(samples.iter().any(|sample| *sample < -0.05));
+        assert!(cpu_bus.take_samples().is_empty());
+
+        cpu_bus.tick();
+        let pcm = cpu_bus.take_samples_i16();
+        assert!(pcm.len() <= 1);
+    }
+
+    #[test]
+    fn bus_wav_recording_is_deterministic() {
+        let record = || {
+            let mut cpu = crate::cpu::CPU::new(new_test_bus(16 * 1024, 0, [0x00; 0x800]));
+            cpu.reset();
+            cpu.bus_mut().cpu_write(0x4015, 0x01);
+            cpu.bus_mut().cpu_write(0x4000, 0xBF);
+            cpu.bus_mut().cpu_write(0x4002, 0xFD);
+            cpu.bus_mut().cpu_write(0x4003, 0x00);
+            crate::wav::record_frames(&mut cpu, 2, std::io::Cursor::new(vec![]))
+                .unwrap()
+                .into_inner()
+        };
+
+        let first = record();
+        assert!(first.len() > 44 + 2 * 2 * 700);
+        assert_eq!(first, record());
+    }
+
+    #[test]
+    fn bus_wav_channel_recording() {
+        let mut cpu = crate::cpu::CPU::new(new_test_bus(16 * 1024, 0, [0x00; 0x800]));
+        cpu.reset();
+        cpu.bus_mut().cpu_write(0x4015, 0x01);
+        cpu.bus_mut().cpu_write(0x4000, 0xBF);
+        cpu.bus_mut().cpu_write(0x4002, 0xFD);
+        cpu.bus_mut().cpu_write(0x4003, 0x00);
+
+        let writers = vec![
+            (Channel::Pulse1, std::io::Cursor::new(vec![])),
+            (Channel::Noise, std::io::Cursor::new(vec![])),
+        ];
+        let recorded = crate::wav::record_channels(&mut cpu, 2, writers).unwrap();
+        let pulse = recorded[0].get_ref();
+        let noise = recorded[1].get_ref();
+
+        assert_eq!(pulse.len(), noise.len());
+        assert!(pulse[44..].iter().any(|byte| *byte != 0));
+        assert!(noise[44..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn bus_mmc3_scanline_irq() {
+        let mut cpu_bus = new_test_bus(32 * 1024, 4, [0x00; 0x800]);
+
+        //IRQ after 3 scanlines: reload on line 0, then 1, 0 on lines 1 and 2
+        cpu_bus.cpu_write(0xC000, 0x02);
+        cpu_bus.cpu_write(0xC001, 0x00);
+        cpu_bus.cpu_write(0xE001, 0x00);
+        //Background from $0000, sprites from $1000, rendering on
+        cpu_bus.cpu_write(0x2000, 0x08);
+        cpu_bus.cpu_write(0x2001, 0x18);
+
+        while !cpu_bus.irq_line() {
+            cpu_bus.tick();
+        }
+        //Sprite fetches raise A12 at dot 261 of line 2, caught within one CPU cycle
+        let dot = cpu_bus.ppu.cycle();
+        assert!((2 * 341 + 261..2 * 341 + 261 + 3).contains(&dot));
+    }
+
+    #[test]
+    fn bus_from_cartridge() {
+        //NES 2.0, NROM-128, no PRG RAM, 8K CHR RAM, PAL
+        let mut rom = vec![0x00; 16 + 16 * 1024];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 0x01;
+        rom[7] = 0x08;
+        rom[11] = 0x07;
+        rom[12] = 0x01;
+        rom[16 + 0x3FFC] = 0x34;
+        let cartridge = Cartridge::from_bytes(&rom).unwrap();
+
+        let mut cpu_bus = NesBus::from_cartridge(&cartridge).unwrap();
+        assert_eq!(cpu_bus.region(), Region::Pal);
+        assert_eq!(cpu_bus.cpu_read(0xFFFC), 0x34);
+        assert_eq!(cpu_bus.cpu_read(0xBFFC), 0x34);
+        assert!(cpu_bus.prg_ram.is_empty());
+        assert_eq!(cpu_bus.chr_ram.len(), 8 * 1024);
+
+        //A trainer lands at $7000 even when the header asks for no PRG RAM
+        let mut trainer_rom = rom[..16].to_vec();
+        trainer_rom[6] = 0x04;
+        trainer_rom.extend((0..512).map(|i| i as u8));
+        trainer_rom.extend_from_slice(&rom[16..]);
+        let cartridge = Cartridge::from_bytes(&trainer_rom).unwrap();
+        let mut cpu_bus = NesBus::from_cartridge(&cartridge).unwrap();
+        assert_eq!(cpu_bus.prg_ram.len(), 8 * 1024);
+        assert_eq!(cpu_bus.cpu_read(0x6FFF), 0x00);
+        assert_eq!(cpu_bus.cpu_read(0x7000), 0x00);
+        assert_eq!(cpu_bus.cpu_read(0x7001), 0x01);
+        assert_eq!(cpu_bus.cpu_read(0x71FF), 0xFF);
+        assert_eq!(cpu_bus.cpu_read(0x7200), 0x00);
+        assert_eq!(cpu_bus.cpu_read(0xFFFC), 0x34);
+
+        //Unsupported boards come back as a ROM error
+        rom[6] = 0xF0;
+        rom[7] = 0xF8;
+        let cartridge = Cartridge::from_bytes(&rom).unwrap();
+        let error = NesBus::from_cartridge(&cartridge).err().unwrap();
+        assert_eq!(error.to_string(), "Unsupported mapper 255");
+    }
+
+    //blargg's mmc3_test ROMs, from roms/mmc3_test or $MMC3_TEST_ROMS. Missing ROMs are skipped.
+    //Results come back through PRG RAM: DE B0 61 at $6001 marks them valid, $6000 is $80
+    //while running, $81 when the ROM wants the reset button, otherwise the result code
+    #[test]
+    #[ignore]
+    fn mmc3_test_roms() {
+        let directory = std::env::var("MMC3_TEST_ROMS").unwrap_or_else(|_| "roms/mmc3_test".to_string());
+        let names = [
+            "1-clocking.nes",
+            "2-details.nes",
+            "3-A12_clocking.nes",
+            "4-scanline_timing.nes",
+            "5-MMC3.nes",
+            "6-MMC3_alt.nes",
+        ];
+        let mut failures = Vec::new();
+        for name in names {
+            let path = std::path::Path::new(&directory).join(name);
+            if !path.exists() {
+                eprintln!("Skipping {}, not found", path.display());
+                continue;
+            }
+            let cartridge = Cartridge::from_path(&path).unwrap();
+            let mut cpu = CPU::new(NesBus::from_cartridge(&cartridge).unwrap());
+            cpu.reset();
+
+            let mut reset_frame = None;
+            let mut result = None;
+            for frame in 0..1200 {
+                cpu.run_frame();
+                if cpu.halted() {
+                    result = Some(format!("CPU halted at ${:04X}", cpu.program_counter()));
+                    break;
+                }
+                if reset_frame == Some(frame) {
+                    reset_frame = None;
+                    cpu.reset();
+                    continue;
+                }
+                let prg_ram = &cpu.bus().prg_ram;
+                if prg_ram[1..4] != [0xDE, 0xB0, 0x61] {
+                    continue;
+                }
+                match prg_ram[0] {
+                    0x80 => {}
+                    //The ROM wants at least 100 ms between the request and the reset
+                    0x81 => {
+                        if reset_frame.is_none() {
+                            reset_frame = Some(frame + 6);
+                        }
+                    }
+                    0x00 => {
+                        result = Some(String::new());
+                        break;
+                    }
+                    code => {
+                        let text = prg_ram[4..].iter().take_while(|&&byte| byte != 0).map(|&byte| byte as char);
+                        result = Some(format!("code {}: {}", code, text.collect::<String>().trim()));
+                        break;
+                    }
+                }
+            }
+            match result {
+                Some(text) if text.is_empty() => {}
+                Some(text) => failures.push(format!("{}: {}", name, text)),
+                None => failures.push(format!("{}: timed out", name)),
+            }
+        }
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
 }
@@ -2,10 +2,247 @@ mod rom_loader;
 mod mapper;
 mod cpu_bus;
 mod cpu;
+mod apu;
+mod controller;
+mod ppu;
+mod ppu_bus;
+mod region;
+mod wav;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use apu::Channel;
+use cpu::CPU;
+use cpu_bus::NesBus;
+use region::Region;
+use rom_loader::Cartridge;
+
+const USAGE: &str = concat!(
+    "<rom.nes> [--frames N] [--region ntsc|pal|dendy] [--mute channel]... [--solo channel] ",
+    "[--wav out.wav | --wav-channels dir]"
+);
+
+//Command line options. Without a frame count the console runs until the CPU halts. WAV
+//recordings need a frame count so headless runs in CI always finish. --wav-channels writes
+//<rom name>_<channel>.wav for each channel into the directory. --region overrides the
+//header's timing. --mute (repeatable) and --solo take a channel name such as pulse1 or dmc
+#[derive(Debug, PartialEq)]
+struct Options {
+    rom: String,
+    frames: Option<u32>,
+    wav: Option<PathBuf>,
+    wav_channels: Option<PathBuf>,
+    region: Option<Region>,
+    muted: Vec<Channel>,
+    solo: Option<Channel>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom = None;
+    let mut frames = None;
+    let mut wav = None;
+    let mut wav_channels = None;
+    let mut region = None;
+    let mut muted = Vec::new();
+    let mut solo = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let count = args.next().ok_or("--frames needs a count")?;
+                frames = Some(count.parse().map_err(|_| format!("Invalid frame count {}", count))?);
+            }
+            "--region" => {
+                region = Some(match args.next().ok_or("--region needs ntsc, pal or dendy")?.as_str() {
+                    "ntsc" => Region::Ntsc,
+                    "pal" => Region::Pal,
+                    "dendy" => Region::Dendy,
+                    other => return Err(format!("Unknown region {}", other)),
+                });
+            }
+            "--mute" => muted.push(parse_channel(args.next().ok_or("--mute needs a channel")?)?),
+            "--solo" => solo = Some(parse_channel(args.next().ok_or("--solo needs a channel")?)?),
+            "--wav" => wav = Some(PathBuf::from(args.next().ok_or("--wav needs a file")?)),
+            "--wav-channels" => {
+                wav_channels = Some(PathBuf::from(args.next().ok_or("--wav-channels needs a directory")?));
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    if !muted.is_empty() && solo.is_some() {
+        return Err("--mute and --solo can't be combined".to_string());
+    }
+    if wav.is_some() && wav_channels.is_some() {
+        return Err("--wav and --wav-channels can't be combined".to_string());
+    }
+    if (wav.is_some() || wav_channels.is_some()) && frames.is_none() {
+        return Err("WAV recording needs --frames".to_string());
+    }
+    Ok(Options {
+        rom: rom.ok_or("No ROM given")?,
+        frames,
+        wav,
+        wav_channels,
+        region,
+        muted,
+        solo,
+    })
+}
+
+fn parse_channel(name: String) -> Result<Channel, String> {
+    Channel::ALL.into_iter().find(|channel| channel.name() == name).ok_or_else(|| {
+        let names: Vec<_> = Channel::ALL.iter().map(|channel| channel.name()).collect();
+        format!("Unknown channel {}, expected one of {}", name, names.join(", "))
+    })
+}
 
 fn main() {
-    let _result = match rom_loader::load_rom() {
-        Ok(cartridge) => cartridge,
-        Err(error) => panic!("{}", error)
+    //ROM to run is the first argument, so any game or test ROM works without a rebuild
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}", error);
+            eprintln!("Usage: {} {}", env!("CARGO_PKG_NAME"), USAGE);
+            process::exit(2);
+        }
     };
+    if let Err(error) = run(&options) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let cartridge = Cartridge::from_path(&options.rom).map_err(|error| format!("{}: {}", options.rom, error))?;
+    println!("{}", cartridge);
+    for warning in cartridge.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    let mut bus = NesBus::from_cartridge(&cartridge).map_err(|error| format!("{}: {}", options.rom, error))?;
+    if let Some(region) = options.region {
+        bus.set_region(region);
+    }
+    for &channel in &options.muted {
+        bus.apu_mut().set_muted(channel, true);
+    }
+    if let Some(channel) = options.solo {
+        bus.apu_mut().solo(channel);
+    }
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    if let (Some(path), Some(frames)) = (&options.wav, options.frames) {
+        wav::record_to_file(&mut cpu, frames, path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        println!("Recorded {} frames to {}", frames, path.display());
+        return Ok(());
+    }
+    if let (Some(directory), Some(frames)) = (&options.wav_channels, options.frames) {
+        let stem = Path::new(&options.rom).file_stem().unwrap_or("audio".as_ref());
+        let path = directory.join(stem).with_extension("wav");
+        wav::record_channels_to_files(&mut cpu, frames, &path)
+            .map_err(|error| format!("{}: {}", directory.display(), error))?;
+        println!("Recorded {} frames per channel to {}", frames, directory.display());
+        return Ok(());
+    }
+
+    let mut frames = 0;
+    while !cpu.halted() && options.frames.is_none_or(|limit| frames < limit) {
+        cpu.run_frame();
+        frames += 1;
+    }
+    match cpu.halted() {
+        true => println!("CPU halted at ${:04X} after {} frames", cpu.program_counter(), frames),
+        false => println!("Ran {} frames", frames),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn parse_rom_and_frames() {
+        let options = parse_args(args(&["game.nes", "--frames", "60"])).unwrap();
+        assert_eq!(
+            options,
+            Options {
+                rom: "game.nes".to_string(),
+                frames: Some(60),
+                wav: None,
+                wav_channels: None,
+                region: None,
+                muted: vec![],
+                solo: None,
+            }
+        );
+
+        let options = parse_args(args(&["game.nes"])).unwrap();
+        assert_eq!(options.frames, None);
+    }
+
+    #[test]
+    fn parse_wav() {
+        let options = parse_args(args(&["--wav", "out.wav", "game.nes", "--frames", "600"])).unwrap();
+        assert_eq!(options.rom, "game.nes");
+        assert_eq!(options.frames, Some(600));
+        assert_eq!(options.wav, Some(PathBuf::from("out.wav")));
+
+        assert_eq!(parse_args(args(&["game.nes", "--wav", "out.wav"])), Err("WAV recording needs --frames".to_string()));
+
+        let options = parse_args(args(&["game.nes", "--frames", "60", "--wav-channels", "audio"])).unwrap();
+        assert_eq!(options.wav_channels, Some(PathBuf::from("audio")));
+        assert_eq!(
+            parse_args(args(&["game.nes", "--frames", "1", "--wav", "a.wav", "--wav-channels", "audio"])),
+            Err("--wav and --wav-channels can't be combined".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_region() {
+        let options = parse_args(args(&["game.nes", "--region", "pal"])).unwrap();
+        assert_eq!(options.region, Some(Region::Pal));
+        let options = parse_args(args(&["--region", "dendy", "game.nes"])).unwrap();
+        assert_eq!(options.region, Some(Region::Dendy));
+        assert_eq!(parse_args(args(&["game.nes", "--region", "ntsc"])).unwrap().region, Some(Region::Ntsc));
+
+        assert_eq!(parse_args(args(&["game.nes", "--region", "secam"])), Err("Unknown region secam".to_string()));
+        assert_eq!(parse_args(args(&["game.nes", "--region"])), Err("--region needs ntsc, pal or dendy".to_string()));
+    }
+
+    #[test]
+    fn parse_mute_and_solo() {
+        let options = parse_args(args(&["game.nes", "--mute", "pulse1", "--mute", "dmc"])).unwrap();
+        assert_eq!(options.muted, vec![Channel::Pulse1, Channel::Dmc]);
+        assert_eq!(options.solo, None);
+        let options = parse_args(args(&["--solo", "triangle", "game.nes"])).unwrap();
+        assert_eq!(options.solo, Some(Channel::Triangle));
+        assert!(options.muted.is_empty());
+
+        assert_eq!(
+            parse_args(args(&["game.nes", "--mute", "bass"])),
+            Err("Unknown channel bass, expected one of pulse1, pulse2, triangle, noise, dmc, expansion".to_string())
+        );
+        assert_eq!(parse_args(args(&["game.nes", "--solo"])), Err("--solo needs a channel".to_string()));
+        assert_eq!(
+            parse_args(args(&["game.nes", "--mute", "noise", "--solo", "dmc"])),
+            Err("--mute and --solo can't be combined".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_args(args(&[])), Err("No ROM given".to_string()));
+        assert_eq!(parse_args(args(&["game.nes", "--frames"])), Err("--frames needs a count".to_string()));
+        assert_eq!(parse_args(args(&["game.nes", "--frames", "x"])), Err("Invalid frame count x".to_string()));
+        assert_eq!(parse_args(args(&["game.nes", "--fast"])), Err("Unknown option --fast".to_string()));
+        assert_eq!(parse_args(args(&["a.nes", "b.nes"])), Err("Unexpected argument b.nes".to_string()));
+    }
 }
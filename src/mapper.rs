@@ -1,161 +1,474 @@
-use std::fs;
-// use crate::rom_loader::Cartridge;
-
-pub struct Mapper {
-    mapper: usize,
-    //For later
-    //mapper_state: usize,
+mod axrom;
+mod bnrom;
+mod camerica;
+mod cnrom;
+mod color_dreams;
+mod fme7;
+mod gxrom;
+mod mmc1;
+mod mmc2;
+mod mmc3;
+mod mmc5;
+mod namco108;
+mod namco163;
+mod nina06;
+mod nrom;
+mod uxrom;
+mod vrc4;
+mod vrc6;
+mod vrc7;
+mod vrc_irq;
+
+use std::fmt;
+
+use crate::mapper::axrom::Axrom;
+use crate::mapper::bnrom::Bnrom;
+use crate::mapper::camerica::Camerica;
+use crate::mapper::cnrom::Cnrom;
+use crate::mapper::color_dreams::ColorDreams;
+use crate::mapper::fme7::Fme7;
+use crate::mapper::gxrom::Gxrom;
+use crate::mapper::mmc1::Mmc1;
+use crate::mapper::mmc2::Mmc2;
+use crate::mapper::mmc3::Mmc3;
+use crate::mapper::mmc5::Mmc5;
+use crate::mapper::namco108::Namco108;
+use crate::mapper::namco163::Namco163;
+use crate::mapper::nina06::Nina06;
+use crate::mapper::nrom::Nrom;
+use crate::mapper::uxrom::Uxrom;
+use crate::mapper::vrc4::Vrc4;
+use crate::mapper::vrc6::Vrc6;
+use crate::mapper::vrc7::Vrc7;
+use crate::rom_loader::{Cartridge, Nametable};
+
+//How long PPU A12 has to stay low before a rise counts. Boards like MMC3 filter out the
+//short dips between sprite pattern fetches so they only see one edge per scanline
+const A12_LOW_FILTER_CYCLES: u64 = 10;
+
+pub struct A12Watcher {
+    a12: bool,
+    low_since: u64,
 }
-impl Mapper {
-    pub fn new(mapper: usize) -> Self {
-        Self { mapper }
-    }
-    pub fn cpu_read(&self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
-        //This will eventually be a switch statement for all implemented mappers
-        match self.mapper {
-            0 => self.cpu_read_mapper_0(prg_rom, prg_ram, address),
-            _ => 0,
+impl A12Watcher {
+    pub fn new() -> Self {
+        Self { a12: false, low_since: 0 }
+    }
+    //Feed every address the PPU drives. Returns true on a filtered rising edge of A12
+    pub fn observe(&mut self, address: u16, ppu_cycle: u64) -> bool {
+        let a12 = address & 0x1000 != 0;
+        let rising_edge = a12 && !self.a12 && ppu_cycle.wrapping_sub(self.low_since) >= A12_LOW_FILTER_CYCLES;
+
+        if !a12 && self.a12 {
+            self.low_since = ppu_cycle;
         }
+        self.a12 = a12;
+
+        rising_edge
     }
-    pub fn cpu_write(&self, prg_ram: &mut [u8], address: u16, value: u8) {
-        match self.mapper {
-            0 => self.cpu_write_mapper_0(prg_ram, address, value),
-            _ => (),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MapperError {
+    Unsupported { mapper: u16, submapper: u8 },
+    InvalidState,
+}
+impl fmt::Display for MapperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapperError::Unsupported { mapper, submapper: 0 } => write!(f, "Unsupported mapper {}", mapper),
+            MapperError::Unsupported { mapper, submapper } => {
+                write!(f, "Unsupported mapper {} (submapper {})", mapper, submapper)
+            }
+            MapperError::InvalidState => write!(f, "Mapper save state does not match this board"),
         }
     }
-    fn cpu_read_mapper_0(&self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
-        //CPU $6000-$7FFF: Unbanked PRG-RAM, mirrored as necessary to fill entire 8 KiB window, write protectable with an external switch.
-        //CPU $8000-$BFFF: First 16 KiB of PRG-ROM.
-        //CPU $C000-$FFFF: Last 16 KiB of PRG-ROM (NROM-256) or mirror of $8000-$BFFF (NROM-128).
-        //PPU $0000-$1FFF: 8 KiB CHR-ROM.
+}
+impl std::error::Error for MapperError {}
+
+//Cartridge board. The ROM and RAM contents stay owned by the bus and are passed in as
+//slices, boards only hold their banking registers and whatever extra hardware they carry
+pub trait Mapper {
+    //CPU $4020-$FFFF. prg_rom is passed to writes as well for boards with bus conflicts
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8;
+    fn cpu_write(&mut self, prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8);
+
+    //PPU $0000-$3EFF. vram is the console's 2 KiB of CIRAM plus 2 KiB that only four
+    //screen boards wire up
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8;
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8);
+
+    //Current nametable arrangement. Fixed for simple boards, register controlled on others
+    fn mirroring(&self) -> Nametable;
+
+    //Level of the cartridge's IRQ output
+    fn irq(&self) -> bool {
+        false
+    }
+    //Every address the PPU drives, for boards that count scanlines off A12 or nametable fetches
+    fn ppu_address(&mut self, _address: u16, _ppu_cycle: u64) {}
+    //CPU writes to the PPU registers, for boards that snoop $2000/$2001 to follow the
+    //sprite size or rendering state
+    fn ppu_register_write(&mut self, _address: u16, _value: u8) {}
+    //Once per CPU cycle, for cycle counting IRQs
+    fn cpu_clock(&mut self) {}
+    //Expansion audio level, mixed in with the APU
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
 
-        //Need a sanity check here. Check if address is reasonable
+    //Banking registers and counters only. The bus has no save state yet, so PRG/CHR RAM and
+    //VRAM have to be kept alongside this blob by whoever calls it
+    fn save_state(&self) -> Vec<u8> {
+        vec![]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state.is_empty() {
+            true => Ok(()),
+            false => Err(MapperError::InvalidState),
+        }
+    }
+}
 
-        let translated_address: usize;
+//Pick the board for an iNES/NES 2.0 mapper number
+pub fn new_mapper(mapper: u16, submapper: u8, mirroring: Nametable) -> Result<Box<dyn Mapper>, MapperError> {
+    match mapper {
+        0 => Ok(Box::new(Nrom::new(mirroring))),
+        1 => Ok(Box::new(Mmc1::new())),
+        2 => Ok(Box::new(Uxrom::new(mirroring, submapper))),
+        3 => Ok(Box::new(Cnrom::new(mirroring, submapper))),
+        4 => Ok(Box::new(Mmc3::new(mirroring, submapper))),
+        5 => Ok(Box::new(Mmc5::new())),
+        7 => Ok(Box::new(Axrom::new(submapper))),
+        9 | 10 => Ok(Box::new(Mmc2::new(mapper))),
+        11 => Ok(Box::new(ColorDreams::new(mirroring, submapper))),
+        19 => Ok(Box::new(Namco163::new())),
+        21 | 22 | 23 | 25 => Ok(Box::new(Vrc4::new(mapper, submapper)?)),
+        24 | 26 => Ok(Box::new(Vrc6::new(mapper))),
+        34 => Ok(Box::new(Bnrom::new(mirroring, submapper))),
+        66 | 140 => Ok(Box::new(Gxrom::new(mapper, mirroring, submapper))),
+        69 => Ok(Box::new(Fme7::new())),
+        71 => Ok(Box::new(Camerica::new(mirroring, submapper))),
+        79 => Ok(Box::new(Nina06::new(mirroring))),
+        85 => Ok(Box::new(Vrc7::new(submapper))),
+        206 => Ok(Box::new(Namco108::new(mirroring))),
+        _ => Err(MapperError::Unsupported { mapper, submapper }),
+    }
+}
 
-        //Get size of PRG_ROM and RAM
-        let prg_rom_size: usize = prg_rom.len();
-        let prg_ram_size: usize = prg_ram.len();
+pub fn from_cartridge(cartridge: &Cartridge) -> Result<Box<dyn Mapper>, MapperError> {
+    new_mapper(cartridge.mapper(), cartridge.submapper(), cartridge.mirroring())
+}
 
-        //Notes for my own sake:
-        //(address - base) % bank_size gives an automatic translated address with baked-in mirroring
-        //All addresses up to bank_size are mirrored to the next bytes of bank_size
+//Discrete logic boards (UxROM, CNROM, AxROM...) have their register and the ROM both
+//driving the data bus on writes, so the value latched is ANDed with the ROM byte. NES 2.0
+//submapper 2 marks boards with AND-type conflicts, 1 boards without. Unspecified (0) is
+//treated as conflict free, since the rare games that care write matching ROM values anyway
+pub fn bus_conflicts(submapper: u8) -> bool {
+    submapper == 2
+}
 
-        //Start address mapping
-        match address {
-            0x6000..=0x7FFF => {
-                translated_address = (address as usize - 0x6000) % prg_ram_size;
-                prg_ram[translated_address]
-            }
-            0x8000..=0xFFFF => {
-                translated_address = (address as usize - 0x8000) % prg_rom_size;
-                prg_rom[translated_address]
-            }
-            _ => {
-                println!("Invalid address");
-                0 as u8
-            }
-        }
+//Index into vram for a nametable address. The console only has 2 KiB of nametable RAM
+//(CIRAM). The cartridge decides which of the four logical nametables land on which
+//physical one via CIRAM A10
+//Horizontal: $2000/$2400 share the first KiB, $2800/$2C00 share the second
+//Vertical: $2000/$2800 share the first KiB, $2400/$2C00 share the second
+//Single screen: all four share one KiB
+//Four screen: the cartridge adds 2 KiB so every nametable is unique
+pub fn nametable_address(mirroring: Nametable, address: u16) -> usize {
+    let address = (address & 0x0FFF) as usize;
+    match mirroring {
+        Nametable::Horizontal => ((address >> 1) & 0x0400) | (address & 0x03FF),
+        Nametable::Vertical => address & 0x07FF,
+        Nametable::SingleScreenLower => address & 0x03FF,
+        Nametable::SingleScreenUpper => 0x0400 | (address & 0x03FF),
+        Nametable::FourScreen => address,
     }
-    fn cpu_write_mapper_0(&self, prg_ram: &mut [u8], address: u16, value: u8) {
-        let translated_address: usize;
+}
 
-        //Get size of PRG_RAM
-        let prg_ram_size: usize = prg_ram.len();
+//Pattern table byte at a board's translated CHR address. Boards without CHR ROM use their
+//CHR RAM, either one wraps to its size
+pub fn chr_read(chr_rom: &[u8], chr_ram: &[u8], chr_address: usize) -> u8 {
+    let chr = if chr_rom.is_empty() { chr_ram } else { chr_rom };
+    chr[chr_address % chr.len()]
+}
+//CHR ROM is read only, writes only land in CHR RAM
+pub fn chr_write(chr_rom: &[u8], chr_ram: &mut [u8], chr_address: usize, value: u8) {
+    if chr_rom.is_empty() {
+        let chr_ram_size = chr_ram.len();
+        chr_ram[chr_address % chr_ram_size] = value;
+    }
+}
 
-        //Start address matching. Need to mutate prg_ram location to new value
-        match address {
-            0x6000..=0x7FFF => {
-                translated_address = (address as usize - 0x6000) % prg_ram_size;
-                prg_ram[translated_address] = value;
-            }
-            _ => {
-                println!("Invalid address")
-            }
-        }
+//PPU side of the boards that only bank CHR: pattern tables through the board's chr_address
+//translation, nametables in CIRAM with its mirroring
+pub fn board_ppu_read(
+    chr_rom: &[u8],
+    chr_ram: &[u8],
+    vram: &[u8],
+    mirroring: Nametable,
+    address: u16,
+    chr_address: impl FnOnce(u16) -> usize,
+) -> u8 {
+    match address {
+        0x0000..=0x1FFF => chr_read(chr_rom, chr_ram, chr_address(address)),
+        0x2000..=0x3EFF => vram[nametable_address(mirroring, address)],
+        _ => 0,
     }
 }
+pub fn board_ppu_write(
+    chr_rom: &[u8],
+    chr_ram: &mut [u8],
+    vram: &mut [u8],
+    mirroring: Nametable,
+    address: u16,
+    value: u8,
+    chr_address: impl FnOnce(u16) -> usize,
+) {
+    match address {
+        0x0000..=0x1FFF => chr_write(chr_rom, chr_ram, chr_address(address), value),
+        0x2000..=0x3EFF => vram[nametable_address(mirroring, address)] = value,
+        _ => {}
+    }
+}
+
+//Test ROM where every byte holds the number of the bank it sits in, so a read shows which
+//bank is mapped
+#[cfg(test)]
+pub(crate) fn banked_rom(bank_size: usize, banks: usize) -> Vec<u8> {
+    (0..banks * bank_size).map(|i| (i / bank_size) as u8).collect()
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn prg_nrom_128_mapper_0() {
-        let mapper = Mapper::new(0);
-
-        let prg_rom_size = 16 * 1024;
-        let mut prg_rom = vec![0xEA; prg_rom_size];
-        let prg_ram = vec![0x00; 8 * 1024];
-
-        prg_rom[0] = 0xEB;
-
-        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x8000), 0xEB);
-        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0xC000), 0xEB);
+    fn board_ppu_helpers() {
+        let chr_rom = banked_rom(1024, 8);
+        let mut chr_ram = vec![0x00; 8 * 1024];
+        let mut vram = vec![0x00; 0x800];
+
+        //CHR ROM wins when present and ignores writes, addresses wrap to its size
+        assert_eq!(chr_read(&chr_rom, &chr_ram, 3 * 1024), 3);
+        assert_eq!(chr_read(&chr_rom, &chr_ram, 9 * 1024), 1);
+        chr_write(&chr_rom, &mut chr_ram, 0x10, 0xAA);
+        assert_eq!(chr_ram[0x10], 0x00);
+
+        //The board's translation only applies to pattern table addresses
+        let upper_bank = |address: u16| address as usize + 0x1000;
+        board_ppu_write(&[], &mut chr_ram, &mut vram, Nametable::Vertical, 0x0010, 0xAA, upper_bank);
+        assert_eq!(chr_ram[0x1010], 0xAA);
+        board_ppu_write(&[], &mut chr_ram, &mut vram, Nametable::Vertical, 0x2C01, 0xBB, |_| unreachable!());
+        assert_eq!(vram[0x0401], 0xBB);
+        assert_eq!(board_ppu_read(&[], &chr_ram, &vram, Nametable::Vertical, 0x2401, |_| unreachable!()), 0xBB);
+        assert_eq!(board_ppu_read(&[], &chr_ram, &vram, Nametable::Vertical, 0x0010, upper_bank), 0xAA);
     }
 
     #[test]
-    fn prg_nrom_256_mapper_0() {
-        let mapper = Mapper::new(0);
-
-        let prg_rom_size = 32 * 1024;
-        let mut prg_rom = vec![0xEA; prg_rom_size];
-        let prg_ram = vec![0x00; 8 * 1024];
-
-        prg_rom[0] = 0xEB;
-        prg_rom[0x4000] = 0xEC;
-
-        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x8000), 0xEB);
-        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0xC000), 0xEC);
+    fn a12_watcher_filters_short_dips() {
+        let mut watcher = A12Watcher::new();
+
+        //Background fetches from $0000 keep A12 low
+        assert!(!watcher.observe(0x2000, 100));
+        assert!(!watcher.observe(0x0010, 102));
+        //First sprite fetch from $1000 is a real edge
+        assert!(watcher.observe(0x1000, 200));
+        //Garbage nametable fetches between sprites only dip for 4 cycles
+        assert!(!watcher.observe(0x2000, 204));
+        assert!(!watcher.observe(0x1010, 208));
+        //Low for a whole line counts again
+        assert!(!watcher.observe(0x0000, 220));
+        assert!(watcher.observe(0x1000, 541));
     }
 
     #[test]
-    fn prg_ram_read_mapper_0() {
-        let mapper = Mapper::new(0);
-
-        let prg_rom_size = 16 * 1024;
-        let prg_rom = vec![0x00; prg_rom_size];
-        let mut prg_ram = vec![0xEA; 8 * 1024];
+    fn unsupported_mapper_error() {
+        let error = new_mapper(255, 0, Nametable::Horizontal).err().unwrap();
+        assert_eq!(error, MapperError::Unsupported { mapper: 255, submapper: 0 });
+        assert_eq!(error.to_string(), "Unsupported mapper 255");
+        let error = new_mapper(4000, 3, Nametable::Horizontal).err().unwrap();
+        assert_eq!(error.to_string(), "Unsupported mapper 4000 (submapper 3)");
+    }
 
-        prg_ram[0] = 0xEB;
-        prg_ram[8191] = 0xEC;
+    #[test]
+    fn factory_builds_nrom() {
+        let mut mapper = new_mapper(0, 0, Nametable::Vertical).unwrap();
+        let mut prg_rom = vec![0x00; 16 * 1024];
+        prg_rom[0] = 0xEB;
 
-        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x6000), 0xEB);
-        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x7FFF), 0xEC);
+        assert_eq!(mapper.mirroring(), Nametable::Vertical);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[0x00; 8 * 1024], 0xC000), 0xEB);
+        assert!(!mapper.irq());
     }
 
-   #[test]
-    fn prg_ram_write_mapper_0() {
-        let mapper = Mapper::new(0);
-
-        let mut prg_ram = vec![0x00; 8 * 1024];
+    //Writes to a fresh board, then the 8K PRG and 1K CHR bank expected at each address
+    struct BankSwitchCase {
+        mapper: u16,
+        submapper: u8,
+        writes: &'static [(u16, u8)],
+        prg: &'static [(u16, u8)],
+        chr: &'static [(u16, u8)],
+        mirroring: Nametable,
+    }
 
-        mapper.cpu_write(&mut prg_ram, 0x6001, 0xFF);
+    const BANK_SWITCH_CASES: &[BankSwitchCase] = &[
+        //MMC2: one 8K bank, three fixed, CHR from the $FE latch banks at power on
+        BankSwitchCase {
+            mapper: 9,
+            submapper: 0,
+            writes: &[(0xA000, 3), (0xB000, 2), (0xC000, 5), (0xD000, 6), (0xE000, 7), (0xF000, 1)],
+            prg: &[(0x8000, 3), (0xA000, 29), (0xC000, 30), (0xE000, 31)],
+            chr: &[(0x0000, 20), (0x1000, 28)],
+            mirroring: Nametable::Horizontal,
+        },
+        //MMC4: 16K bank
+        BankSwitchCase {
+            mapper: 10,
+            submapper: 0,
+            writes: &[(0xA000, 3), (0xC000, 5)],
+            prg: &[(0x8000, 6), (0xA000, 7), (0xC000, 30), (0xE000, 31)],
+            chr: &[(0x0000, 20)],
+            mirroring: Nametable::Vertical,
+        },
+        //Color Dreams: PRG in the low bits, CHR in the high nibble
+        BankSwitchCase {
+            mapper: 11,
+            submapper: 0,
+            writes: &[(0x8000, 0x32)],
+            prg: &[(0x8000, 8), (0xE000, 11)],
+            chr: &[(0x0000, 24), (0x1C00, 31)],
+            mirroring: Nametable::Vertical,
+        },
+        //BNROM ANDs the write with the ROM byte, which is 0 in bank 0
+        BankSwitchCase {
+            mapper: 34,
+            submapper: 2,
+            writes: &[(0x8000, 3)],
+            prg: &[(0x8000, 0), (0xE000, 3)],
+            chr: &[(0x0000, 0), (0x1000, 4)],
+            mirroring: Nametable::Vertical,
+        },
+        BankSwitchCase {
+            mapper: 34,
+            submapper: 0,
+            writes: &[(0x8000, 3)],
+            prg: &[(0x8000, 12), (0xE000, 15)],
+            chr: &[(0x0000, 0), (0x1000, 4)],
+            mirroring: Nametable::Vertical,
+        },
+        //NINA-001 registers at the top of PRG RAM
+        BankSwitchCase {
+            mapper: 34,
+            submapper: 1,
+            writes: &[(0x7FFD, 1), (0x7FFE, 2), (0x7FFF, 5), (0x8000, 0)],
+            prg: &[(0x8000, 4), (0xE000, 7)],
+            chr: &[(0x0000, 8), (0x1000, 20)],
+            mirroring: Nametable::Vertical,
+        },
+        //GxROM: PRG in bits 4-5, CHR in bits 0-1
+        BankSwitchCase {
+            mapper: 66,
+            submapper: 0,
+            writes: &[(0x8000, 0x12)],
+            prg: &[(0x8000, 4), (0xE000, 7)],
+            chr: &[(0x0000, 16)],
+            mirroring: Nametable::Vertical,
+        },
+        //Jaleco JF-11/14: the same register at $6000-$7FFF
+        BankSwitchCase {
+            mapper: 140,
+            submapper: 0,
+            writes: &[(0x6000, 0x25), (0x8000, 0x00)],
+            prg: &[(0x8000, 8)],
+            chr: &[(0x0000, 40)],
+            mirroring: Nametable::Vertical,
+        },
+        //Camerica: UxROM layout with the register at $C000
+        BankSwitchCase {
+            mapper: 71,
+            submapper: 0,
+            writes: &[(0xC000, 5), (0x8000, 0x10)],
+            prg: &[(0x8000, 10), (0xA000, 11), (0xC000, 30), (0xE000, 31)],
+            chr: &[(0x0000, 0)],
+            mirroring: Nametable::Vertical,
+        },
+        //Fire Hawk's one screen mirroring
+        BankSwitchCase {
+            mapper: 71,
+            submapper: 1,
+            writes: &[(0x8000, 0x10)],
+            prg: &[(0x8000, 0), (0xC000, 30)],
+            chr: &[],
+            mirroring: Nametable::SingleScreenUpper,
+        },
+        //NINA-03/06: register decoded in $4100-$5FFF
+        BankSwitchCase {
+            mapper: 79,
+            submapper: 0,
+            writes: &[(0x4100, 0x0D), (0x6100, 0x00), (0x5E00, 0x00)],
+            prg: &[(0x8000, 4), (0xE000, 7)],
+            chr: &[(0x0000, 40)],
+            mirroring: Nametable::Vertical,
+        },
+        //Namco 108: MMC3 style registers in fixed modes
+        BankSwitchCase {
+            mapper: 206,
+            submapper: 0,
+            writes: &[
+                (0x8000, 0),
+                (0x8001, 4),
+                (0x8000, 2),
+                (0x8001, 9),
+                (0x8000, 6),
+                (0x8001, 3),
+                (0x8000, 7),
+                (0x8001, 5),
+                (0xA000, 1),
+            ],
+            prg: &[(0x8000, 3), (0xA000, 5), (0xC000, 30), (0xE000, 31)],
+            chr: &[(0x0000, 4), (0x0400, 5), (0x1000, 9)],
+            mirroring: Nametable::Vertical,
+        },
+    ];
 
-        assert_eq!(prg_ram[1], 0xFF);
+    #[test]
+    fn bank_switching_table() {
+        let prg_rom = banked_rom(8 * 1024, 32);
+        let chr_rom = banked_rom(1024, 128);
+        let vram = [0x00; 0x1000];
+
+        for case in BANK_SWITCH_CASES {
+            let mut mapper = new_mapper(case.mapper, case.submapper, Nametable::Vertical).unwrap();
+            let mut prg_ram = vec![0x00; 8 * 1024];
+            for &(address, value) in case.writes {
+                mapper.cpu_write(&prg_rom, &mut prg_ram, address, value);
+            }
+            for &(address, bank) in case.prg {
+                let read = mapper.cpu_read(&prg_rom, &prg_ram, address);
+                assert_eq!(read, bank, "mapper {} PRG ${:04X}", case.mapper, address);
+            }
+            for &(address, bank) in case.chr {
+                let read = mapper.ppu_read(&chr_rom, &[], &vram, address);
+                assert_eq!(read, bank, "mapper {} CHR ${:04X}", case.mapper, address);
+            }
+            assert_eq!(mapper.mirroring(), case.mirroring, "mapper {} mirroring", case.mapper);
+        }
     }
 
     #[test]
-    fn prg_ram_write_mirrored_mapper_0() {
-        let mapper = Mapper::new(0);
-
-        let mut prg_ram = vec![0x00; 2*1024];
-
-        mapper.cpu_write(&mut prg_ram, 0x6000, 0xFF);
-        assert_eq!(prg_ram[0], 0xFF);
-        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6800), 0xFF);
+    fn single_screen_and_four_screen() {
+        assert_eq!(nametable_address(Nametable::SingleScreenLower, 0x2C05), 0x0005);
+        assert_eq!(nametable_address(Nametable::SingleScreenUpper, 0x2005), 0x0405);
+        assert_eq!(nametable_address(Nametable::FourScreen, 0x2C05), 0x0C05);
+        assert_eq!(nametable_address(Nametable::FourScreen, 0x3405), 0x0405);
     }
 
     #[test]
-    fn prg_rom_write_mapper_0() {
-        let mapper = Mapper::new(0);
-
-        let mut prg_ram = vec![0x00; 8 * 1024];
-        let prg_rom = vec![0xEA; 16 * 1024];
-
-        mapper.cpu_write(&mut prg_ram, 0x8001, 0xFF);
-
-        assert!(prg_ram.iter().all(|&x| x == 0));
-        assert!(prg_rom.iter().all(|&x| x == 0xEA));
+    fn stateless_board_rejects_foreign_state() {
+        let mut mapper = new_mapper(0, 0, Nametable::Vertical).unwrap();
+        assert_eq!(mapper.save_state(), Vec::<u8>::new());
+        assert_eq!(mapper.load_state(&[]), Ok(()));
+        assert_eq!(mapper.load_state(&[1, 2]), Err(MapperError::InvalidState));
     }
 }
@@ -0,0 +1,106 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write, bus_conflicts};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 32 * 1024;
+
+//Mapper 7. 32K PRG switching and a register bit picking which CIRAM page is shown on all
+//four nametables
+pub struct Axrom {
+    bus_conflicts: bool,
+    register: u8,
+}
+impl Axrom {
+    pub fn new(submapper: u8) -> Self {
+        Self {
+            bus_conflicts: bus_conflicts(submapper),
+            register: 0,
+        }
+    }
+}
+impl Mapper for Axrom {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (self.register & 0x0F) as usize;
+                prg_rom[(bank * PRG_BANK_SIZE + (address as usize - 0x8000)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.register = match self.bus_conflicts {
+                true => value & self.cpu_read(prg_rom, &[], address),
+                false => value,
+            };
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring(), address, |address| address as usize)
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| address as usize);
+    }
+    fn mirroring(&self) -> Nametable {
+        match self.register & 0x10 {
+            0 => Nametable::SingleScreenLower,
+            _ => Nametable::SingleScreenUpper,
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.register]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[register] => {
+                self.register = register;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn prg_32k_switch() {
+        let mut mapper = Axrom::new(0);
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xFFFF), 0);
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x06);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 6);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xFFFF), 6);
+    }
+
+    #[test]
+    fn single_screen_select() {
+        let mut mapper = Axrom::new(0);
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+        let mut vram = [0x00; 0x1000];
+
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2C05, 0xAA);
+        assert_eq!(vram[0x0005], 0xAA);
+        assert_eq!(mapper.mirroring(), Nametable::SingleScreenLower);
+
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x10);
+        assert_eq!(mapper.mirroring(), Nametable::SingleScreenUpper);
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2005, 0xBB);
+        assert_eq!(vram[0x0405], 0xBB);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2805), 0xBB);
+    }
+
+    #[test]
+    fn bus_conflicts() {
+        let mut prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+        prg_rom[0x10] = 0x13;
+
+        let mut mapper = Axrom::new(2);
+        mapper.cpu_write(&prg_rom, &mut [], 0x8010, 0x1E);
+        assert_eq!(mapper.register, 0x12);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 32 * 1024;
+const CHR_BANK_SIZE: usize = 4 * 1024;
+
+//Mapper 34 covers two unrelated boards. BNROM (submapper 2) switches 32K PRG with a latch
+//at $8000-$FFFF and has AND type bus conflicts. AVE NINA-001 (submapper 1) has its
+//registers over the last bytes of PRG RAM: $7FFD PRG, $7FFE and $7FFF the two 4K CHR
+//banks. Without a submapper both register sets are live, neither board's games write to
+//the other's
+pub struct Bnrom {
+    bnrom: bool,
+    nina: bool,
+    bus_conflicts: bool,
+    mirroring: Nametable,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+}
+impl Bnrom {
+    pub fn new(mirroring: Nametable, submapper: u8) -> Self {
+        Self {
+            bnrom: submapper != 1,
+            nina: submapper != 2,
+            bus_conflicts: submapper == 2,
+            mirroring,
+            prg_bank: 0,
+            //BNROM's 8K of CHR RAM is the two halves in order
+            chr_banks: [0, 1],
+        }
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        self.chr_banks[address as usize >> 12] as usize * CHR_BANK_SIZE + (address as usize & 0x0FFF)
+    }
+}
+impl Mapper for Bnrom {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !prg_ram.is_empty() => prg_ram[(address as usize - 0x6000) % prg_ram.len()],
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank as usize;
+                prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x7FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF => {
+                //The NINA-001 registers also land in the RAM underneath
+                if !prg_ram.is_empty() {
+                    let prg_ram_size = prg_ram.len();
+                    prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+                }
+                match address {
+                    0x7FFD if self.nina => self.prg_bank = value & 0x01,
+                    0x7FFE | 0x7FFF if self.nina => self.chr_banks[address as usize - 0x7FFE] = value & 0x0F,
+                    _ => {}
+                }
+            }
+            0x8000..=0xFFFF if self.bnrom => {
+                self.prg_bank = match self.bus_conflicts {
+                    true => value & self.cpu_read(prg_rom, &[], address),
+                    false => value,
+                };
+            }
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.prg_bank, self.chr_banks[0], self.chr_banks[1]]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[prg_bank, chr_bank_0, chr_bank_1] => {
+                self.prg_bank = prg_bank;
+                self.chr_banks = [chr_bank_0, chr_bank_1];
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
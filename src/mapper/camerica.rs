@@ -0,0 +1,71 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 16 * 1024;
+
+//Mapper 71, Camerica/Codemasters BF909x. UxROM style banking with the register at
+//$C000-$FFFF, so there are no bus conflicts. The BF9097 used by Fire Hawk (submapper 1)
+//adds one screen mirroring at $8000-$9FFF. Older dumps of Fire Hawk have no submapper,
+//and since only it writes to $9000-$9FFF that range always controls mirroring
+pub struct Camerica {
+    mirroring: Nametable,
+    mirroring_control: bool,
+    prg_bank: u8,
+    //0 until the game picks a screen, then 1 + CIRAM page
+    one_screen: u8,
+}
+impl Camerica {
+    pub fn new(mirroring: Nametable, submapper: u8) -> Self {
+        Self {
+            mirroring,
+            mirroring_control: submapper == 1,
+            prg_bank: 0,
+            one_screen: 0,
+        }
+    }
+}
+impl Mapper for Camerica {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x8000..=0xBFFF => self.prg_bank as usize % banks,
+            0xC000..=0xFFFF => banks - 1,
+            _ => return 0,
+        };
+        prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x3FFF)) % prg_rom.len()]
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x8000..=0x8FFF if !self.mirroring_control => {}
+            0x8000..=0x9FFF => self.one_screen = 1 + ((value >> 4) & 0x01),
+            0xC000..=0xFFFF => self.prg_bank = value,
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring(), address, |address| address as usize)
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| address as usize);
+    }
+    fn mirroring(&self) -> Nametable {
+        match self.one_screen {
+            0 => self.mirroring,
+            1 => Nametable::SingleScreenLower,
+            _ => Nametable::SingleScreenUpper,
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.prg_bank, self.one_screen]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[prg_bank, one_screen] => {
+                self.prg_bank = prg_bank;
+                self.one_screen = one_screen;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write, bus_conflicts};
+use crate::rom_loader::Nametable;
+
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+//Mapper 3. NROM style PRG with a switchable 8K CHR ROM bank
+pub struct Cnrom {
+    mirroring: Nametable,
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+impl Cnrom {
+    pub fn new(mirroring: Nametable, submapper: u8) -> Self {
+        Self {
+            mirroring,
+            bus_conflicts: bus_conflicts(submapper),
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        self.chr_bank as usize * CHR_BANK_SIZE + address as usize
+    }
+}
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => prg_rom[(address as usize - 0x8000) % prg_rom.len()],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.chr_bank = match self.bus_conflicts {
+                true => value & self.cpu_read(prg_rom, &[], address),
+                false => value,
+            };
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.chr_bank]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[chr_bank] => {
+                self.chr_bank = chr_bank;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn chr_bank_switch() {
+        let mut mapper = Cnrom::new(Nametable::Vertical, 0);
+        let prg_rom = vec![0xFF; 32 * 1024];
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 4);
+        let vram = [0x00; 0x1000];
+
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1FFF), 0);
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x02);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 2);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1FFF), 2);
+        //Bank wraps to the CHR size
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x05);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 1);
+    }
+
+    #[test]
+    fn prg_like_nrom() {
+        let mut mapper = Cnrom::new(Nametable::Vertical, 0);
+        let mut prg_rom = vec![0x00; 16 * 1024];
+        prg_rom[0] = 0xEB;
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 0xEB);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 0xEB);
+    }
+
+    #[test]
+    fn bus_conflicts() {
+        let mut mapper = Cnrom::new(Nametable::Vertical, 2);
+        let mut prg_rom = vec![0x00; 32 * 1024];
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 4);
+        let vram = [0x00; 0x1000];
+        prg_rom[0x10] = 0x01;
+
+        mapper.cpu_write(&prg_rom, &mut [], 0x8010, 0x03);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 1);
+    }
+
+    #[test]
+    fn chr_rom_is_read_only() {
+        let mut mapper = Cnrom::new(Nametable::Vertical, 0);
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 1);
+        let mut vram = [0x00; 0x1000];
+
+        mapper.ppu_write(&chr_rom, &mut [], &mut vram, 0x0001, 0xFF);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0001), 0);
+    }
+}
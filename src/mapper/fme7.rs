@@ -0,0 +1,278 @@
+mod audio;
+
+use crate::mapper::fme7::audio::Sunsoft5b;
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const STATE_SIZE: usize = 19 + 16;
+
+//Mapper 69, Sunsoft FME-7 and the 5B that adds sound. Commands written to $8000 pick
+//which register the parameter at $A000 goes to: eight 1K CHR banks, a $6000 bank that can
+//be ROM or RAM, three 8K PRG banks, mirroring and a 16 bit cycle counting IRQ
+pub struct Fme7 {
+    command: u8,
+    chr_banks: [u8; 8],
+    //Command 8: bits 0-5 bank, bit 6 RAM instead of ROM, bit 7 RAM enable
+    prg_ram_bank: u8,
+    prg_banks: [u8; 3],
+    mirroring: u8,
+    //Command D: bit 0 IRQ enable, bit 7 counter enable
+    irq_control: u8,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio_register: u8,
+    audio: Sunsoft5b,
+}
+impl Fme7 {
+    pub fn new() -> Self {
+        Self {
+            command: 0,
+            chr_banks: [0; 8],
+            prg_ram_bank: 0,
+            prg_banks: [0; 3],
+            mirroring: 0,
+            irq_control: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            audio_register: 0,
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x6000..=0x7FFF => self.prg_ram_bank as usize & 0x3F,
+            0xE000..=0xFFFF => banks - 1,
+            _ => self.prg_banks[(address as usize - 0x8000) >> 13] as usize & 0x3F,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        self.chr_banks[address as usize >> 10] as usize * CHR_BANK_SIZE + (address as usize & 0x03FF)
+    }
+    fn prg_ram_selected(&self) -> bool {
+        self.prg_ram_bank & 0x40 != 0
+    }
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_ram_bank & 0xC0 == 0xC0
+    }
+    fn prg_ram_address(&self, prg_ram: &[u8], address: u16) -> usize {
+        ((self.prg_ram_bank as usize & 0x3F) * PRG_BANK_SIZE + (address as usize - 0x6000)) % prg_ram.len()
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+            0x8 => self.prg_ram_bank = value,
+            0x9..=0xB => self.prg_banks[self.command as usize - 0x9] = value,
+            0xC => self.mirroring = value & 0x03,
+            0xD => {
+                self.irq_control = value;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16) << 8,
+        }
+    }
+}
+impl Mapper for Fme7 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram_selected() => prg_rom[self.prg_address(prg_rom, address)],
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !prg_ram.is_empty() => {
+                prg_ram[self.prg_ram_address(prg_ram, address)]
+            }
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !prg_ram.is_empty() => {
+                let translated_address = self.prg_ram_address(prg_ram, address);
+                prg_ram[translated_address] = value;
+            }
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio_register = value,
+            //The 5B ignores writes unless the upper nibble of the register select is clear
+            0xE000..=0xFFFF if self.audio_register & 0xF0 == 0 => self.audio.write(self.audio_register, value),
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring(), address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        match self.mirroring {
+            0 => Nametable::Vertical,
+            1 => Nametable::Horizontal,
+            2 => Nametable::SingleScreenLower,
+            _ => Nametable::SingleScreenUpper,
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+    //The counter decrements every cycle while enabled and fires when it wraps from 0
+    fn cpu_clock(&mut self) {
+        if self.irq_control & 0x80 != 0 {
+            if self.irq_counter == 0 && self.irq_control & 0x01 != 0 {
+                self.irq_pending = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+        self.audio.clock();
+    }
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.command];
+        state.extend_from_slice(&self.chr_banks);
+        state.push(self.prg_ram_bank);
+        state.extend_from_slice(&self.prg_banks);
+        state.extend_from_slice(&[self.mirroring, self.irq_control]);
+        state.extend_from_slice(&self.irq_counter.to_le_bytes());
+        state.extend_from_slice(&[self.irq_pending as u8, self.audio_register]);
+        state.extend_from_slice(&self.audio.save_state());
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.command = state[0];
+        self.chr_banks.copy_from_slice(&state[1..9]);
+        self.prg_ram_bank = state[9];
+        self.prg_banks.copy_from_slice(&state[10..13]);
+        self.mirroring = state[13];
+        self.irq_control = state[14];
+        self.irq_counter = u16::from_le_bytes([state[15], state[16]]);
+        self.irq_pending = state[17] != 0;
+        self.audio_register = state[18];
+        self.audio.load_state(&state[19..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    fn command(mapper: &mut Fme7, command: u8, value: u8) {
+        mapper.cpu_write(&[], &mut [], 0x8000, command);
+        mapper.cpu_write(&[], &mut [], 0xA000, value);
+    }
+
+    #[test]
+    fn prg_banking() {
+        let mut mapper = Fme7::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 32);
+
+        command(&mut mapper, 0x9, 4);
+        command(&mut mapper, 0xA, 5);
+        command(&mut mapper, 0xB, 6);
+        command(&mut mapper, 0x8, 7);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x6000), 7);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 4);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 5);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 6);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 31);
+    }
+
+    #[test]
+    fn prg_ram_select_and_enable() {
+        let mut mapper = Fme7::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 4);
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        //RAM selected but disabled: open bus, writes ignored
+        command(&mut mapper, 0x8, 0x40);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x6000, 0x55);
+        assert_eq!(prg_ram[0], 0x00);
+
+        command(&mut mapper, 0x8, 0xC0);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x6000, 0x55);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x6000), 0x55);
+    }
+
+    #[test]
+    fn chr_banking_and_mirroring() {
+        let mut mapper = Fme7::new();
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 256);
+        let vram = [0x00; 0x1000];
+
+        command(&mut mapper, 0x1, 0x21);
+        command(&mut mapper, 0x7, 0x87);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0400), 0x21);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1C00), 0x87);
+
+        command(&mut mapper, 0xC, 0x03);
+        assert_eq!(mapper.mirroring(), Nametable::SingleScreenUpper);
+    }
+
+    #[test]
+    fn irq_fires_when_the_counter_wraps() {
+        let mut mapper = Fme7::new();
+        command(&mut mapper, 0xE, 0x02);
+        command(&mut mapper, 0xF, 0x00);
+        command(&mut mapper, 0xD, 0x81);
+
+        for _ in 0..2 {
+            mapper.cpu_clock();
+            assert!(!mapper.irq());
+        }
+        mapper.cpu_clock();
+        assert!(mapper.irq());
+
+        //Writing the control register acknowledges
+        command(&mut mapper, 0xD, 0x81);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn irq_counter_can_run_without_irqs() {
+        let mut mapper = Fme7::new();
+        command(&mut mapper, 0xD, 0x80);
+        mapper.cpu_clock();
+        assert!(!mapper.irq());
+        assert_eq!(mapper.irq_counter, 0xFFFF);
+    }
+
+    #[test]
+    fn audio_ports() {
+        let mut mapper = Fme7::new();
+        for (register, value) in [(0x00, 0x01), (0x07, 0x3E), (0x08, 0x0F)] {
+            mapper.cpu_write(&[], &mut [], 0xC000, register);
+            mapper.cpu_write(&[], &mut [], 0xE000, value);
+        }
+        let mut heard = false;
+        for _ in 0..64 {
+            mapper.cpu_clock();
+            heard |= mapper.audio_output() != 0.0;
+        }
+        assert!(heard);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut mapper = Fme7::new();
+        command(&mut mapper, 0x3, 0x12);
+        command(&mut mapper, 0xE, 0x34);
+        mapper.cpu_write(&[], &mut [], 0xC000, 0x08);
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x0C);
+        let state = mapper.save_state();
+
+        let mut restored = Fme7::new();
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+    }
+}
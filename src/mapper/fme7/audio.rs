@@ -0,0 +1,289 @@
+//Volume of the loudest step relative to the APU, a Sunsoft 5B tone at full volume is
+//somewhat louder than an APU pulse
+const LEVEL_SCALE: f32 = 0.15;
+//The chip divides the CPU clock by 16 before its tone, noise and envelope counters
+const PRESCALER: u8 = 16;
+
+//Logarithmic DAC, 32 steps of 1.5 dB. Channel volumes are 4 bits and use every other step
+const VOLUME_TABLE: [f32; 32] = {
+    let mut table = [0.0; 32];
+    //10^(-1.5/20)
+    const STEP: f32 = 0.841_395_1;
+    let mut level = 1.0;
+    let mut i = 31;
+    while i > 0 {
+        table[i] = level;
+        level *= STEP;
+        i -= 1;
+    }
+    table
+};
+
+//Square wave whose period counts prescaled clocks, flipping every time it expires
+struct Tone {
+    period: u16,
+    timer: u16,
+    high: bool,
+}
+impl Tone {
+    fn new() -> Self {
+        Self { period: 0, timer: 0, high: false }
+    }
+    fn clock(&mut self) {
+        self.timer += 1;
+        if self.timer >= self.period.max(1) {
+            self.timer = 0;
+            self.high = !self.high;
+        }
+    }
+}
+
+//32 step volume ramp. Register 13 bits: 0 hold, 1 alternate, 2 attack, 3 continue
+struct Envelope {
+    period: u16,
+    timer: u16,
+    shape: u8,
+    step: u8,
+    holding: bool,
+    //Direction the ramp is currently going, flipped by alternate
+    rising: bool,
+}
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            period: 0,
+            timer: 0,
+            shape: 0,
+            step: 0,
+            holding: false,
+            rising: false,
+        }
+    }
+    fn restart(&mut self, shape: u8) {
+        self.shape = shape & 0x0F;
+        self.timer = 0;
+        self.step = 0;
+        self.holding = false;
+        self.rising = self.shape & 0x04 != 0;
+    }
+    fn clock(&mut self) {
+        if self.holding {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < self.period.max(1) {
+            return;
+        }
+        self.timer = 0;
+        if self.step < 31 {
+            self.step += 1;
+            return;
+        }
+
+        //End of a ramp
+        let continues = self.shape & 0x08 != 0;
+        let hold = self.shape & 0x01 != 0;
+        let alternate = self.shape & 0x02 != 0;
+        match (continues, hold) {
+            //Shapes 0-7 drop to silence and stay there
+            (false, _) => {
+                self.rising = false;
+                self.holding = true;
+            }
+            (true, true) => {
+                self.rising ^= alternate;
+                self.holding = true;
+            }
+            (true, false) => {
+                self.rising ^= alternate;
+                self.step = 0;
+            }
+        }
+        if self.holding {
+            self.step = 31;
+        }
+    }
+    fn level(&self) -> u8 {
+        match self.rising {
+            true => self.step,
+            false => 31 - self.step,
+        }
+    }
+}
+
+//Sunsoft 5B, a licensed AY-3-8910: three square wave tones, one noise generator and an
+//envelope, all behind a register select at $C000 and data port at $E000
+pub struct Sunsoft5b {
+    tones: [Tone; 3],
+    noise_period: u8,
+    noise_timer: u8,
+    //17 bit LFSR, output is bit 0
+    noise: u32,
+    envelope: Envelope,
+    prescaler: u8,
+    //Register values for save states, replayed on load
+    registers: [u8; 16],
+}
+impl Sunsoft5b {
+    pub fn new() -> Self {
+        Self {
+            tones: [Tone::new(), Tone::new(), Tone::new()],
+            noise_period: 0,
+            noise_timer: 0,
+            noise: 1,
+            envelope: Envelope::new(),
+            prescaler: 0,
+            registers: [0; 16],
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = register & 0x0F;
+        self.registers[register as usize] = value;
+        match register {
+            0x00..=0x05 => {
+                let tone = &mut self.tones[register as usize >> 1];
+                tone.period = match register & 0x01 {
+                    0 => (tone.period & 0x0F00) | value as u16,
+                    _ => (tone.period & 0x00FF) | (value as u16 & 0x0F) << 8,
+                };
+            }
+            0x06 => self.noise_period = value & 0x1F,
+            0x0B => self.envelope.period = (self.envelope.period & 0xFF00) | value as u16,
+            0x0C => self.envelope.period = (self.envelope.period & 0x00FF) | (value as u16) << 8,
+            0x0D => self.envelope.restart(value),
+            //Mixer and volumes are read straight from the registers
+            _ => {}
+        }
+    }
+
+    //Once per CPU cycle
+    pub fn clock(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < PRESCALER {
+            return;
+        }
+        self.prescaler = 0;
+
+        for tone in self.tones.iter_mut() {
+            tone.clock();
+        }
+        self.noise_timer += 1;
+        if self.noise_timer >= self.noise_period.max(1) * 2 {
+            self.noise_timer = 0;
+            let feedback = (self.noise ^ (self.noise >> 3)) & 0x01;
+            self.noise = (self.noise >> 1) | feedback << 16;
+        }
+        self.envelope.clock();
+    }
+
+    pub fn output(&self) -> f32 {
+        let mixer = self.registers[0x07];
+        let level: f32 = (0..3)
+            .map(|channel| {
+                //Mixer bits are active low disables, a disabled source reads as high
+                let tone = mixer & (1 << channel) != 0 || self.tones[channel].high;
+                let noise = mixer & (8 << channel) != 0 || self.noise & 0x01 != 0;
+                if !(tone && noise) {
+                    return 0.0;
+                }
+                let volume = self.registers[0x08 + channel];
+                let step = match volume & 0x10 {
+                    0 => match volume & 0x0F {
+                        0 => 0,
+                        volume => volume * 2 + 1,
+                    },
+                    _ => self.envelope.level(),
+                };
+                VOLUME_TABLE[step as usize]
+            })
+            .sum();
+        level * LEVEL_SCALE
+    }
+
+    pub fn save_state(&self) -> [u8; 16] {
+        self.registers
+    }
+    pub fn load_state(&mut self, state: &[u8]) {
+        *self = Sunsoft5b::new();
+        for (register, &value) in state.iter().enumerate() {
+            //Writing the shape restarts the envelope, fine for a state load
+            self.write(register as u8, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_table_is_logarithmic() {
+        assert_eq!(VOLUME_TABLE[0], 0.0);
+        assert_eq!(VOLUME_TABLE[31], 1.0);
+        //Four steps is 6 dB, half the amplitude
+        assert!((VOLUME_TABLE[27] - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn tone_period() {
+        let mut audio = Sunsoft5b::new();
+        //Tone A only, period 2, full volume
+        audio.write(0x00, 0x02);
+        audio.write(0x07, 0x3E);
+        audio.write(0x08, 0x0F);
+
+        let mut levels = vec![];
+        for _ in 0..4 {
+            for _ in 0..PRESCALER * 2 {
+                audio.clock();
+            }
+            levels.push(audio.output());
+        }
+        assert_eq!(levels, [LEVEL_SCALE, 0.0, LEVEL_SCALE, 0.0]);
+    }
+
+    #[test]
+    fn envelope_shapes() {
+        let mut envelope = Envelope::new();
+        envelope.period = 1;
+
+        //Shape 0: decay then silence
+        envelope.restart(0x00);
+        assert_eq!(envelope.level(), 31);
+        for _ in 0..40 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.level(), 0);
+
+        //Shape 14: triangle
+        envelope.restart(0x0E);
+        for _ in 0..31 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.level(), 31);
+        envelope.clock();
+        assert_eq!(envelope.level(), 31);
+        envelope.clock();
+        assert_eq!(envelope.level(), 30);
+
+        //Shape 11: decay then hold high
+        envelope.restart(0x0B);
+        for _ in 0..40 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.level(), 31);
+    }
+
+    #[test]
+    fn noise_lfsr_runs() {
+        let mut audio = Sunsoft5b::new();
+        audio.write(0x06, 0x01);
+        let mut outputs = std::collections::HashSet::new();
+        for _ in 0..PRESCALER as usize * 2 * 64 {
+            audio.clock();
+            outputs.insert(audio.noise & 0x01);
+        }
+        assert_eq!(outputs.len(), 2);
+    }
+}
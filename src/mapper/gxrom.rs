@@ -0,0 +1,77 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write, bus_conflicts};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 32 * 1024;
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+//Mappers 66 (GxROM) and 140 (Jaleco JF-11/JF-14). Bits 4-5 pick the 32K PRG bank, bits
+//0-3 the 8K CHR bank. GxROM latches writes to $8000-$FFFF, the Jaleco boards latch
+//$6000-$7FFF so they have no bus conflicts
+pub struct Gxrom {
+    mirroring: Nametable,
+    jaleco: bool,
+    bus_conflicts: bool,
+    bank: u8,
+}
+impl Gxrom {
+    pub fn new(mapper: u16, mirroring: Nametable, submapper: u8) -> Self {
+        Self {
+            mirroring,
+            jaleco: mapper == 140,
+            bus_conflicts: mapper == 66 && bus_conflicts(submapper),
+            bank: 0,
+        }
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        let bank = match self.jaleco {
+            true => self.bank & 0x0F,
+            false => self.bank & 0x03,
+        };
+        bank as usize * CHR_BANK_SIZE + address as usize
+    }
+}
+impl Mapper for Gxrom {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (self.bank as usize >> 4) & 0x03;
+                prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x7FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.jaleco => self.bank = value,
+            0x8000..=0xFFFF if !self.jaleco => {
+                self.bank = match self.bus_conflicts {
+                    true => value & self.cpu_read(prg_rom, &[], address),
+                    false => value,
+                };
+            }
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[bank] => {
+                self.bank = bank;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
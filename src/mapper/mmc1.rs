@@ -0,0 +1,403 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 16 * 1024;
+const CHR_BANK_SIZE: usize = 4 * 1024;
+const PRG_RAM_BANK_SIZE: usize = 8 * 1024;
+//Boards with 512 KiB of PRG use a CHR register bit to pick the 256 KiB half
+const PRG_OUTER_BANK_SIZE: usize = 256 * 1024;
+
+//Mapper 1. Registers are loaded serially, one bit per write, through a 5 bit shift register.
+//SUROM, SOROM and SXROM reuse the CHR bank bits for PRG ROM/RAM banking, so which variant
+//is in play is worked out from the PRG ROM and RAM sizes
+pub struct Mmc1 {
+    shift_register: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+
+    //The MMC1 ignores a write on the cycle straight after another, which games trip
+    //over with read-modify-write instructions
+    cycle: u64,
+    last_write_cycle: Option<u64>,
+    //Which CHR register is currently on the bus, needed for the variants in 4K mode
+    chr_a12: bool,
+}
+impl Mmc1 {
+    pub fn new() -> Self {
+        Self {
+            shift_register: 0x00,
+            shift_count: 0,
+            //Power on in fix-last PRG mode so the reset vector is always reachable
+            control: 0x0C,
+            chr_bank_0: 0x00,
+            chr_bank_1: 0x00,
+            prg_bank: 0x00,
+            cycle: 0,
+            last_write_cycle: None,
+            chr_a12: false,
+        }
+    }
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+    //CHR register driving the upper PRG lines. In 8K mode it is always bank 0
+    fn active_chr_bank(&self) -> u8 {
+        if self.control & 0x10 != 0 && self.chr_a12 {
+            self.chr_bank_1
+        } else {
+            self.chr_bank_0
+        }
+    }
+    fn prg_rom_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let outer = if prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            ((self.active_chr_bank() >> 4) & 0x01) as usize * PRG_OUTER_BANK_SIZE
+        } else {
+            0
+        };
+        let banks_in_outer = (prg_rom.len().min(PRG_OUTER_BANK_SIZE) / PRG_BANK_SIZE).max(1);
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let offset = address as usize & 0x3FFF;
+
+        let bank = match (self.control >> 2) & 0x03 {
+            //32K mode ignores the low bank bit
+            0 | 1 => (bank & !0x01) + ((address as usize - 0x8000) / PRG_BANK_SIZE),
+            //First bank fixed at $8000
+            2 => match address {
+                0x8000..=0xBFFF => 0,
+                _ => bank,
+            },
+            //Last bank fixed at $C000
+            _ => match address {
+                0x8000..=0xBFFF => bank,
+                _ => banks_in_outer - 1,
+            },
+        };
+        (outer + (bank % banks_in_outer) * PRG_BANK_SIZE + offset) % prg_rom.len()
+    }
+    fn prg_ram_address(&self, prg_ram: &[u8], address: u16) -> usize {
+        //SOROM has 16K selected by bit 3, SXROM 32K selected by bits 2-3
+        let bank = match prg_ram.len() {
+            0x4000 => ((self.active_chr_bank() >> 3) & 0x01) as usize,
+            0x8000 => ((self.active_chr_bank() >> 2) & 0x03) as usize,
+            _ => 0,
+        };
+        (bank * PRG_RAM_BANK_SIZE + (address as usize - 0x6000)) % prg_ram.len()
+    }
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        let offset = address as usize & 0x0FFF;
+        let bank = if self.control & 0x10 != 0 {
+            match address {
+                0x0000..=0x0FFF => self.chr_bank_0 as usize,
+                _ => self.chr_bank_1 as usize,
+            }
+        } else {
+            //8K mode ignores the low bit of bank 0
+            (self.chr_bank_0 & 0x1E) as usize + (address as usize >> 12)
+        };
+        bank * CHR_BANK_SIZE + offset
+    }
+}
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            //Disabled or missing PRG RAM reads as open bus
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !prg_ram.is_empty() => {
+                prg_ram[self.prg_ram_address(prg_ram, address)]
+            }
+            0x8000..=0xFFFF => prg_rom[self.prg_rom_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !prg_ram.is_empty() => {
+                let translated_address = self.prg_ram_address(prg_ram, address);
+                prg_ram[translated_address] = value;
+            }
+            0x8000..=0xFFFF => {
+                let consecutive = self.last_write_cycle.is_some_and(|last| self.cycle <= last + 1);
+                self.last_write_cycle = Some(self.cycle);
+                if consecutive {
+                    return;
+                }
+
+                //Bit 7 resets the shift register and locks PRG into fix-last mode
+                if value & 0x80 != 0 {
+                    self.shift_register = 0x00;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+
+                self.shift_register |= (value & 0x01) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.write_register(address, self.shift_register);
+                    self.shift_register = 0x00;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        if address < 0x2000 {
+            self.chr_a12 = address & 0x1000 != 0;
+        }
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring(), address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        if address < 0x2000 {
+            self.chr_a12 = address & 0x1000 != 0;
+        }
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        match self.control & 0x03 {
+            0 => Nametable::SingleScreenLower,
+            1 => Nametable::SingleScreenUpper,
+            2 => Nametable::Vertical,
+            _ => Nametable::Horizontal,
+        }
+    }
+    fn ppu_address(&mut self, address: u16, _ppu_cycle: u64) {
+        if address < 0x2000 {
+            self.chr_a12 = address & 0x1000 != 0;
+        }
+    }
+    fn cpu_clock(&mut self) {
+        self.cycle += 1;
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![
+            self.shift_register,
+            self.shift_count,
+            self.control,
+            self.chr_bank_0,
+            self.chr_bank_1,
+            self.prg_bank,
+        ]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[shift_register, shift_count, control, chr_bank_0, chr_bank_1, prg_bank] => {
+                self.shift_register = shift_register;
+                self.shift_count = shift_count;
+                self.control = control;
+                self.chr_bank_0 = chr_bank_0;
+                self.chr_bank_1 = chr_bank_1;
+                self.prg_bank = prg_bank;
+                self.last_write_cycle = None;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    //Serially load a register the way games do, one bit per write on separate cycles
+    fn write_serial(mapper: &mut Mmc1, prg_ram: &mut [u8], address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(&[], prg_ram, address, (value >> bit) & 0x01);
+            mapper.cpu_clock();
+            mapper.cpu_clock();
+        }
+    }
+
+    #[test]
+    fn power_on_fixes_last_bank() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 0);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 7);
+    }
+
+    #[test]
+    fn switch_bank_at_8000() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        write_serial(&mut mapper, &mut [], 0xE000, 0x03);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 3);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xFFFF), 7);
+    }
+
+    #[test]
+    fn fix_first_mode() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        write_serial(&mut mapper, &mut [], 0x8000, 0x08);
+        write_serial(&mut mapper, &mut [], 0xE000, 0x05);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 0);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 5);
+    }
+
+    #[test]
+    fn prg_32k_mode_ignores_low_bit() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        write_serial(&mut mapper, &mut [], 0x8000, 0x00);
+        write_serial(&mut mapper, &mut [], 0xE000, 0x05);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 4);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 5);
+    }
+
+    #[test]
+    fn reset_bit_clears_shift_and_fixes_last() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        write_serial(&mut mapper, &mut [], 0x8000, 0x00);
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x01);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        mapper.cpu_write(&[], &mut [], 0x8000, 0x80);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+
+        assert_eq!(mapper.shift_count, 0);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 7);
+    }
+
+    #[test]
+    fn consecutive_writes_ignored() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        //Read-modify-write: dummy write then the real one on the next cycle
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x01);
+        mapper.cpu_clock();
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x00);
+        assert_eq!(mapper.shift_count, 1);
+
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        write_serial(&mut mapper, &mut [], 0xE000, 0x00);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 1);
+    }
+
+    #[test]
+    fn mirroring_control() {
+        let mut mapper = Mmc1::new();
+
+        for (value, mirroring) in [
+            (0x0C, Nametable::SingleScreenLower),
+            (0x0D, Nametable::SingleScreenUpper),
+            (0x0E, Nametable::Vertical),
+            (0x0F, Nametable::Horizontal),
+        ] {
+            write_serial(&mut mapper, &mut [], 0x8000, value);
+            assert_eq!(mapper.mirroring(), mirroring);
+        }
+    }
+
+    #[test]
+    fn chr_4k_and_8k_banking() {
+        let mut mapper = Mmc1::new();
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 32);
+        let vram = [0x00; 0x1000];
+
+        //8K mode: bank 0 register picks an even/odd pair
+        write_serial(&mut mapper, &mut [], 0xA000, 0x05);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 4);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 5);
+
+        //4K mode
+        write_serial(&mut mapper, &mut [], 0x8000, 0x1C);
+        write_serial(&mut mapper, &mut [], 0xC000, 0x09);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 5);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 9);
+    }
+
+    #[test]
+    fn chr_ram_write() {
+        let mut mapper = Mmc1::new();
+        let mut chr_ram = vec![0x00; 8 * 1024];
+        let mut vram = [0x00; 0x1000];
+
+        mapper.ppu_write(&[], &mut chr_ram, &mut vram, 0x1005, 0xAA);
+        assert_eq!(chr_ram[0x1005], 0xAA);
+    }
+
+    #[test]
+    fn prg_ram_disable() {
+        let mut mapper = Mmc1::new();
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x42);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6000), 0x42);
+
+        write_serial(&mut mapper, &mut prg_ram, 0xE000, 0x10);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6000), 0x00);
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x99);
+        assert_eq!(prg_ram[0], 0x42);
+    }
+
+    #[test]
+    fn surom_outer_prg_bank() {
+        let mut mapper = Mmc1::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 32);
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 15);
+        write_serial(&mut mapper, &mut [], 0xA000, 0x10);
+        write_serial(&mut mapper, &mut [], 0xE000, 0x02);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 18);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 31);
+    }
+
+    #[test]
+    fn sorom_prg_ram_bank() {
+        let mut mapper = Mmc1::new();
+        let mut prg_ram = vec![0x00; 16 * 1024];
+
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x11);
+        write_serial(&mut mapper, &mut prg_ram, 0xA000, 0x08);
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x22);
+
+        assert_eq!(prg_ram[0x0000], 0x11);
+        assert_eq!(prg_ram[0x2000], 0x22);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6000), 0x22);
+    }
+
+    #[test]
+    fn sxrom_prg_ram_bank() {
+        let mut mapper = Mmc1::new();
+        let mut prg_ram = vec![0x00; 32 * 1024];
+
+        write_serial(&mut mapper, &mut prg_ram, 0xA000, 0x0C);
+        mapper.cpu_write(&[], &mut prg_ram, 0x6001, 0x33);
+        assert_eq!(prg_ram[0x6001], 0x33);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut mapper = Mmc1::new();
+        write_serial(&mut mapper, &mut [], 0xE000, 0x03);
+        let state = mapper.save_state();
+
+        let mut restored = Mmc1::new();
+        assert_eq!(restored.load_state(&state), Ok(()));
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+        assert_eq!(restored.cpu_read(&prg_rom, &[], 0x8000), 3);
+        assert_eq!(restored.load_state(&[0x00]), Err(MapperError::InvalidState));
+    }
+}
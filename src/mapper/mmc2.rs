@@ -0,0 +1,180 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 4 * 1024;
+const STATE_SIZE: usize = 8;
+
+//Mappers 9 (MMC2, Punch-Out!!) and 10 (MMC4, Fire Emblem). Each 4K pattern table has two
+//CHR banks, one for latch $FD and one for latch $FE, and the latch flips when the PPU
+//fetches tile $FD or $FE from that table. The MMC2 switches one 8K PRG bank and fixes the
+//last three, the MMC4 switches 16K and fixes the last 16K
+pub struct Mmc2 {
+    mmc4: bool,
+    prg_bank: u8,
+    //[table 0 $FD, table 0 $FE, table 1 $FD, table 1 $FE]
+    chr_banks: [u8; 4],
+    latches: [u8; 2],
+    mirroring: u8,
+}
+impl Mmc2 {
+    pub fn new(mapper: u16) -> Self {
+        Self {
+            mmc4: mapper == 10,
+            prg_bank: 0,
+            chr_banks: [0; 4],
+            latches: [0xFE; 2],
+            mirroring: 0,
+        }
+    }
+
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let slot = (address as usize - 0x8000) >> 13;
+        let bank = match (self.mmc4, slot) {
+            (false, 0) => self.prg_bank as usize & 0x0F,
+            (true, 0 | 1) => (self.prg_bank as usize & 0x0F) * 2 + slot,
+            //Fixed to the end of the ROM
+            (_, slot) => banks.max(4) - 4 + slot,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        let table = address as usize >> 12;
+        let bank = self.chr_banks[table * 2 + (self.latches[table] == 0xFE) as usize];
+        bank as usize * CHR_BANK_SIZE + (address as usize & 0x0FFF)
+    }
+    //The latch changes after the fetch, so the trigger tile itself still uses the old bank.
+    //The MMC2 only watches the first row of tile $FD/$FE in table 0, everything else
+    //watches the whole tile
+    fn update_latch(&mut self, address: u16) {
+        let (table, tile) = match address {
+            0x0FD8 | 0x0FE8 => (0, address >> 4),
+            0x0FD8..=0x0FDF | 0x0FE8..=0x0FEF if self.mmc4 => (0, address >> 4),
+            0x1FD8..=0x1FDF | 0x1FE8..=0x1FEF => (1, address >> 4),
+            _ => return,
+        };
+        self.latches[table] = tile as u8;
+    }
+}
+impl Mapper for Mmc2 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !prg_ram.is_empty() => prg_ram[(address as usize - 0x6000) % prg_ram.len()],
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if !prg_ram.is_empty() => {
+                let prg_ram_size = prg_ram.len();
+                prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+            }
+            0xA000..=0xAFFF => self.prg_bank = value,
+            0xB000..=0xEFFF => self.chr_banks[(address as usize - 0xB000) >> 12] = value & 0x1F,
+            0xF000..=0xFFFF => self.mirroring = value & 0x01,
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        //The latch flips after the fetch, the tile that triggers it still comes from the old bank
+        let mirroring = self.mirroring();
+        let value = board_ppu_read(chr_rom, chr_ram, vram, mirroring, address, |address| self.chr_address(address));
+        self.update_latch(address);
+        value
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        match self.mirroring {
+            0 => Nametable::Vertical,
+            _ => Nametable::Horizontal,
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.prg_bank];
+        state.extend_from_slice(&self.chr_banks);
+        state.extend_from_slice(&self.latches);
+        state.push(self.mirroring);
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.prg_bank = state[0];
+        self.chr_banks.copy_from_slice(&state[1..5]);
+        self.latches.copy_from_slice(&state[5..7]);
+        self.mirroring = state[7];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    fn setup(mapper: u16) -> Mmc2 {
+        let mut mapper = Mmc2::new(mapper);
+        for (address, bank) in [(0xB000, 1), (0xC000, 2), (0xD000, 3), (0xE000, 4)] {
+            mapper.cpu_write(&[], &mut [], address, bank);
+        }
+        mapper
+    }
+
+    #[test]
+    fn latch_switches_after_the_trigger_fetch() {
+        let mut mapper = setup(9);
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 8);
+        let vram = [0x00; 0x1000];
+
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 2);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0FD8), 2);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 1);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0FE8), 1);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 2);
+
+        //The second table is independent and triggers anywhere in the tile
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 4);
+        mapper.ppu_read(&chr_rom, &[], &vram, 0x1FDD);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 3);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 2);
+    }
+
+    #[test]
+    fn mmc2_only_triggers_on_the_first_row_in_table_0() {
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 8);
+        let vram = [0x00; 0x1000];
+
+        let mut mmc2 = setup(9);
+        mmc2.ppu_read(&chr_rom, &[], &vram, 0x0FDA);
+        assert_eq!(mmc2.ppu_read(&chr_rom, &[], &vram, 0x0000), 2);
+
+        let mut mmc4 = setup(10);
+        mmc4.ppu_read(&chr_rom, &[], &vram, 0x0FDA);
+        assert_eq!(mmc4.ppu_read(&chr_rom, &[], &vram, 0x0000), 1);
+    }
+
+    #[test]
+    fn mirroring_control() {
+        let mut mapper = Mmc2::new(9);
+        assert_eq!(mapper.mirroring(), Nametable::Vertical);
+        mapper.cpu_write(&[], &mut [], 0xF000, 0x01);
+        assert_eq!(mapper.mirroring(), Nametable::Horizontal);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut mapper = setup(10);
+        mapper.cpu_write(&[], &mut [], 0xA000, 0x03);
+        mapper.ppu_read(&[0x00; 0x2000], &[], &[0x00; 0x1000], 0x1FD8);
+        let state = mapper.save_state();
+
+        let mut restored = Mmc2::new(10);
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+    }
+}
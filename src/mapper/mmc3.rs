@@ -0,0 +1,403 @@
+use crate::mapper::{A12Watcher, Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+
+//The IRQ counter differs between chip revisions. Rev A (MMC3A, NES 2.0 submapper 4) only
+//fires when the counter reaches 0 by decrementing or by a $C001 reload, Rev B (MMC3B/C)
+//fires every time the counter is 0 after a clock, including a natural reload of a 0 latch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mmc3Revision {
+    A,
+    B,
+}
+
+//Mapper 4. Eight bank registers, 8K PRG and 1K/2K CHR banks, and a scanline counter
+//clocked by rising edges of PPU A12
+pub struct Mmc3 {
+    revision: Mmc3Revision,
+    four_screen: bool,
+
+    bank_select: u8,
+    registers: [u8; 8],
+    mirroring: Nametable,
+    prg_ram_control: u8,
+
+    a12: A12Watcher,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+impl Mmc3 {
+    pub fn new(mirroring: Nametable, submapper: u8) -> Self {
+        Self {
+            revision: match submapper {
+                4 => Mmc3Revision::A,
+                _ => Mmc3Revision::B,
+            },
+            four_screen: mirroring == Nametable::FourScreen,
+            bank_select: 0x00,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring,
+            prg_ram_control: 0x80,
+            a12: A12Watcher::new(),
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let second_last = banks.saturating_sub(2);
+        let swapped = self.bank_select & 0x40 != 0;
+
+        let bank = match (address, swapped) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.registers[6] as usize & 0x3F,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.registers[7] as usize & 0x3F,
+            _ => banks - 1,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        //CHR inversion swaps the 2K and 1K halves
+        let address = match self.bank_select & 0x80 {
+            0 => address,
+            _ => address ^ 0x1000,
+        };
+        let bank = match address {
+            0x0000..=0x07FF => (self.registers[0] & 0xFE) as usize + ((address as usize >> 10) & 0x01),
+            0x0800..=0x0FFF => (self.registers[1] & 0xFE) as usize + ((address as usize >> 10) & 0x01),
+            _ => self.registers[2 + ((address as usize - 0x1000) >> 10)] as usize,
+        };
+        bank * CHR_BANK_SIZE + (address as usize & 0x03FF)
+    }
+    fn prg_ram_readable(&self) -> bool {
+        self.prg_ram_control & 0x80 != 0
+    }
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_control & 0xC0 == 0x80
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let previous = self.irq_counter;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        let fire = match self.revision {
+            Mmc3Revision::A => self.irq_counter == 0 && (previous > 0 || self.irq_reload),
+            Mmc3Revision::B => self.irq_counter == 0,
+        };
+        if fire && self.irq_enabled {
+            self.irq_pending = true;
+        }
+        self.irq_reload = false;
+    }
+}
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_readable() && !prg_ram.is_empty() => {
+                prg_ram[(address as usize - 0x6000) % prg_ram.len()]
+            }
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        let even = address & 0x01 == 0;
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_writable() && !prg_ram.is_empty() => {
+                let prg_ram_size = prg_ram.len();
+                prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+            }
+            0x8000..=0x9FFF if even => self.bank_select = value,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0x07) as usize] = value,
+            //Four screen boards ignore the mirroring register
+            0xA000..=0xBFFF if even && self.four_screen => {}
+            0xA000..=0xBFFF if even => {
+                self.mirroring = match value & 0x01 {
+                    0 => Nametable::Vertical,
+                    _ => Nametable::Horizontal,
+                };
+            }
+            0xA000..=0xBFFF => self.prg_ram_control = value,
+            0xC000..=0xDFFF if even => self.irq_latch = value,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+    fn ppu_address(&mut self, address: u16, ppu_cycle: u64) {
+        if self.a12.observe(address, ppu_cycle) {
+            self.clock_irq_counter();
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.bank_select];
+        state.extend_from_slice(&self.registers);
+        state.extend_from_slice(&[
+            (self.mirroring == Nametable::Horizontal) as u8,
+            self.prg_ram_control,
+            self.irq_latch,
+            self.irq_counter,
+            self.irq_reload as u8,
+            self.irq_enabled as u8,
+            self.irq_pending as u8,
+        ]);
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != 16 {
+            return Err(MapperError::InvalidState);
+        }
+        self.bank_select = state[0];
+        self.registers.copy_from_slice(&state[1..9]);
+        if !self.four_screen {
+            self.mirroring = match state[9] {
+                0 => Nametable::Vertical,
+                _ => Nametable::Horizontal,
+            };
+        }
+        self.prg_ram_control = state[10];
+        self.irq_latch = state[11];
+        self.irq_counter = state[12];
+        self.irq_reload = state[13] != 0;
+        self.irq_enabled = state[14] != 0;
+        self.irq_pending = state[15] != 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    fn set_register(mapper: &mut Mmc3, register: u8, value: u8) {
+        let mode = mapper.bank_select & 0xC0;
+        mapper.cpu_write(&[], &mut [], 0x8000, mode | register);
+        mapper.cpu_write(&[], &mut [], 0x8001, value);
+    }
+
+    //One scanline's worth of A12: low for the background, high for the sprite fetches
+    fn scanline(mapper: &mut Mmc3, line: u64) {
+        mapper.ppu_address(0x0000, line * 341);
+        mapper.ppu_address(0x1000, line * 341 + 260);
+    }
+
+    fn setup_irq(mapper: &mut Mmc3, latch: u8) {
+        mapper.cpu_write(&[], &mut [], 0xC000, latch);
+        mapper.cpu_write(&[], &mut [], 0xC001, 0x00);
+        mapper.cpu_write(&[], &mut [], 0xE001, 0x00);
+    }
+
+    #[test]
+    fn prg_banking_modes() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 16);
+
+        set_register(&mut mapper, 6, 3);
+        set_register(&mut mapper, 7, 5);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 3);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 5);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 14);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 15);
+
+        //PRG mode 1 swaps $8000 and $C000
+        mapper.cpu_write(&[], &mut [], 0x8000, 0x40);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 14);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 5);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 3);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 15);
+    }
+
+    #[test]
+    fn chr_banking_and_inversion() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 64);
+        let vram = [0x00; 0x1000];
+
+        set_register(&mut mapper, 0, 9);
+        set_register(&mut mapper, 1, 20);
+        set_register(&mut mapper, 2, 30);
+        set_register(&mut mapper, 5, 33);
+
+        //2K banks ignore the low bit
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 8);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0400), 9);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0800), 20);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 30);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1C00), 33);
+
+        mapper.cpu_write(&[], &mut [], 0x8000, 0x80);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 30);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0C00), 33);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 8);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1800), 20);
+    }
+
+    #[test]
+    fn mirroring_control() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        mapper.cpu_write(&[], &mut [], 0xA000, 0x01);
+        assert_eq!(mapper.mirroring(), Nametable::Horizontal);
+        mapper.cpu_write(&[], &mut [], 0xA000, 0x00);
+        assert_eq!(mapper.mirroring(), Nametable::Vertical);
+
+        let mut mapper = Mmc3::new(Nametable::FourScreen, 0);
+        mapper.cpu_write(&[], &mut [], 0xA000, 0x01);
+        assert_eq!(mapper.mirroring(), Nametable::FourScreen);
+    }
+
+    #[test]
+    fn prg_ram_protect() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x11);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6000), 0x11);
+
+        //Write protected
+        mapper.cpu_write(&[], &mut prg_ram, 0xA001, 0xC0);
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x22);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6000), 0x11);
+
+        //Chip disabled
+        mapper.cpu_write(&[], &mut prg_ram, 0xA001, 0x00);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6000), 0x00);
+    }
+
+    #[test]
+    fn irq_after_latch_plus_one_scanlines() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        setup_irq(&mut mapper, 3);
+
+        //First clock reloads to 3, then 2, 1, 0
+        for line in 0..3 {
+            scanline(&mut mapper, line);
+            assert!(!mapper.irq());
+        }
+        scanline(&mut mapper, 3);
+        assert!(mapper.irq());
+
+        //$E000 acknowledges and disables
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x00);
+        assert!(!mapper.irq());
+        for line in 4..12 {
+            scanline(&mut mapper, line);
+        }
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn irq_counter_reloads_and_repeats() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        setup_irq(&mut mapper, 1);
+
+        scanline(&mut mapper, 0);
+        scanline(&mut mapper, 1);
+        assert!(mapper.irq());
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x00);
+        mapper.cpu_write(&[], &mut [], 0xE001, 0x00);
+
+        //Counter hit 0, next clock reloads to 1 then fires again after that
+        scanline(&mut mapper, 2);
+        assert!(!mapper.irq());
+        scanline(&mut mapper, 3);
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn a12_dips_between_sprites_do_not_clock() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        setup_irq(&mut mapper, 1);
+
+        //Eight sprite fetches with short nametable dips between them
+        for slot in 0..8 {
+            mapper.ppu_address(0x2000, 257 + slot * 8);
+            mapper.ppu_address(0x1000, 261 + slot * 8);
+        }
+        assert_eq!(mapper.irq_counter, 1);
+    }
+
+    #[test]
+    fn rev_b_fires_on_zero_latch_every_clock() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        setup_irq(&mut mapper, 0);
+
+        scanline(&mut mapper, 0);
+        assert!(mapper.irq());
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x00);
+        mapper.cpu_write(&[], &mut [], 0xE001, 0x00);
+        scanline(&mut mapper, 1);
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn rev_a_only_fires_on_decrement_or_reload() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 4);
+        assert_eq!(mapper.revision, Mmc3Revision::A);
+        setup_irq(&mut mapper, 0);
+
+        //$C001 reload to 0 fires once
+        scanline(&mut mapper, 0);
+        assert!(mapper.irq());
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x00);
+        mapper.cpu_write(&[], &mut [], 0xE001, 0x00);
+
+        //Natural reloads of a 0 latch stay quiet
+        scanline(&mut mapper, 1);
+        scanline(&mut mapper, 2);
+        assert!(!mapper.irq());
+
+        mapper.revision = Mmc3Revision::B;
+        scanline(&mut mapper, 3);
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut mapper = Mmc3::new(Nametable::Vertical, 0);
+        set_register(&mut mapper, 6, 3);
+        mapper.cpu_write(&[], &mut [], 0xA000, 0x01);
+        setup_irq(&mut mapper, 5);
+        scanline(&mut mapper, 0);
+        let state = mapper.save_state();
+
+        let mut restored = Mmc3::new(Nametable::Vertical, 0);
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.mirroring(), Nametable::Horizontal);
+        assert_eq!(restored.irq_counter, 5);
+        assert_eq!(restored.load_state(&state[1..]), Err(MapperError::InvalidState));
+    }
+}
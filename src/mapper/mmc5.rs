@@ -0,0 +1,755 @@
+mod audio;
+
+use crate::mapper::mmc5::audio::Mmc5Audio;
+use crate::mapper::{Mapper, MapperError, chr_read, chr_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const EXRAM_SIZE: usize = 1024;
+const STATE_SIZE: usize = 50 + audio::STATE_SIZE + EXRAM_SIZE;
+
+//CPU cycles without a PPU read before the MMC5 decides rendering has stopped
+const IDLE_CYCLES: u8 = 3;
+
+//Mapper 5 (ExROM). PRG in 8K-32K banks that can point at ROM or RAM, CHR in 1K-8K banks
+//with a separate set for 8x16 sprites, 1 KiB of ExRAM usable as an extra nametable, for
+//per tile attributes or as plain RAM, fill mode, a vertical split, a scanline IRQ, an
+//8x8 multiplier and two pulses plus PCM of expansion audio
+//
+//The MMC5 has no view of the PPU's dot counter, so like the real chip it finds scanlines
+//from the fetch pattern: three reads of the same nametable address in a row only happen
+//at the end of a line (dots 337 and 339) and the first fetch of the next one. Counting
+//nametable fetches after that tells background fetches from sprite ones
+pub struct Mmc5 {
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    //$5113-$5117. $5113 always selects RAM for $6000-$7FFF, bit 7 of the others picks ROM
+    prg_banks: [u8; 5],
+    //$5120-$5127 and $5128-$512B with the two upper bits from $5130 already applied
+    chr_banks_a: [u16; 8],
+    chr_banks_b: [u16; 4],
+    chr_upper: u8,
+    //Outside 8x16 sprite rendering, whichever set was written last is used for everything
+    last_chr_set_b: bool,
+    sprite_8x16: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_page: u8,
+
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    exram: [u8; EXRAM_SIZE],
+    audio: Mmc5Audio,
+
+    //Scanline detection
+    last_address: u16,
+    matches: u8,
+    nametable_fetches: u8,
+    idle_cycles: u8,
+    //Screen column of the background tile being fetched, None during sprite fetches
+    fetch_tile: Option<u8>,
+    //Split screen row for the current tile if it falls in the split region
+    split_row: Option<u16>,
+    //ExRAM byte for the current tile in extended attribute mode
+    extended_attribute: u8,
+}
+impl Mmc5 {
+    pub fn new() -> Self {
+        Self {
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0x00, 0xFF, 0xFF, 0xFF, 0xFF],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            sprite_8x16: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_page: 0,
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            exram: [0; EXRAM_SIZE],
+            audio: Mmc5Audio::new(),
+            last_address: 0,
+            matches: 0,
+            nametable_fetches: 0,
+            idle_cycles: 0,
+            fetch_tile: None,
+            split_row: None,
+            extended_attribute: 0,
+        }
+    }
+
+    //ROM or RAM and the 8K bank behind a CPU address in $8000-$FFFF
+    fn prg_bank(&self, address: u16) -> (bool, usize) {
+        let slot = (address as usize - 0x8000) >> 13;
+        //Register ($5113 + n) and window size in 8K banks
+        let (register, size) = match (self.prg_mode, slot) {
+            (0, _) => (4, 4),
+            (1, 0..=1) | (2, 0..=1) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 2) => (3, 1),
+            (2, _) => (4, 1),
+            (_, slot) => (slot + 1, 1),
+        };
+        let value = self.prg_banks[register];
+        let rom = register == 4 || value & 0x80 != 0;
+        let bank = (value as usize & 0x7F & !(size - 1)) + slot % size;
+        (rom, bank)
+    }
+    fn prg_ram_address(prg_ram: &[u8], bank: usize, address: u16) -> usize {
+        ((bank & 0x0F) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_ram.len()
+    }
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    fn chr_address(&self, address: u16, set_b: bool) -> usize {
+        //Window size shrinks from 8K in mode 0 to 1K in mode 3. Each window uses the last
+        //register that falls inside it, set B repeats its four for both pattern tables
+        let size = 0x2000 >> self.chr_mode;
+        let register = (address as usize >> 10) | ((8 >> self.chr_mode) - 1);
+        let bank = match set_b {
+            true => self.chr_banks_b[register & 0x03],
+            false => self.chr_banks_a[register],
+        };
+        bank as usize * size + (address as usize & (size - 1))
+    }
+    fn chr_fetch_address(&self, address: u16) -> usize {
+        if let Some(row) = self.split_row {
+            //Split tiles come from the 4K page in $5202, with the split's own fine Y
+            return self.split_page as usize * 0x1000 + ((address as usize & 0x0FF8) | (row as usize & 0x07));
+        }
+        if self.fetch_tile.is_some() && self.exram_mode == 1 {
+            let bank = (self.extended_attribute as usize & 0x3F) | (self.chr_upper as usize) << 6;
+            return bank * 0x1000 + (address as usize & 0x0FFF);
+        }
+        //8x16 sprites use set A and the background set B, but only while rendering
+        let set_b = match self.in_frame && self.sprite_8x16 {
+            true => self.fetch_tile.is_some(),
+            false => self.last_chr_set_b,
+        };
+        self.chr_address(address, set_b)
+    }
+
+    fn nametable_read(&self, vram: &[u8], address: u16) -> u8 {
+        let offset = address as usize & 0x03FF;
+        let attribute = offset >= 0x03C0;
+
+        if let (Some(row), Some(tile)) = (self.split_row, self.fetch_tile) {
+            let column = tile as usize & 0x1F;
+            let row = row as usize;
+            return match attribute {
+                false => self.exram[(row / 8) * 32 + column],
+                true => {
+                    let byte = self.exram[0x03C0 + (row / 32) * 8 + column / 4];
+                    let shift = ((row / 16) & 0x01) * 4 + ((column / 2) & 0x01) * 2;
+                    ((byte >> shift) & 0x03) * 0x55
+                }
+            };
+        }
+        //Extended attributes give every tile its own palette, repeated in all four slots
+        if attribute && self.fetch_tile.is_some() && self.exram_mode == 1 {
+            return (self.extended_attribute >> 6) * 0x55;
+        }
+
+        match (self.nametable_mapping >> (((address >> 10) & 0x03) * 2)) & 0x03 {
+            0 => vram[offset],
+            1 => vram[0x0400 | offset],
+            2 if self.exram_mode < 2 => self.exram[offset],
+            2 => 0,
+            _ if attribute => self.fill_attribute * 0x55,
+            _ => self.fill_tile,
+        }
+    }
+
+    //Called on the third matching nametable read, at the start of every rendered line
+    fn new_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_target {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+        }
+        self.nametable_fetches = 0;
+    }
+    fn end_frame(&mut self) {
+        self.in_frame = false;
+        self.matches = 0;
+        self.fetch_tile = None;
+        self.split_row = None;
+    }
+
+    //Work out what the next few reads are for from a nametable fetch
+    fn nametable_fetch(&mut self, address: u16) {
+        self.nametable_fetches = self.nametable_fetches.saturating_add(1);
+        //32 background fetches (tiles 2-33), 16 garbage ones for sprites, then tiles 0-1 of
+        //the next line and the two unused fetches at the end
+        let (tile, next_line) = match self.nametable_fetches {
+            fetch @ 1..=32 => (Some(fetch + 1), false),
+            fetch @ 49..=50 => (Some(fetch - 49), true),
+            _ => (None, false),
+        };
+        self.fetch_tile = tile;
+        self.split_row = None;
+
+        if let Some(tile) = tile {
+            self.extended_attribute = self.exram[address as usize & 0x03FF];
+
+            let threshold = self.split_control & 0x1F;
+            let in_split = match self.split_control & 0x40 {
+                0 => tile < threshold,
+                _ => tile >= threshold,
+            };
+            if self.split_control & 0x80 != 0 && self.exram_mode < 2 && in_split {
+                let line = self.scanline as u16 + next_line as u16;
+                self.split_row = Some((self.split_scroll as u16 + line) % 240);
+            }
+        }
+    }
+}
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x5010 | 0x5015 => self.audio.cpu_read(address),
+            //Bit 7 is the pending flag, acknowledged by the read, bit 6 the in frame flag
+            0x5204 => {
+                let status = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                status
+            }
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            //Write only in the nametable modes
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[address as usize - 0x5C00],
+            0x6000..=0x7FFF if !prg_ram.is_empty() => {
+                prg_ram[Self::prg_ram_address(prg_ram, self.prg_banks[0] as usize, address)]
+            }
+            0x8000..=0xFFFF => {
+                let value = match self.prg_bank(address) {
+                    (true, bank) => prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()],
+                    (false, _) if prg_ram.is_empty() => 0,
+                    (false, bank) => prg_ram[Self::prg_ram_address(prg_ram, bank, address)],
+                };
+                self.audio.prg_read(address, value);
+                value
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x5000..=0x5015 => self.audio.cpu_write(address, value),
+            0x5100 => self.prg_mode = value & 0x03,
+            0x5101 => self.chr_mode = value & 0x03,
+            0x5102 => self.prg_ram_protect[0] = value & 0x03,
+            0x5103 => self.prg_ram_protect[1] = value & 0x03,
+            0x5104 => self.exram_mode = value & 0x03,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0x03,
+            0x5113..=0x5117 => self.prg_banks[address as usize - 0x5113] = value,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[address as usize - 0x5120] = value as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[address as usize - 0x5128] = value as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = true;
+            }
+            0x5130 => self.chr_upper = value & 0x03,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_page = value,
+            0x5203 => self.irq_target = value,
+            0x5204 => self.irq_enabled = value & 0x80 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            //In the nametable modes the CPU can only write while the PPU is rendering,
+            //anything else stores 0
+            0x5C00..=0x5FFF => match self.exram_mode {
+                0 | 1 if self.in_frame => self.exram[address as usize - 0x5C00] = value,
+                0 | 1 => self.exram[address as usize - 0x5C00] = 0,
+                2 => self.exram[address as usize - 0x5C00] = value,
+                _ => {}
+            },
+            0x6000..=0x7FFF if self.prg_ram_writable() && !prg_ram.is_empty() => {
+                let translated_address = Self::prg_ram_address(prg_ram, self.prg_banks[0] as usize, address);
+                prg_ram[translated_address] = value;
+            }
+            0x8000..=0xDFFF if self.prg_ram_writable() && !prg_ram.is_empty() => {
+                if let (false, bank) = self.prg_bank(address) {
+                    let translated_address = Self::prg_ram_address(prg_ram, bank, address);
+                    prg_ram[translated_address] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => chr_read(chr_rom, chr_ram, self.chr_fetch_address(address)),
+            0x2000..=0x3EFF => self.nametable_read(vram, address),
+            _ => 0,
+        }
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => chr_write(chr_rom, chr_ram, self.chr_address(address, self.last_chr_set_b), value),
+            0x2000..=0x3EFF => {
+                let offset = address as usize & 0x03FF;
+                match (self.nametable_mapping >> (((address >> 10) & 0x03) * 2)) & 0x03 {
+                    0 => vram[offset] = value,
+                    1 => vram[0x0400 | offset] = value,
+                    2 if self.exram_mode < 2 => self.exram[offset] = value,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    //$5105 can describe layouts the Nametable enum has no name for, those report as four
+    //screen since every quadrant is set independently
+    fn mirroring(&self) -> Nametable {
+        match self.nametable_mapping {
+            0x44 => Nametable::Vertical,
+            0x50 => Nametable::Horizontal,
+            0x00 => Nametable::SingleScreenLower,
+            0x55 => Nametable::SingleScreenUpper,
+            _ => Nametable::FourScreen,
+        }
+    }
+    fn irq(&self) -> bool {
+        (self.irq_enabled && self.irq_pending) || self.audio.irq()
+    }
+    fn ppu_address(&mut self, address: u16, _ppu_cycle: u64) {
+        self.idle_cycles = 0;
+
+        let nametable = (0x2000..=0x3EFF).contains(&address) && address & 0x03FF < 0x03C0;
+        if nametable && address == self.last_address {
+            self.matches += 1;
+            if self.matches == 2 {
+                self.new_scanline();
+            }
+        } else {
+            self.matches = 0;
+        }
+        self.last_address = address;
+
+        if nametable && self.in_frame {
+            self.nametable_fetch(address);
+        }
+    }
+    fn ppu_register_write(&mut self, address: u16, value: u8) {
+        match address & 0x2007 {
+            0x2000 => self.sprite_8x16 = value & 0x20 != 0,
+            //Turning rendering off stops the frame straight away
+            0x2001 if value & 0x18 == 0 => self.end_frame(),
+            _ => {}
+        }
+    }
+    fn cpu_clock(&mut self) {
+        self.audio.clock();
+        if self.idle_cycles < IDLE_CYCLES {
+            self.idle_cycles += 1;
+            if self.idle_cycles == IDLE_CYCLES {
+                self.end_frame();
+            }
+        }
+    }
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![
+            self.prg_mode,
+            self.chr_mode,
+            self.prg_ram_protect[0],
+            self.prg_ram_protect[1],
+            self.exram_mode,
+            self.nametable_mapping,
+            self.fill_tile,
+            self.fill_attribute,
+        ];
+        state.extend_from_slice(&self.prg_banks);
+        for bank in self.chr_banks_a.iter().chain(self.chr_banks_b.iter()) {
+            state.extend_from_slice(&bank.to_le_bytes());
+        }
+        state.extend_from_slice(&[
+            self.chr_upper,
+            self.last_chr_set_b as u8,
+            self.sprite_8x16 as u8,
+            self.split_control,
+            self.split_scroll,
+            self.split_page,
+            self.irq_target,
+            self.irq_enabled as u8,
+            self.irq_pending as u8,
+            self.in_frame as u8,
+            self.scanline,
+            self.multiplicand,
+            self.multiplier,
+        ]);
+        state.extend_from_slice(&self.audio.save_state());
+        state.extend_from_slice(&self.exram);
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.prg_mode = state[0];
+        self.chr_mode = state[1];
+        self.prg_ram_protect.copy_from_slice(&state[2..4]);
+        self.exram_mode = state[4];
+        self.nametable_mapping = state[5];
+        self.fill_tile = state[6];
+        self.fill_attribute = state[7];
+        self.prg_banks.copy_from_slice(&state[8..13]);
+        let mut banks = state[13..37].chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+        for bank in self.chr_banks_a.iter_mut().chain(self.chr_banks_b.iter_mut()) {
+            *bank = banks.next().unwrap_or(0);
+        }
+        self.chr_upper = state[37];
+        self.last_chr_set_b = state[38] != 0;
+        self.sprite_8x16 = state[39] != 0;
+        self.split_control = state[40];
+        self.split_scroll = state[41];
+        self.split_page = state[42];
+        self.irq_target = state[43];
+        self.irq_enabled = state[44] != 0;
+        self.irq_pending = state[45] != 0;
+        self.in_frame = state[46] != 0;
+        self.scanline = state[47];
+        self.multiplicand = state[48];
+        self.multiplier = state[49];
+        self.audio.load_state(&state[50..50 + audio::STATE_SIZE]);
+        self.exram.copy_from_slice(&state[50 + audio::STATE_SIZE..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    //The fetches the PPU makes for one rendered line, starting at dot 1 with the tile at
+    //nametable_base. Ends with the two unused fetches that set up the next detection
+    fn render_line(mapper: &mut Mmc5, chr_rom: &[u8], vram: &[u8], nametable_base: u16) -> Vec<u8> {
+        let mut pattern = vec![];
+        for tile in 0..34 {
+            let address = nametable_base + if tile < 32 { tile + 2 } else { tile - 32 };
+            if tile == 32 {
+                //Sprite fetches: two garbage nametable reads and two pattern reads each
+                for _ in 0..8 {
+                    mapper.ppu_address(nametable_base, 0);
+                    mapper.ppu_read(chr_rom, &[], vram, nametable_base);
+                    mapper.ppu_address(nametable_base, 0);
+                    mapper.ppu_read(chr_rom, &[], vram, nametable_base);
+                    mapper.ppu_address(0x1000, 0);
+                    pattern.push(mapper.ppu_read(chr_rom, &[], vram, 0x1000));
+                    mapper.ppu_address(0x1008, 0);
+                    mapper.ppu_read(chr_rom, &[], vram, 0x1008);
+                }
+            }
+            mapper.ppu_address(address, 0);
+            mapper.ppu_read(chr_rom, &[], vram, address);
+            mapper.ppu_address(0x23C0, 0);
+            mapper.ppu_read(chr_rom, &[], vram, 0x23C0);
+            mapper.ppu_address(0x0000, 0);
+            pattern.push(mapper.ppu_read(chr_rom, &[], vram, 0x0000));
+            mapper.ppu_address(0x0008, 0);
+            mapper.ppu_read(chr_rom, &[], vram, 0x0008);
+        }
+        for _ in 0..2 {
+            mapper.ppu_address(nametable_base + 2, 0);
+            mapper.ppu_read(chr_rom, &[], vram, nametable_base + 2);
+        }
+        pattern
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut mapper = Mmc5::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 32);
+
+        //Power on: mode 3 with $5117 = $FF
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 31);
+
+        mapper.cpu_write(&[], &mut [], 0x5100, 0x00);
+        mapper.cpu_write(&[], &mut [], 0x5117, 0x85);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 4);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 7);
+
+        mapper.cpu_write(&[], &mut [], 0x5100, 0x01);
+        mapper.cpu_write(&[], &mut [], 0x5115, 0x8B);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 10);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 11);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 4);
+
+        mapper.cpu_write(&[], &mut [], 0x5100, 0x02);
+        mapper.cpu_write(&[], &mut [], 0x5116, 0x93);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 11);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 19);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 5);
+
+        mapper.cpu_write(&[], &mut [], 0x5100, 0x03);
+        mapper.cpu_write(&[], &mut [], 0x5114, 0x81);
+        mapper.cpu_write(&[], &mut [], 0x5115, 0x82);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 1);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 2);
+    }
+
+    #[test]
+    fn prg_ram_banking_and_protect() {
+        let mut mapper = Mmc5::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+        let mut prg_ram = vec![0x00; 64 * 1024];
+
+        //Locked until $5102 = 2 and $5103 = 1
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x6000, 0x11);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x6000), 0x00);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x5102, 0x02);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x5103, 0x01);
+
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x5113, 0x03);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x6000, 0x11);
+        assert_eq!(prg_ram[3 * PRG_BANK_SIZE], 0x11);
+
+        //Bit 7 clear maps RAM into $8000-$DFFF, writable there too
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x5114, 0x03);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x8000), 0x11);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x8001, 0x22);
+        assert_eq!(prg_ram[3 * PRG_BANK_SIZE + 1], 0x22);
+    }
+
+    #[test]
+    fn chr_modes_and_sets() {
+        let mut mapper = Mmc5::new();
+        let chr_rom = banked_rom(1024, 256);
+        let vram = [0x00; 0x1000];
+
+        //1K mode, set A
+        mapper.cpu_write(&[], &mut [], 0x5101, 0x03);
+        for register in 0..8 {
+            mapper.cpu_write(&[], &mut [], 0x5120 + register, 10 + register as u8);
+        }
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 10);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1C00), 17);
+
+        //2K mode uses the odd registers
+        mapper.cpu_write(&[], &mut [], 0x5101, 0x02);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 22);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0C00), 27);
+
+        //Set B was written last, repeated over both pattern tables
+        mapper.cpu_write(&[], &mut [], 0x5101, 0x03);
+        for register in 0..4 {
+            mapper.cpu_write(&[], &mut [], 0x5128 + register, 40 + register as u8);
+        }
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0400), 41);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1C00), 43);
+
+        //Upper bits from $5130
+        mapper.cpu_write(&[], &mut [], 0x5130, 0x01);
+        mapper.cpu_write(&[], &mut [], 0x5120, 0x00);
+        assert_eq!(mapper.chr_address(0x0000, false), 256 * 1024);
+    }
+
+    #[test]
+    fn nametable_mapping_and_fill() {
+        let mut mapper = Mmc5::new();
+        let mut vram = [0x00; 0x1000];
+
+        //$2000 CIRAM 0, $2400 CIRAM 1, $2800 ExRAM, $2C00 fill
+        mapper.cpu_write(&[], &mut [], 0x5105, 0b11_10_01_00);
+        mapper.cpu_write(&[], &mut [], 0x5106, 0x42);
+        mapper.cpu_write(&[], &mut [], 0x5107, 0x02);
+        assert_eq!(mapper.mirroring(), Nametable::FourScreen);
+
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2005, 0x01);
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2405, 0x02);
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2805, 0x03);
+        assert_eq!(vram[0x0005], 0x01);
+        assert_eq!(vram[0x0405], 0x02);
+        assert_eq!(mapper.exram[0x0005], 0x03);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2805), 0x03);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2C05), 0x42);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2FC5), 0xAA);
+
+        mapper.cpu_write(&[], &mut [], 0x5105, 0x44);
+        assert_eq!(mapper.mirroring(), Nametable::Vertical);
+        mapper.cpu_write(&[], &mut [], 0x5105, 0x50);
+        assert_eq!(mapper.mirroring(), Nametable::Horizontal);
+    }
+
+    #[test]
+    fn exram_cpu_access_by_mode() {
+        let mut mapper = Mmc5::new();
+
+        //Nametable modes: write only, and only while rendering
+        mapper.cpu_write(&[], &mut [], 0x5C00, 0x12);
+        assert_eq!(mapper.exram[0], 0x00);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5C00), 0x00);
+        mapper.in_frame = true;
+        mapper.cpu_write(&[], &mut [], 0x5C00, 0x12);
+        assert_eq!(mapper.exram[0], 0x12);
+
+        //RAM mode
+        mapper.cpu_write(&[], &mut [], 0x5104, 0x02);
+        mapper.cpu_write(&[], &mut [], 0x5C01, 0x34);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5C01), 0x34);
+
+        //Read only mode
+        mapper.cpu_write(&[], &mut [], 0x5104, 0x03);
+        mapper.cpu_write(&[], &mut [], 0x5C01, 0x56);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5C01), 0x34);
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut mapper = Mmc5::new();
+        mapper.cpu_write(&[], &mut [], 0x5205, 200);
+        mapper.cpu_write(&[], &mut [], 0x5206, 150);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5205), (30000 & 0xFF) as u8);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5206), (30000 >> 8) as u8);
+    }
+
+    #[test]
+    fn scanline_detection_and_irq() {
+        let mut mapper = Mmc5::new();
+        let vram = [0x00; 0x1000];
+        mapper.cpu_write(&[], &mut [], 0x5203, 3);
+        mapper.cpu_write(&[], &mut [], 0x5204, 0x80);
+
+        //Pre-render line: its end is what the first detection sees
+        render_line(&mut mapper, &[0; 1024], &vram, 0x2000);
+        assert!(!mapper.in_frame);
+        for _ in 0..3 {
+            render_line(&mut mapper, &[0; 1024], &vram, 0x2000);
+            assert!(mapper.in_frame);
+            assert!(!mapper.irq());
+        }
+        render_line(&mut mapper, &[0; 1024], &vram, 0x2000);
+        assert!(mapper.irq());
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5204), 0xC0);
+        assert!(!mapper.irq());
+
+        //Vblank: no fetches for a few CPU cycles ends the frame
+        for _ in 0..IDLE_CYCLES {
+            mapper.cpu_clock();
+        }
+        assert!(!mapper.in_frame);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5204), 0x00);
+    }
+
+    #[test]
+    fn sprites_8x16_use_set_a() {
+        let mut mapper = Mmc5::new();
+        let chr_rom = banked_rom(1024, 64);
+        let vram = [0x00; 0x1000];
+
+        mapper.cpu_write(&[], &mut [], 0x5101, 0x03);
+        mapper.cpu_write(&[], &mut [], 0x5124, 5);
+        mapper.cpu_write(&[], &mut [], 0x5128, 9);
+        mapper.ppu_register_write(0x2000, 0x20);
+
+        render_line(&mut mapper, &chr_rom, &vram, 0x2000);
+        let pattern = render_line(&mut mapper, &chr_rom, &vram, 0x2000);
+        //Background from set B, sprites from set A
+        assert_eq!(pattern[0], 9);
+        assert_eq!(pattern[32], 5);
+        assert_eq!(pattern[40], 9);
+
+        //Not rendering: the last written set
+        mapper.ppu_register_write(0x2001, 0x00);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x1000), 9);
+    }
+
+    #[test]
+    fn extended_attributes() {
+        let mut mapper = Mmc5::new();
+        let chr_rom = banked_rom(1024, 64);
+        let vram = [0x00; 0x1000];
+
+        mapper.cpu_write(&[], &mut [], 0x5104, 0x01);
+        //Tile 2 of the first row: palette 3, 4K bank 5
+        mapper.exram[2] = 0xC5;
+
+        //The next line's first fetch is the one that detects it
+        render_line(&mut mapper, &chr_rom, &vram, 0x2000);
+        mapper.ppu_address(0x2002, 0);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x2002), 0x00);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x23C0), 0xFF);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0010), 20);
+    }
+
+    #[test]
+    fn vertical_split() {
+        let mut mapper = Mmc5::new();
+        let chr_rom = banked_rom(1024, 64);
+        let vram = [0x00; 0x1000];
+
+        //Left split of 4 tiles scrolled 8 lines down, CHR page 2
+        mapper.cpu_write(&[], &mut [], 0x5200, 0x84);
+        mapper.cpu_write(&[], &mut [], 0x5201, 8);
+        mapper.cpu_write(&[], &mut [], 0x5202, 2);
+        mapper.exram[32 + 2] = 0x77;
+        mapper.exram[32 + 4] = 0x88;
+
+        render_line(&mut mapper, &chr_rom, &vram, 0x2000);
+        //Tile 2 is inside the split, tile 4 is not
+        mapper.ppu_address(0x2002, 0);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x2002), 0x77);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 8);
+        mapper.ppu_address(0x2003, 0);
+        mapper.ppu_address(0x2004, 0);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x2004), 0x00);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0000), 0);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut mapper = Mmc5::new();
+        mapper.cpu_write(&[], &mut [], 0x5100, 0x01);
+        mapper.cpu_write(&[], &mut [], 0x5127, 0x33);
+        mapper.cpu_write(&[], &mut [], 0x5104, 0x02);
+        mapper.cpu_write(&[], &mut [], 0x5C10, 0x44);
+        mapper.cpu_write(&[], &mut [], 0x5011, 0x80);
+        let state = mapper.save_state();
+        assert_eq!(state.len(), STATE_SIZE);
+
+        let mut restored = Mmc5::new();
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.load_state(&state[1..]), Err(MapperError::InvalidState));
+    }
+}
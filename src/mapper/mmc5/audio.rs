@@ -0,0 +1,259 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::mixer;
+use crate::apu::pulse::DUTY_TABLE;
+
+//The MMC5 has its own frame sequencer fixed at about 240 Hz, clocking envelopes and length
+//counters together. It ignores $4017
+const FRAME_PERIOD: u32 = 7457;
+
+//Bytes written by Mmc5Audio::save_state
+pub const STATE_SIZE: usize = 0x18;
+
+//Same as an APU pulse minus the sweep unit, so low periods are never muted either
+struct Mmc5Pulse {
+    duty: u8,
+    sequence_step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+}
+impl Mmc5Pulse {
+    fn new() -> Self {
+        Mmc5Pulse {
+            duty: 0,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+    //Register offset 0-3. Offset 1 would be the sweep and does nothing
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length_counter.set_halt(value & 0x20 != 0);
+                self.envelope.write_control(value);
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.length_counter.load(value >> 3);
+                self.sequence_step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn clock_frame(&mut self) {
+        self.envelope.clock();
+        self.length_counter.clock();
+    }
+    fn output(&self) -> u8 {
+        if !self.length_counter.active() || DUTY_TABLE[self.duty as usize][self.sequence_step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+//Two pulse channels at $5000-$5007 and an 8 bit PCM channel at $5010/$5011, enabled
+//through $5015
+pub struct Mmc5Audio {
+    pulse_1: Mmc5Pulse,
+    pulse_2: Mmc5Pulse,
+    cycle: u64,
+    frame_divider: u32,
+
+    //PCM in read mode takes its level from CPU reads of $8000-$BFFF instead of $5011
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    pcm_level: u8,
+
+    //Last value written to each register, replayed when loading a save state
+    registers: [u8; 0x16],
+}
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Mmc5Audio {
+            pulse_1: Mmc5Pulse::new(),
+            pulse_2: Mmc5Pulse::new(),
+            cycle: 0,
+            frame_divider: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            pcm_level: 0,
+            registers: [0; 0x16],
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.pcm_irq_enabled && self.pcm_irq
+    }
+
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            //Reading acknowledges the PCM IRQ
+            0x5010 => {
+                let status = (self.irq() as u8) << 7 | self.pcm_read_mode as u8;
+                self.pcm_irq = false;
+                status
+            }
+            0x5015 => self.pulse_1.length_counter.active() as u8 | (self.pulse_2.length_counter.active() as u8) << 1,
+            _ => 0,
+        }
+    }
+
+    pub fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x5000..=0x5015 = address {
+            self.registers[address as usize - 0x5000] = value;
+        }
+        match address {
+            0x5000..=0x5003 => self.pulse_1.write(address - 0x5000, value),
+            0x5004..=0x5007 => self.pulse_2.write(address - 0x5004, value),
+            0x5010 => {
+                self.pcm_read_mode = value & 0x01 != 0;
+                self.pcm_irq_enabled = value & 0x80 != 0;
+            }
+            //Writing 0 is ignored, the level only changes on non zero values
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm_level = value,
+            0x5015 => {
+                self.pulse_1.length_counter.set_enabled(value & 0x01 != 0);
+                self.pulse_2.length_counter.set_enabled(value & 0x02 != 0);
+            }
+            _ => {}
+        }
+    }
+
+    //Snoops CPU reads of $8000-$BFFF for PCM read mode. A 0 byte raises the IRQ instead
+    pub fn prg_read(&mut self, address: u16, value: u8) {
+        if self.pcm_read_mode && (0x8000..=0xBFFF).contains(&address) {
+            match value {
+                0 => self.pcm_irq = true,
+                _ => self.pcm_level = value,
+            }
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if self.cycle % 2 == 1 {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+        self.frame_divider += 1;
+        if self.frame_divider == FRAME_PERIOD {
+            self.frame_divider = 0;
+            self.pulse_1.clock_frame();
+            self.pulse_2.clock_frame();
+        }
+        self.cycle += 1;
+    }
+
+    //The pulses go through the same kind of DAC as the APU's. PCM is 8 bit where the DMC
+    //is 7, so it lands on the same curve at half resolution
+    pub fn output(&self) -> f32 {
+        mixer::mix([self.pulse_1.output(), self.pulse_2.output(), 0, 0, self.pcm_level >> 1])
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = self.registers.to_vec();
+        state.extend_from_slice(&[self.pcm_level, self.pcm_irq as u8]);
+        state
+    }
+    //Restores register values rather than the exact sequencer positions, which is close
+    //enough for audio
+    pub fn load_state(&mut self, state: &[u8]) {
+        *self = Mmc5Audio::new();
+        for (offset, &value) in state[..0x16].iter().enumerate() {
+            let address = 0x5000 + offset as u16;
+            if address != 0x5011 {
+                self.cpu_write(address, value);
+            }
+        }
+        self.registers.copy_from_slice(&state[..0x16]);
+        self.pcm_level = state[0x16];
+        self.pcm_irq = state[0x17] != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_plays_without_sweep_muting() {
+        let mut audio = Mmc5Audio::new();
+        audio.cpu_write(0x5015, 0x01);
+        //Constant volume 15, 50% duty, period 4 which the APU would mute
+        audio.cpu_write(0x5000, 0xBF);
+        audio.cpu_write(0x5002, 0x04);
+        audio.cpu_write(0x5003, 0x08);
+
+        let mut heard = false;
+        for _ in 0..100 {
+            audio.clock();
+            heard |= audio.output() > 0.0;
+        }
+        assert!(heard);
+        assert_eq!(audio.cpu_read(0x5015), 0x01);
+    }
+
+    #[test]
+    fn length_counter_runs_at_240_hz() {
+        let mut audio = Mmc5Audio::new();
+        audio.cpu_write(0x5015, 0x02);
+        audio.cpu_write(0x5004, 0x10);
+        //Length index 3 loads 2
+        audio.cpu_write(0x5007, 0x18);
+
+        for _ in 0..FRAME_PERIOD {
+            audio.clock();
+        }
+        assert_eq!(audio.cpu_read(0x5015), 0x02);
+        for _ in 0..FRAME_PERIOD {
+            audio.clock();
+        }
+        assert_eq!(audio.cpu_read(0x5015), 0x00);
+    }
+
+    #[test]
+    fn pcm_write_mode_ignores_zero() {
+        let mut audio = Mmc5Audio::new();
+        audio.cpu_write(0x5011, 0x80);
+        let level = audio.output();
+        assert!(level > 0.0);
+        audio.cpu_write(0x5011, 0x00);
+        assert_eq!(audio.output(), level);
+    }
+
+    #[test]
+    fn pcm_read_mode_and_irq() {
+        let mut audio = Mmc5Audio::new();
+        audio.cpu_write(0x5010, 0x81);
+
+        audio.prg_read(0x8000, 0x40);
+        assert_eq!(audio.pcm_level, 0x40);
+        //Reads outside $8000-$BFFF don't count
+        audio.prg_read(0xC000, 0x20);
+        assert_eq!(audio.pcm_level, 0x40);
+
+        audio.prg_read(0x9000, 0x00);
+        assert!(audio.irq());
+        assert_eq!(audio.cpu_read(0x5010), 0x81);
+        assert!(!audio.irq());
+    }
+}
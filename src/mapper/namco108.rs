@@ -0,0 +1,82 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const STATE_SIZE: usize = 9;
+
+//Mapper 206, Namco 108 (and the Tengen 108 clones). The MMC3's predecessor: the same bank
+//select at $8000 and bank data at $8001, but no PRG or CHR mode bits, no mirroring control,
+//no PRG RAM and no IRQ. R0/R1 are 2K CHR at $0000/$0800, R2-R5 1K CHR at $1000-$1C00, R6/R7
+//8K PRG at $8000/$A000, the last 16K is fixed
+pub struct Namco108 {
+    mirroring: Nametable,
+    bank_select: u8,
+    registers: [u8; 8],
+}
+impl Namco108 {
+    pub fn new(mirroring: Nametable) -> Self {
+        Self {
+            mirroring,
+            bank_select: 0,
+            registers: [0; 8],
+        }
+    }
+
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x8000..=0x9FFF => self.registers[6] as usize & 0x0F,
+            0xA000..=0xBFFF => self.registers[7] as usize & 0x0F,
+            0xC000..=0xDFFF => banks.max(2) - 2,
+            _ => banks - 1,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        let address = address as usize;
+        let bank = match address {
+            0x0000..=0x07FF => (self.registers[0] & 0x3E) as usize + (address >> 10 & 0x01),
+            0x0800..=0x0FFF => (self.registers[1] & 0x3E) as usize + (address >> 10 & 0x01),
+            _ => self.registers[2 + ((address - 0x1000) >> 10)] as usize & 0x3F,
+        };
+        bank * CHR_BANK_SIZE + (address & 0x03FF)
+    }
+}
+impl Mapper for Namco108 {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF if address & 0x01 == 0 => self.bank_select = value & 0x07,
+            0x8000..=0x9FFF => self.registers[self.bank_select as usize] = value,
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.bank_select];
+        state.extend_from_slice(&self.registers);
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.bank_select = state[0];
+        self.registers.copy_from_slice(&state[1..]);
+        Ok(())
+    }
+}
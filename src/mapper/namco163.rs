@@ -0,0 +1,302 @@
+mod audio;
+
+use crate::mapper::namco163::audio::Namco163Audio;
+use crate::mapper::{Mapper, MapperError, chr_read, chr_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const STATE_SIZE: usize = 21 + 128;
+
+//Bank values from $E0 up select a page of CIRAM instead of CHR ROM
+const CIRAM_BANKS: u8 = 0xE0;
+
+//Mapper 19. Three 8K PRG banks, eight 1K CHR banks and four nametable banks that can each
+//point at CHR ROM or CIRAM, a 15 bit cycle counting IRQ and 128 bytes of internal RAM
+//shared between save data and the wavetable sound channels
+pub struct Namco163 {
+    //$8000-$B800 pattern tables, $C000-$D800 nametables
+    chr_banks: [u8; 12],
+    prg_banks: [u8; 3],
+    //Bits 6 and 7 of $E800 stop $E0+ values mapping CIRAM into the two pattern tables
+    ciram_disable: u8,
+    sound_disable: bool,
+    //$F800: internal RAM address with auto increment in bit 7, also the PRG RAM protect
+    ram_address: u8,
+    irq_counter: u16,
+    irq_enabled: bool,
+    audio: Namco163Audio,
+}
+impl Namco163 {
+    pub fn new() -> Self {
+        Self {
+            chr_banks: [0; 12],
+            prg_banks: [0; 3],
+            ciram_disable: 0,
+            sound_disable: false,
+            ram_address: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            audio: Namco163Audio::new(),
+        }
+    }
+
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0xE000..=0xFFFF => banks - 1,
+            _ => self.prg_banks[(address as usize - 0x8000) >> 13] as usize & 0x3F,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    //Upper nibble 0100 unlocks PRG RAM, then each low bit protects one 2K quarter
+    fn prg_ram_writable(&self, address: u16) -> bool {
+        let quarter = (address as usize - 0x6000) >> 11;
+        self.ram_address & 0xF0 == 0x40 && self.ram_address & (1 << quarter) == 0
+    }
+
+    //Where a PPU address lands: Ok for CHR ROM/RAM, Err for a CIRAM offset
+    fn ppu_target(&self, address: u16) -> Result<usize, usize> {
+        let slot = (address as usize >> 10) & 0x0F;
+        let (bank, ciram_allowed) = match address {
+            0x0000..=0x1FFF => (self.chr_banks[slot], self.ciram_disable & (0x40 << (slot >> 2)) == 0),
+            _ => (self.chr_banks[8 + (slot & 0x03)], true),
+        };
+        match bank >= CIRAM_BANKS && ciram_allowed {
+            true => Err((bank as usize & 0x01) * 0x400 + (address as usize & 0x03FF)),
+            false => Ok(bank as usize * CHR_BANK_SIZE + (address as usize & 0x03FF)),
+        }
+    }
+
+    fn data_port(&mut self) -> usize {
+        let address = (self.ram_address & 0x7F) as usize;
+        if self.ram_address & 0x80 != 0 {
+            self.ram_address = 0x80 | (self.ram_address.wrapping_add(1) & 0x7F);
+        }
+        address
+    }
+}
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x4800..=0x4FFF => {
+                let address = self.data_port();
+                self.audio.ram[address]
+            }
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+            0x6000..=0x7FFF if !prg_ram.is_empty() => prg_ram[(address as usize - 0x6000) % prg_ram.len()],
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x4800..=0x4FFF => {
+                let address = self.data_port();
+                self.audio.ram[address] = value;
+            }
+            //Writing either half of the counter acknowledges the IRQ
+            0x5000..=0x57FF => self.irq_counter = (self.irq_counter & 0x7F00) | value as u16,
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16 & 0x7F) << 8;
+                self.irq_enabled = value & 0x80 != 0;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(address) && !prg_ram.is_empty() => {
+                let prg_ram_size = prg_ram.len();
+                prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+            }
+            0x8000..=0xDFFF => self.chr_banks[(address as usize - 0x8000) >> 11] = value,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = value & 0x3F;
+                self.sound_disable = value & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => {
+                self.prg_banks[1] = value & 0x3F;
+                self.ciram_disable = value & 0xC0;
+            }
+            0xF000..=0xF7FF => self.prg_banks[2] = value & 0x3F,
+            0xF800..=0xFFFF => self.ram_address = value,
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        match self.ppu_target(address) {
+            Ok(offset) => chr_read(chr_rom, chr_ram, offset),
+            Err(offset) => vram[offset],
+        }
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        match self.ppu_target(address) {
+            Ok(offset) => chr_write(chr_rom, chr_ram, offset, value),
+            Err(offset) => vram[offset] = value,
+        }
+    }
+    //Only layouts built entirely from CIRAM have a name
+    fn mirroring(&self) -> Nametable {
+        let pages = [8, 9, 10, 11].map(|slot| match self.chr_banks[slot] >= CIRAM_BANKS {
+            true => Some(self.chr_banks[slot] & 0x01),
+            false => None,
+        });
+        match pages {
+            [Some(0), Some(1), Some(0), Some(1)] => Nametable::Vertical,
+            [Some(0), Some(0), Some(1), Some(1)] => Nametable::Horizontal,
+            [Some(0), Some(0), Some(0), Some(0)] => Nametable::SingleScreenLower,
+            [Some(1), Some(1), Some(1), Some(1)] => Nametable::SingleScreenUpper,
+            _ => Nametable::FourScreen,
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq_enabled && self.irq_counter == 0x7FFF
+    }
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+        }
+        self.audio.clock();
+    }
+    fn audio_output(&self) -> f32 {
+        match self.sound_disable {
+            true => 0.0,
+            false => self.audio.output(),
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = self.chr_banks.to_vec();
+        state.extend_from_slice(&self.prg_banks);
+        state.extend_from_slice(&[
+            self.ciram_disable,
+            self.sound_disable as u8,
+            self.ram_address,
+            self.irq_counter as u8,
+            (self.irq_counter >> 8) as u8,
+            self.irq_enabled as u8,
+        ]);
+        state.extend_from_slice(&self.audio.ram);
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.chr_banks.copy_from_slice(&state[0..12]);
+        self.prg_banks.copy_from_slice(&state[12..15]);
+        self.ciram_disable = state[15];
+        self.sound_disable = state[16] != 0;
+        self.ram_address = state[17];
+        self.irq_counter = u16::from_le_bytes([state[18], state[19]]);
+        self.irq_enabled = state[20] != 0;
+        self.audio.ram.copy_from_slice(&state[21..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn prg_banking() {
+        let mut mapper = Namco163::new();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 32);
+
+        mapper.cpu_write(&[], &mut [], 0xE000, 0x45);
+        mapper.cpu_write(&[], &mut [], 0xE800, 0xC6);
+        mapper.cpu_write(&[], &mut [], 0xF000, 0x07);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 5);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xA000), 6);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 7);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xE000), 31);
+        assert!(mapper.sound_disable);
+    }
+
+    #[test]
+    fn chr_and_ciram_banks() {
+        let mut mapper = Namco163::new();
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 256);
+        let mut vram = [0x00; 0x1000];
+
+        mapper.cpu_write(&[], &mut [], 0x8800, 0x12);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0400), 0x12);
+
+        //$E1 in a pattern table bank is CIRAM page 1 unless $E800 bit 6 disables it
+        mapper.cpu_write(&[], &mut [], 0x8000, 0xE1);
+        mapper.ppu_write(&chr_rom, &mut [], &mut vram, 0x0005, 0x99);
+        assert_eq!(vram[0x0405], 0x99);
+        mapper.cpu_write(&[], &mut [], 0xE800, 0x40);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x0005), 0xE1);
+
+        //Nametables can come from CHR ROM
+        mapper.cpu_write(&[], &mut [], 0xC000, 0xE0);
+        mapper.cpu_write(&[], &mut [], 0xC800, 0xE1);
+        mapper.cpu_write(&[], &mut [], 0xD000, 0xE0);
+        mapper.cpu_write(&[], &mut [], 0xD800, 0xE1);
+        assert_eq!(mapper.mirroring(), Nametable::Vertical);
+        mapper.cpu_write(&[], &mut [], 0xD800, 0x33);
+        assert_eq!(mapper.mirroring(), Nametable::FourScreen);
+        assert_eq!(mapper.ppu_read(&chr_rom, &[], &vram, 0x2C00), 0x33);
+    }
+
+    #[test]
+    fn internal_ram_port() {
+        let mut mapper = Namco163::new();
+        //Auto increment from $7E
+        mapper.cpu_write(&[], &mut [], 0xF800, 0xFE);
+        mapper.cpu_write(&[], &mut [], 0x4800, 0x11);
+        mapper.cpu_write(&[], &mut [], 0x4800, 0x22);
+        mapper.cpu_write(&[], &mut [], 0x4800, 0x33);
+        assert_eq!(mapper.audio.ram[0x7E], 0x11);
+        assert_eq!(mapper.audio.ram[0x7F], 0x22);
+        assert_eq!(mapper.audio.ram[0x00], 0x33);
+
+        mapper.cpu_write(&[], &mut [], 0xF800, 0x7F);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x4800), 0x22);
+        assert_eq!(mapper.cpu_read(&[], &[], 0x4800), 0x22);
+    }
+
+    #[test]
+    fn prg_ram_write_protect() {
+        let mut mapper = Namco163::new();
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x11);
+        assert_eq!(prg_ram[0], 0x00);
+        //Unlocked except the second quarter
+        mapper.cpu_write(&[], &mut prg_ram, 0xF800, 0x42);
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0x11);
+        mapper.cpu_write(&[], &mut prg_ram, 0x6800, 0x22);
+        assert_eq!(prg_ram[0x0000], 0x11);
+        assert_eq!(prg_ram[0x0800], 0x00);
+    }
+
+    #[test]
+    fn irq_counts_up_to_7fff() {
+        let mut mapper = Namco163::new();
+        mapper.cpu_write(&[], &mut [], 0x5000, 0xFD);
+        mapper.cpu_write(&[], &mut [], 0x5800, 0xFF);
+        mapper.cpu_clock();
+        assert!(!mapper.irq());
+        mapper.cpu_clock();
+        assert!(mapper.irq());
+        //Stays there until rewritten
+        mapper.cpu_clock();
+        assert!(mapper.irq());
+        assert_eq!(mapper.cpu_read(&[], &[], 0x5800), 0xFF);
+        mapper.cpu_write(&[], &mut [], 0x5000, 0x00);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut mapper = Namco163::new();
+        mapper.cpu_write(&[], &mut [], 0x9000, 0x44);
+        mapper.cpu_write(&[], &mut [], 0xF800, 0x10);
+        mapper.cpu_write(&[], &mut [], 0x4800, 0x55);
+        let state = mapper.save_state();
+
+        let mut restored = Namco163::new();
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+    }
+}
@@ -0,0 +1,132 @@
+//CPU cycles the chip spends on each channel before moving to the next
+const CYCLES_PER_CHANNEL: u8 = 15;
+//Channel registers take up the top of the internal RAM, 8 bytes each, channel 7 at $78
+const CHANNEL_BASE: usize = 0x40;
+
+//Level of one unit of (sample - 8) * volume. A single channel at full swing is about as
+//loud as an APU pulse
+const LEVEL_SCALE: f32 = 0.00125;
+
+//Up to eight wavetable channels playing 4 bit samples out of the chip's own 128 bytes of
+//RAM, which also holds the channel registers. Only one channel is updated every 15 cycles
+//so enabling more of them lowers every channel's sample rate. Real hardware also outputs
+//them one at a time, which whines at high channel counts, so the channels are averaged
+//instead like most players do
+pub struct Namco163Audio {
+    pub ram: [u8; 128],
+    //Channel being updated, counting down from 7
+    channel: usize,
+    divider: u8,
+    outputs: [i16; 8],
+}
+impl Namco163Audio {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; 128],
+            channel: 7,
+            divider: 0,
+            outputs: [0; 8],
+        }
+    }
+
+    //Enabled channels, 1-8 from bits 4-6 of $7F. They are always the highest numbered ones
+    fn channel_count(&self) -> usize {
+        ((self.ram[0x7F] >> 4) & 0x07) as usize + 1
+    }
+
+    //Once per CPU cycle
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < CYCLES_PER_CHANNEL {
+            return;
+        }
+        self.divider = 0;
+
+        self.update_channel(self.channel);
+        let lowest = 8 - self.channel_count();
+        self.channel = match self.channel {
+            channel if channel <= lowest => 7,
+            channel => channel - 1,
+        };
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let registers = CHANNEL_BASE + channel * 8;
+        let read = |offset: usize| self.ram[registers + offset] as u32;
+
+        let frequency = read(0) | read(2) << 8 | (read(4) & 0x03) << 16;
+        let length = (256 - (read(4) & 0xFC)) << 16;
+        let mut phase = read(1) | read(3) << 8 | read(5) << 16;
+        phase = (phase + frequency) % length;
+
+        let sample_address = (read(6) + (phase >> 16)) as usize & 0xFF;
+        let byte = self.ram[sample_address >> 1];
+        let sample = match sample_address & 0x01 {
+            0 => byte & 0x0F,
+            _ => byte >> 4,
+        };
+        self.outputs[channel] = (sample as i16 - 8) * (read(7) & 0x0F) as i16;
+
+        self.ram[registers + 1] = phase as u8;
+        self.ram[registers + 3] = (phase >> 8) as u8;
+        self.ram[registers + 5] = (phase >> 16) as u8;
+    }
+
+    pub fn output(&self) -> f32 {
+        let count = self.channel_count();
+        let sum: i16 = self.outputs[8 - count..].iter().sum();
+        sum as f32 / count as f32 * LEVEL_SCALE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Channel 7 playing a 4 sample square wave from the start of RAM at full volume
+    fn square_channel(audio: &mut Namco163Audio) {
+        audio.ram[0] = 0xFF;
+        audio.ram[1] = 0x00;
+        audio.ram[0x78] = 0x00;
+        audio.ram[0x7A] = 0x00;
+        //Frequency $10000 is one sample per update, length $FC is 4 samples
+        audio.ram[0x7C] = 0xFC | 0x01;
+        audio.ram[0x7E] = 0x00;
+        audio.ram[0x7F] = 0x0F;
+    }
+
+    #[test]
+    fn plays_wavetable() {
+        let mut audio = Namco163Audio::new();
+        square_channel(&mut audio);
+
+        let mut levels = vec![];
+        for _ in 0..4 {
+            for _ in 0..CYCLES_PER_CHANNEL {
+                audio.clock();
+            }
+            levels.push(audio.output());
+        }
+        //Samples 1, 2, 3, 0: $F, $0, $0, $F
+        let high = 7.0 * 15.0 * LEVEL_SCALE;
+        let low = -8.0 * 15.0 * LEVEL_SCALE;
+        assert_eq!(levels, [high, low, low, high]);
+    }
+
+    #[test]
+    fn more_channels_share_the_update_rate() {
+        let mut audio = Namco163Audio::new();
+        square_channel(&mut audio);
+        //Two channels: 7 and 6 alternate
+        audio.ram[0x7F] = 0x1F;
+
+        for _ in 0..CYCLES_PER_CHANNEL * 2 {
+            audio.clock();
+        }
+        assert_eq!(audio.ram[0x7D], 0x01);
+        for _ in 0..CYCLES_PER_CHANNEL * 2 {
+            audio.clock();
+        }
+        assert_eq!(audio.ram[0x7D], 0x02);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 32 * 1024;
+const CHR_BANK_SIZE: usize = 8 * 1024;
+
+//Mapper 79, AVE NINA-03 and NINA-06. The register sits in the expansion area, at any
+//address in $4100-$5FFF with A8 set and A13-A15 matching $4100. Bit 3 picks the 32K PRG
+//bank, bits 0-2 the 8K CHR bank
+pub struct Nina06 {
+    mirroring: Nametable,
+    bank: u8,
+}
+impl Nina06 {
+    pub fn new(mirroring: Nametable) -> Self {
+        Self { mirroring, bank: 0 }
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        (self.bank as usize & 0x07) * CHR_BANK_SIZE + address as usize
+    }
+}
+impl Mapper for Nina06 {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (self.bank as usize >> 3) & 0x01;
+                prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x7FFF)) % prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        if address & 0xE100 == 0x4100 {
+            self.bank = value;
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[bank] => {
+                self.bank = bank;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
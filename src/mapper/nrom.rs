@@ -0,0 +1,259 @@
+use crate::mapper::{Mapper, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+//Mapper 0. No banking: 16 or 32 KiB PRG, 8 KiB CHR and soldered mirroring
+pub struct Nrom {
+    mirroring: Nametable,
+}
+impl Nrom {
+    pub fn new(mirroring: Nametable) -> Self {
+        Self { mirroring }
+    }
+}
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        //CPU $6000-$7FFF: Unbanked PRG-RAM, mirrored as necessary to fill entire 8 KiB window, write protectable with an external switch.
+        //CPU $8000-$BFFF: First 16 KiB of PRG-ROM.
+        //CPU $C000-$FFFF: Last 16 KiB of PRG-ROM (NROM-256) or mirror of $8000-$BFFF (NROM-128).
+        //PPU $0000-$1FFF: 8 KiB CHR-ROM.
+
+        //Need a sanity check here. Check if address is reasonable
+
+        let translated_address: usize;
+
+        //Get size of PRG_ROM and RAM
+        let prg_rom_size: usize = prg_rom.len();
+        let prg_ram_size: usize = prg_ram.len();
+
+        //Notes for my own sake:
+        //(address - base) % bank_size gives an automatic translated address with baked-in mirroring
+        //All addresses up to bank_size are mirrored to the next bytes of bank_size
+
+        //Start address mapping
+        match address {
+            //NES 2.0 headers can say the board has no PRG RAM at all
+            0x6000..=0x7FFF if prg_ram_size == 0 => 0,
+            0x6000..=0x7FFF => {
+                translated_address = (address as usize - 0x6000) % prg_ram_size;
+                prg_ram[translated_address]
+            }
+            0x8000..=0xFFFF => {
+                translated_address = (address as usize - 0x8000) % prg_rom_size;
+                prg_rom[translated_address]
+            }
+            //$4020-$5FFF expansion area, nothing on the board answers: open bus
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        let translated_address: usize;
+
+        //Get size of PRG_RAM
+        let prg_ram_size: usize = prg_ram.len();
+
+        //Start address matching. Need to mutate prg_ram location to new value
+        match address {
+            0x6000..=0x7FFF if prg_ram_size == 0 => {}
+            0x6000..=0x7FFF => {
+                translated_address = (address as usize - 0x6000) % prg_ram_size;
+                prg_ram[translated_address] = value;
+            }
+            //ROM and the empty expansion area ignore writes
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| address as usize)
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| address as usize);
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prg_nrom_128() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let prg_rom_size = 16 * 1024;
+        let mut prg_rom = vec![0xEA; prg_rom_size];
+        let prg_ram = vec![0x00; 8 * 1024];
+
+        prg_rom[0] = 0xEB;
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x8000), 0xEB);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0xC000), 0xEB);
+    }
+
+    #[test]
+    fn prg_nrom_256() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let prg_rom_size = 32 * 1024;
+        let mut prg_rom = vec![0xEA; prg_rom_size];
+        let prg_ram = vec![0x00; 8 * 1024];
+
+        prg_rom[0] = 0xEB;
+        prg_rom[0x4000] = 0xEC;
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x8000), 0xEB);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0xC000), 0xEC);
+    }
+
+    #[test]
+    fn prg_ram_read() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let prg_rom_size = 16 * 1024;
+        let prg_rom = vec![0x00; prg_rom_size];
+        let mut prg_ram = vec![0xEA; 8 * 1024];
+
+        prg_ram[0] = 0xEB;
+        prg_ram[8191] = 0xEC;
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x6000), 0xEB);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x7FFF), 0xEC);
+    }
+
+    #[test]
+    fn prg_ram_write() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        mapper.cpu_write(&[], &mut prg_ram, 0x6001, 0xFF);
+
+        assert_eq!(prg_ram[1], 0xFF);
+    }
+
+    #[test]
+    fn prg_ram_write_mirrored() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let mut prg_ram = vec![0x00; 2 * 1024];
+
+        mapper.cpu_write(&[], &mut prg_ram, 0x6000, 0xFF);
+        assert_eq!(prg_ram[0], 0xFF);
+        assert_eq!(mapper.cpu_read(&[], &prg_ram, 0x6800), 0xFF);
+    }
+
+    #[test]
+    fn prg_rom_write() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let mut prg_ram = vec![0x00; 8 * 1024];
+        let prg_rom = vec![0xEA; 16 * 1024];
+
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x8001, 0xFF);
+
+        assert!(prg_ram.iter().all(|&x| x == 0));
+        assert!(prg_rom.iter().all(|&x| x == 0xEA));
+    }
+
+    #[test]
+    fn expansion_area_is_open_bus() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let prg_rom = vec![0xEA; 16 * 1024];
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x4020, 0xFF);
+        mapper.cpu_write(&prg_rom, &mut prg_ram, 0x5FFF, 0xFF);
+
+        assert!(prg_ram.iter().all(|&x| x == 0));
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x4020), 0x00);
+        assert_eq!(mapper.cpu_read(&prg_rom, &prg_ram, 0x5FFF), 0x00);
+    }
+
+    #[test]
+    fn chr_rom_read() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let mut chr_rom = vec![0xEA; 8 * 1024];
+        let chr_ram = vec![];
+        let vram = vec![0x00; 4 * 1024];
+
+        chr_rom[0] = 0xEB;
+        chr_rom[0x1FFF] = 0xEC;
+
+        assert_eq!(mapper.ppu_read(&chr_rom, &chr_ram, &vram, 0x0000), 0xEB);
+        assert_eq!(mapper.ppu_read(&chr_rom, &chr_ram, &vram, 0x1FFF), 0xEC);
+    }
+
+    #[test]
+    fn chr_rom_write() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let chr_rom = vec![0xEA; 8 * 1024];
+        let mut chr_ram = vec![];
+        let mut vram = vec![0x00; 4 * 1024];
+
+        mapper.ppu_write(&chr_rom, &mut chr_ram, &mut vram, 0x0001, 0xFF);
+
+        assert_eq!(mapper.ppu_read(&chr_rom, &chr_ram, &vram, 0x0001), 0xEA);
+        assert!(vram.iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn chr_ram_write() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let chr_rom = vec![];
+        let mut chr_ram = vec![0x00; 8 * 1024];
+        let mut vram = vec![0x00; 4 * 1024];
+
+        mapper.ppu_write(&chr_rom, &mut chr_ram, &mut vram, 0x1001, 0xFF);
+
+        assert_eq!(chr_ram[0x1001], 0xFF);
+        assert_eq!(mapper.ppu_read(&chr_rom, &chr_ram, &vram, 0x1001), 0xFF);
+    }
+
+    #[test]
+    fn nametable_horizontal_mirroring() {
+        let mut mapper = Nrom::new(Nametable::Horizontal);
+
+        let mut vram = vec![0x00; 4 * 1024];
+
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2001, 0xAA);
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2801, 0xBB);
+
+        assert_eq!(vram[0x0001], 0xAA);
+        assert_eq!(vram[0x0401], 0xBB);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2401), 0xAA);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2C01), 0xBB);
+        //$3000-$3EFF mirrors $2000-$2EFF
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x3401), 0xAA);
+    }
+
+    #[test]
+    fn nametable_vertical_mirroring() {
+        let mut mapper = Nrom::new(Nametable::Vertical);
+
+        let mut vram = vec![0x00; 4 * 1024];
+
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2001, 0xAA);
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2401, 0xBB);
+
+        assert_eq!(vram[0x0001], 0xAA);
+        assert_eq!(vram[0x0401], 0xBB);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2801), 0xAA);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2C01), 0xBB);
+    }
+
+    #[test]
+    fn nametable_four_screen() {
+        let mut mapper = Nrom::new(Nametable::FourScreen);
+
+        let mut vram = vec![0x00; 4 * 1024];
+
+        mapper.ppu_write(&[], &mut [], &mut vram, 0x2C01, 0xAA);
+        assert_eq!(vram[0x0C01], 0xAA);
+        assert_eq!(mapper.ppu_read(&[], &[], &vram, 0x2001), 0x00);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write, bus_conflicts};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 16 * 1024;
+
+//Mapper 2. Switchable 16K at $8000, last 16K fixed at $C000, 8K CHR RAM
+pub struct Uxrom {
+    mirroring: Nametable,
+    bus_conflicts: bool,
+    prg_bank: u8,
+}
+impl Uxrom {
+    pub fn new(mirroring: Nametable, submapper: u8) -> Self {
+        Self {
+            mirroring,
+            bus_conflicts: bus_conflicts(submapper),
+            prg_bank: 0,
+        }
+    }
+}
+impl Mapper for Uxrom {
+    fn cpu_read(&mut self, prg_rom: &[u8], _prg_ram: &[u8], address: u16) -> u8 {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x8000..=0xBFFF => self.prg_bank as usize % banks,
+            0xC000..=0xFFFF => banks - 1,
+            _ => return 0,
+        };
+        prg_rom[(bank * PRG_BANK_SIZE + (address as usize & 0x3FFF)) % prg_rom.len()]
+    }
+    fn cpu_write(&mut self, prg_rom: &[u8], _prg_ram: &mut [u8], address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.prg_bank = match self.bus_conflicts {
+                true => value & self.cpu_read(prg_rom, &[], address),
+                false => value,
+            };
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| address as usize)
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| address as usize);
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.prg_bank]
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        match state {
+            &[prg_bank] => {
+                self.prg_bank = prg_bank;
+                Ok(())
+            }
+            _ => Err(MapperError::InvalidState),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn switch_and_fixed_banks() {
+        let mut mapper = Uxrom::new(Nametable::Vertical, 0);
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 0);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xC000), 7);
+
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x05);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xBFFF), 5);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0xFFFF), 7);
+    }
+
+    #[test]
+    fn bank_wraps_to_rom_size() {
+        let mut mapper = Uxrom::new(Nametable::Vertical, 0);
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x0B);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 3);
+    }
+
+    #[test]
+    fn bus_conflicts_and_value_with_rom() {
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 8);
+
+        //Writing into bank 7 at $C000, where ROM holds 0x07
+        let mut mapper = Uxrom::new(Nametable::Vertical, 2);
+        mapper.cpu_write(&prg_rom, &mut [], 0xC000, 0x06);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 6);
+        //Now bank 6 is at $8000, ROM holds 0x06 there
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x03);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 2);
+
+        let mut mapper = Uxrom::new(Nametable::Vertical, 1);
+        mapper.cpu_write(&prg_rom, &mut [], 0x8000, 0x03);
+        assert_eq!(mapper.cpu_read(&prg_rom, &[], 0x8000), 3);
+    }
+
+    #[test]
+    fn chr_ram_and_mirroring() {
+        let mut mapper = Uxrom::new(Nametable::Horizontal, 0);
+        let mut chr_ram = vec![0x00; 8 * 1024];
+        let mut vram = [0x00; 0x1000];
+
+        mapper.ppu_write(&[], &mut chr_ram, &mut vram, 0x0123, 0xAA);
+        assert_eq!(mapper.ppu_read(&[], &chr_ram, &vram, 0x0123), 0xAA);
+        mapper.ppu_write(&[], &mut chr_ram, &mut vram, 0x2001, 0xBB);
+        assert_eq!(mapper.ppu_read(&[], &chr_ram, &vram, 0x2401), 0xBB);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const STATE_SIZE: usize = 21 + 5;
+
+//Mappers 21, 22, 23 and 25. Konami wired the chip's two register select pins to different
+//CPU address lines on different boards, so the same registers show up at $x001 on one
+//game and $x040 on another. NES 2.0 submappers name the exact wiring, without one both
+//candidate lines are ORed together, which works because the games only ever use their own
+//VRC2 is the cut down version: 1 bit mirroring, 4 bit CHR high nibbles and no IRQ
+pub struct Vrc4 {
+    vrc2: bool,
+    //Address lines feeding register select bits 0 and 1
+    select_0: u16,
+    select_1: u16,
+    //VRC2a only has CHR A10 and up wired, so bank numbers are in 2K units
+    chr_shift: u8,
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    mirroring: Nametable,
+    chr_banks: [u16; 8],
+    //VRC2 boards without PRG RAM have a 1 bit latch at $6000, used for EEPROM protection
+    latch: u8,
+
+    irq: VrcIrq,
+}
+impl Vrc4 {
+    pub fn new(mapper: u16, submapper: u8) -> Result<Self, MapperError> {
+        let (vrc2, select_0, select_1) = match (mapper, submapper) {
+            //VRC4a, VRC4c
+            (21, 1) => (false, 0x02, 0x04),
+            (21, 2) => (false, 0x40, 0x80),
+            (21, 0) => (false, 0x42, 0x84),
+            //VRC2a
+            (22, 0) => (true, 0x02, 0x01),
+            //VRC4f, VRC4e, VRC2b
+            (23, 1) => (false, 0x01, 0x02),
+            (23, 2) => (false, 0x04, 0x08),
+            (23, 3) => (true, 0x01, 0x02),
+            (23, 0) => (false, 0x05, 0x0A),
+            //VRC4b, VRC4d, VRC2c
+            (25, 1) => (false, 0x02, 0x01),
+            (25, 2) => (false, 0x08, 0x04),
+            (25, 3) => (true, 0x02, 0x01),
+            (25, 0) => (false, 0x0A, 0x05),
+            _ => return Err(MapperError::Unsupported { mapper, submapper }),
+        };
+        Ok(Self {
+            vrc2,
+            select_0,
+            select_1,
+            chr_shift: (mapper == 22) as u8,
+            prg_banks: [0, 0],
+            prg_swap: false,
+            mirroring: Nametable::Vertical,
+            chr_banks: [0; 8],
+            latch: 0,
+            irq: VrcIrq::new(),
+        })
+    }
+
+    //Fold whichever address lines are wired to the select pins down to $x000-$x003
+    fn register(&self, address: u16) -> u16 {
+        let select_0 = (address & self.select_0 != 0) as u16;
+        let select_1 = (address & self.select_1 != 0) as u16;
+        (address & 0xF000) | select_1 << 1 | select_0
+    }
+
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match (address, self.prg_swap) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize & 0x1F,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => banks.saturating_sub(2),
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize & 0x1F,
+            _ => banks - 1,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize >> 10] >> self.chr_shift;
+        bank as usize * CHR_BANK_SIZE + (address as usize & 0x03FF)
+    }
+}
+impl Mapper for Vrc4 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !prg_ram.is_empty() => prg_ram[(address as usize - 0x6000) % prg_ram.len()],
+            //Only bit 0 is driven, the rest is open bus
+            0x6000..=0x6FFF if self.vrc2 => self.latch | 0x60,
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        if address < 0x8000 {
+            match address {
+                0x6000..=0x7FFF if !prg_ram.is_empty() => {
+                    let prg_ram_size = prg_ram.len();
+                    prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+                }
+                0x6000..=0x6FFF if self.vrc2 => self.latch = value & 0x01,
+                _ => {}
+            }
+            return;
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_banks[0] = value,
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = match value & 0x01 {
+                    0 => Nametable::Vertical,
+                    _ => Nametable::Horizontal,
+                };
+            }
+            0x9000..=0x9001 => {
+                self.mirroring = match value & 0x03 {
+                    0 => Nametable::Vertical,
+                    1 => Nametable::Horizontal,
+                    2 => Nametable::SingleScreenLower,
+                    _ => Nametable::SingleScreenUpper,
+                };
+            }
+            //Bit 0 is documented as a PRG RAM enable, but games rely on RAM without
+            //setting it so it is ignored like on most boards in the wild
+            0x9002..=0x9003 => self.prg_swap = value & 0x02 != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = value,
+            register @ 0xB000..=0xE003 => {
+                //Two banks per register group, low nibble then high nibble
+                let bank = ((register as usize - 0xB000) >> 12) * 2 + ((register as usize >> 1) & 0x01);
+                let value = value as u16;
+                self.chr_banks[bank] = match register & 0x01 {
+                    0 => (self.chr_banks[bank] & 0x1F0) | (value & 0x0F),
+                    _ if self.vrc2 => (self.chr_banks[bank] & 0x0F) | (value & 0x0F) << 4,
+                    _ => (self.chr_banks[bank] & 0x0F) | (value & 0x1F) << 4,
+                };
+            }
+            _ if self.vrc2 => {}
+            0xF000 => self.irq.write_latch_low(value),
+            0xF001 => self.irq.write_latch_high(value),
+            0xF002 => self.irq.write_control(value),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring, address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring, address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        self.mirroring
+    }
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![
+            self.prg_banks[0],
+            self.prg_banks[1],
+            self.prg_swap as u8,
+            self.mirroring as u8,
+            self.latch,
+        ];
+        for bank in self.chr_banks {
+            state.extend_from_slice(&bank.to_le_bytes());
+        }
+        state.extend_from_slice(&self.irq.save_state());
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.prg_banks.copy_from_slice(&state[0..2]);
+        self.prg_swap = state[2] != 0;
+        self.mirroring = match state[3] {
+            0 => Nametable::Horizontal,
+            1 => Nametable::Vertical,
+            2 => Nametable::SingleScreenLower,
+            _ => Nametable::SingleScreenUpper,
+        };
+        self.latch = state[4];
+        for (bank, bytes) in self.chr_banks.iter_mut().zip(state[5..21].chunks_exact(2)) {
+            *bank = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        self.irq.load_state(&state[21..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn submappers_pick_address_lines() {
+        //$B001 on VRC4f, $B004 on VRC4e, $B040 on VRC4c, both VRC4a/c lines without a submapper
+        let cases = [(23, 1, 0xB001), (23, 2, 0xB004), (21, 2, 0xB040), (21, 0, 0xB040), (21, 0, 0xB002)];
+        for (mapper, submapper, address) in cases {
+            let mut board = Vrc4::new(mapper, submapper).unwrap();
+            board.cpu_write(&[], &mut [], address, 0x01);
+            assert_eq!(board.chr_banks[0], 0x10, "mapper {} submapper {}", mapper, submapper);
+        }
+        assert!(Vrc4::new(21, 5).is_err());
+        assert!(Vrc4::new(22, 1).is_err());
+    }
+
+    #[test]
+    fn prg_banking_and_swap() {
+        let mut board = Vrc4::new(25, 1).unwrap();
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 16);
+
+        board.cpu_write(&[], &mut [], 0x8000, 3);
+        board.cpu_write(&[], &mut [], 0xA000, 5);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0x8000), 3);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xA000), 5);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xC000), 14);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xE000), 15);
+
+        //VRC4b has A0 on select 1, so $9002 is $9001 on the board
+        board.cpu_write(&[], &mut [], 0x9001, 0x02);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0x8000), 14);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xC000), 3);
+    }
+
+    #[test]
+    fn chr_nibbles() {
+        let mut board = Vrc4::new(23, 1).unwrap();
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 512);
+        let vram = [0x00; 0x1000];
+
+        //Bank 7 lives at $E002/$E003. VRC4 high nibbles are 5 bits
+        board.cpu_write(&[], &mut [], 0xE002, 0x03);
+        board.cpu_write(&[], &mut [], 0xE003, 0x12);
+        assert_eq!(board.chr_banks[7], 0x123);
+        assert_eq!(board.ppu_read(&chr_rom, &[], &vram, 0x1C00), 0x23);
+
+        //VRC2a drops the low bit
+        let mut board = Vrc4::new(22, 0).unwrap();
+        board.cpu_write(&[], &mut [], 0xB000, 0x05);
+        assert_eq!(board.ppu_read(&chr_rom, &[], &vram, 0x0000), 2);
+    }
+
+    #[test]
+    fn mirroring_modes() {
+        let mut board = Vrc4::new(21, 1).unwrap();
+        board.cpu_write(&[], &mut [], 0x9000, 0x03);
+        assert_eq!(board.mirroring(), Nametable::SingleScreenUpper);
+
+        //VRC2 only has the one bit
+        let mut board = Vrc4::new(23, 3).unwrap();
+        board.cpu_write(&[], &mut [], 0x9000, 0x03);
+        assert_eq!(board.mirroring(), Nametable::Horizontal);
+    }
+
+    #[test]
+    fn vrc2_latch_without_prg_ram() {
+        let mut board = Vrc4::new(22, 0).unwrap();
+        board.cpu_write(&[], &mut [], 0x6000, 0xFF);
+        assert_eq!(board.cpu_read(&[], &[], 0x6000), 0x61);
+    }
+
+    #[test]
+    fn vrc4_irq() {
+        let mut board = Vrc4::new(21, 1).unwrap();
+        //Latch $FE in two nibbles, cycle mode
+        board.cpu_write(&[], &mut [], 0xF000, 0x0E);
+        board.cpu_write(&[], &mut [], 0xF002, 0x0F);
+        board.cpu_write(&[], &mut [], 0xF004, 0x06);
+        board.cpu_clock();
+        assert!(!board.irq());
+        board.cpu_clock();
+        assert!(board.irq());
+        board.cpu_write(&[], &mut [], 0xF006, 0x00);
+        assert!(!board.irq());
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut board = Vrc4::new(25, 2).unwrap();
+        board.cpu_write(&[], &mut [], 0x8000, 0x07);
+        board.cpu_write(&[], &mut [], 0x9000, 0x02);
+        board.cpu_write(&[], &mut [], 0xC004, 0x13);
+        let state = board.save_state();
+
+        let mut restored = Vrc4::new(25, 2).unwrap();
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.mirroring(), Nametable::SingleScreenLower);
+    }
+}
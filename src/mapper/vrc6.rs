@@ -0,0 +1,219 @@
+mod audio;
+
+use crate::mapper::vrc6::audio::Vrc6Audio;
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const STATE_SIZE: usize = 11 + 5 + 10;
+
+//Mappers 24 (VRC6a) and 26 (VRC6b), which swap the two register select lines. A 16K and
+//an 8K PRG bank, eight 1K CHR banks, the VRC IRQ counter and three channels of audio
+//
+//$B003 can also put CHR ROM behind the nametables, which no released game does. Only the
+//standard CHR layout with its mirroring bits is supported
+pub struct Vrc6 {
+    swap_select: bool,
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; 8],
+    //$B003: bits 2-3 mirroring, bit 7 PRG RAM enable
+    control: u8,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+impl Vrc6 {
+    pub fn new(mapper: u16) -> Self {
+        Self {
+            swap_select: mapper == 26,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        match self.swap_select {
+            true => (address & 0xF000) | (address & 0x01) << 1 | (address & 0x02) >> 1,
+            false => address & 0xF003,
+        }
+    }
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0x8000..=0xBFFF => (self.prg_bank_16k as usize & 0x0F) * 2 + ((address as usize >> 13) & 0x01),
+            0xC000..=0xDFFF => self.prg_bank_8k as usize & 0x1F,
+            _ => banks - 1,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        self.chr_banks[address as usize >> 10] as usize * CHR_BANK_SIZE + (address as usize & 0x03FF)
+    }
+    fn prg_ram_enabled(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+}
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !prg_ram.is_empty() => {
+                prg_ram[(address as usize - 0x6000) % prg_ram.len()]
+            }
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            if self.prg_ram_enabled() && !prg_ram.is_empty() {
+                let prg_ram_size = prg_ram.len();
+                prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+            }
+            return;
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_bank_16k = value,
+            register @ (0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002) => self.audio.write(register, value),
+            0xB003 => self.control = value,
+            0xC000..=0xC003 => self.prg_bank_8k = value,
+            register @ (0xD000..=0xD003 | 0xE000..=0xE003) => {
+                let bank = ((register as usize - 0xD000) >> 12) * 4 + (register as usize & 0x03);
+                self.chr_banks[bank] = value;
+            }
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring(), address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        match (self.control >> 2) & 0x03 {
+            0 => Nametable::Vertical,
+            1 => Nametable::Horizontal,
+            2 => Nametable::SingleScreenLower,
+            _ => Nametable::SingleScreenUpper,
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.prg_bank_16k, self.prg_bank_8k, self.control];
+        state.extend_from_slice(&self.chr_banks);
+        state.extend_from_slice(&self.irq.save_state());
+        state.extend_from_slice(&self.audio.save_state());
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.prg_bank_16k = state[0];
+        self.prg_bank_8k = state[1];
+        self.control = state[2];
+        self.chr_banks.copy_from_slice(&state[3..11]);
+        self.irq.load_state(&state[11..16]);
+        self.audio.load_state(&state[16..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn prg_banking() {
+        let mut board = Vrc6::new(24);
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 32);
+
+        board.cpu_write(&[], &mut [], 0x8000, 3);
+        board.cpu_write(&[], &mut [], 0xC000, 9);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0x8000), 6);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xA000), 7);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xC000), 9);
+        assert_eq!(board.cpu_read(&prg_rom, &[], 0xE000), 31);
+    }
+
+    #[test]
+    fn vrc6b_swaps_select_lines() {
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 256);
+        let vram = [0x00; 0x1000];
+
+        //$D001 is bank 1 on VRC6a and bank 2 on VRC6b
+        let mut board = Vrc6::new(24);
+        board.cpu_write(&[], &mut [], 0xD001, 0x42);
+        assert_eq!(board.ppu_read(&chr_rom, &[], &vram, 0x0400), 0x42);
+        let mut board = Vrc6::new(26);
+        board.cpu_write(&[], &mut [], 0xD001, 0x42);
+        assert_eq!(board.ppu_read(&chr_rom, &[], &vram, 0x0800), 0x42);
+
+        //$B003 is the same on both
+        board.cpu_write(&[], &mut [], 0xB003, 0x84);
+        assert_eq!(board.mirroring(), Nametable::Horizontal);
+    }
+
+    #[test]
+    fn prg_ram_enable() {
+        let mut board = Vrc6::new(24);
+        let mut prg_ram = vec![0x00; 8 * 1024];
+
+        board.cpu_write(&[], &mut prg_ram, 0x6000, 0x11);
+        assert_eq!(board.cpu_read(&[], &prg_ram, 0x6000), 0x00);
+        board.cpu_write(&[], &mut prg_ram, 0xB003, 0x80);
+        board.cpu_write(&[], &mut prg_ram, 0x6000, 0x11);
+        assert_eq!(board.cpu_read(&[], &prg_ram, 0x6000), 0x11);
+    }
+
+    #[test]
+    fn irq_and_audio() {
+        //On VRC6b the control is at $F002 and the acknowledge at $F001
+        let mut board = Vrc6::new(26);
+        board.cpu_write(&[], &mut [], 0xF000, 0xFF);
+        board.cpu_write(&[], &mut [], 0xF002, 0x06);
+        board.cpu_clock();
+        assert!(board.irq());
+        board.cpu_write(&[], &mut [], 0xF001, 0x00);
+        assert!(!board.irq());
+
+        //Pulse 1 in constant mode, enabled through $9002 ($9001 on the board)
+        board.cpu_write(&[], &mut [], 0x9000, 0x8F);
+        board.cpu_write(&[], &mut [], 0x9001, 0x80);
+        board.cpu_clock();
+        assert!(board.audio_output() > 0.0);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut board = Vrc6::new(24);
+        board.cpu_write(&[], &mut [], 0x8000, 0x02);
+        board.cpu_write(&[], &mut [], 0xE003, 0x33);
+        board.cpu_write(&[], &mut [], 0xB000, 0x20);
+        let state = board.save_state();
+
+        let mut restored = Vrc6::new(24);
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+    }
+}
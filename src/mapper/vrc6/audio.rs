@@ -0,0 +1,261 @@
+//Output of one step of the VRC6's DAC relative to the APU. A VRC6 pulse at full volume is
+//about as loud as an APU pulse at full volume
+const LEVEL_SCALE: f32 = 0.00996;
+
+//Pulse with 16 step duty cycles and no envelope. Mode forces the output high, which games
+//use to play raw volume writes as samples
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    mode: bool,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+}
+impl Vrc6Pulse {
+    fn new() -> Self {
+        Self {
+            volume: 0,
+            duty: 0,
+            mode: false,
+            enabled: false,
+            period: 0,
+            timer: 0,
+            step: 0,
+        }
+    }
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.volume = value & 0x0F;
+                self.duty = (value >> 4) & 0x07;
+                self.mode = value & 0x80 != 0;
+            }
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (value as u16 & 0x0F) << 8;
+                self.enabled = value & 0x80 != 0;
+                //Disabling resets the duty cycle
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        match self.enabled && (self.mode || self.step <= self.duty) {
+            true => self.volume,
+            false => 0,
+        }
+    }
+}
+
+//Adds the rate to an accumulator every other step and resets after 7 additions. The top
+//5 bits of the accumulator are the output
+struct Vrc6Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+impl Vrc6Sawtooth {
+    fn new() -> Self {
+        Self {
+            rate: 0,
+            enabled: false,
+            period: 0,
+            timer: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (value as u16 & 0x0F) << 8;
+                self.enabled = value & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+//Two pulses at $9000-$9002 and $A000-$A002, the sawtooth at $B000-$B002 and a frequency
+//control at $9003 that halts every channel or speeds them up 16 or 256 times
+pub struct Vrc6Audio {
+    pulse_1: Vrc6Pulse,
+    pulse_2: Vrc6Pulse,
+    sawtooth: Vrc6Sawtooth,
+    frequency_control: u8,
+    //Register values for save states, replayed on load
+    registers: [u8; 10],
+}
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse_1: Vrc6Pulse::new(),
+            pulse_2: Vrc6Pulse::new(),
+            sawtooth: Vrc6Sawtooth::new(),
+            frequency_control: 0,
+            registers: [0; 10],
+        }
+    }
+
+    //Register as seen by the VRC6a, $9000-$9003, $A000-$A002 and $B000-$B002
+    pub fn write(&mut self, register: u16, value: u8) {
+        let index = match register {
+            0x9000..=0x9003 => register - 0x9000,
+            0xA000..=0xA002 => register - 0xA000 + 4,
+            0xB000..=0xB002 => register - 0xB000 + 7,
+            _ => return,
+        };
+        self.registers[index as usize] = value;
+        match register {
+            0x9003 => self.frequency_control = value & 0x07,
+            0x9000..=0x9002 => self.pulse_1.write(register - 0x9000, value),
+            0xA000..=0xA002 => self.pulse_2.write(register - 0xA000, value),
+            _ => self.sawtooth.write(register - 0xB000, value),
+        }
+    }
+
+    //Once per CPU cycle
+    pub fn clock(&mut self) {
+        let shift = match self.frequency_control {
+            control if control & 0x01 != 0 => return,
+            control if control & 0x04 != 0 => 8,
+            control if control & 0x02 != 0 => 4,
+            _ => 0,
+        };
+        self.pulse_1.clock(shift);
+        self.pulse_2.clock(shift);
+        self.sawtooth.clock(shift);
+    }
+
+    //The VRC6 mixes linearly, 0-61 across the three channels
+    pub fn output(&self) -> f32 {
+        let level = self.pulse_1.output() + self.pulse_2.output() + self.sawtooth.output();
+        level as f32 * LEVEL_SCALE
+    }
+
+    pub fn save_state(&self) -> [u8; 10] {
+        self.registers
+    }
+    pub fn load_state(&mut self, state: &[u8]) {
+        *self = Vrc6Audio::new();
+        let registers = [0x9000, 0x9001, 0x9002, 0x9003, 0xA000, 0xA001, 0xA002, 0xB000, 0xB001, 0xB002];
+        for (register, &value) in registers.into_iter().zip(state) {
+            self.write(register, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_duty_cycle() {
+        let mut audio = Vrc6Audio::new();
+        //Volume 15, duty 3 (4/16), period 0 so every clock is a step
+        audio.write(0x9000, 0x3F);
+        audio.write(0x9001, 0x00);
+        audio.write(0x9002, 0x80);
+
+        let mut high = 0;
+        for _ in 0..16 {
+            audio.clock();
+            if audio.output() > 0.0 {
+                high += 1;
+            }
+        }
+        assert_eq!(high, 4);
+    }
+
+    #[test]
+    fn pulse_mode_ignores_duty() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0xA000, 0x85);
+        audio.write(0xA002, 0x80);
+        for _ in 0..16 {
+            audio.clock();
+            assert_eq!(audio.output(), 5.0 * LEVEL_SCALE);
+        }
+    }
+
+    #[test]
+    fn sawtooth_ramps_and_resets() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0xB000, 0x08);
+        audio.write(0xB002, 0x80);
+
+        let mut levels = vec![];
+        for _ in 0..14 {
+            audio.clock();
+            levels.push(audio.sawtooth.output());
+        }
+        assert_eq!(levels, [0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 0]);
+    }
+
+    #[test]
+    fn frequency_control_halts() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0xB000, 0x08);
+        audio.write(0xB002, 0x80);
+        audio.write(0x9003, 0x01);
+        for _ in 0..10 {
+            audio.clock();
+        }
+        assert_eq!(audio.sawtooth.output(), 0);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut audio = Vrc6Audio::new();
+        audio.write(0x9000, 0x3F);
+        audio.write(0xB002, 0x85);
+        let state = audio.save_state();
+
+        let mut restored = Vrc6Audio::new();
+        restored.load_state(&state);
+        assert_eq!(restored.save_state(), state);
+    }
+}
@@ -0,0 +1,251 @@
+mod opll;
+
+use crate::mapper::vrc7::opll::Opll;
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{Mapper, MapperError, board_ppu_read, board_ppu_write};
+use crate::rom_loader::Nametable;
+
+const PRG_BANK_SIZE: usize = 8 * 1024;
+const CHR_BANK_SIZE: usize = 1024;
+const STATE_SIZE: usize = 13 + 5 + 0x40;
+
+//Mapper 85. Three 8K PRG banks, eight 1K CHR banks, the VRC IRQ counter and an FM sound
+//chip. Lagrange Point (VRC7a, submapper 2) has the second register of each pair on A4,
+//Tiny Toon Adventures 2 (VRC7b, submapper 1) on A3. The audio ports are always at
+//$9010/$9030
+pub struct Vrc7 {
+    select: u16,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    //$E000: bits 0-1 mirroring, bit 6 holds the sound chip in reset, bit 7 PRG RAM enable
+    control: u8,
+    audio_register: u8,
+    irq: VrcIrq,
+    audio: Opll,
+}
+impl Vrc7 {
+    pub fn new(submapper: u8) -> Self {
+        Self {
+            select: match submapper {
+                1 => 0x08,
+                2 => 0x10,
+                _ => 0x18,
+            },
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            audio_register: 0,
+            irq: VrcIrq::new(),
+            audio: Opll::new(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        match address & self.select {
+            0 => address & 0xF000,
+            _ => (address & 0xF000) | 0x10,
+        }
+    }
+    fn prg_address(&self, prg_rom: &[u8], address: u16) -> usize {
+        let banks = (prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let bank = match address {
+            0xE000..=0xFFFF => banks - 1,
+            _ => self.prg_banks[(address as usize - 0x8000) >> 13] as usize & 0x3F,
+        };
+        ((bank % banks) * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % prg_rom.len()
+    }
+    fn chr_address(&self, address: u16) -> usize {
+        self.chr_banks[address as usize >> 10] as usize * CHR_BANK_SIZE + (address as usize & 0x03FF)
+    }
+    fn prg_ram_enabled(&self) -> bool {
+        self.control & 0x80 != 0
+    }
+    fn audio_reset(&self) -> bool {
+        self.control & 0x40 != 0
+    }
+}
+impl Mapper for Vrc7 {
+    fn cpu_read(&mut self, prg_rom: &[u8], prg_ram: &[u8], address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !prg_ram.is_empty() => {
+                prg_ram[(address as usize - 0x6000) % prg_ram.len()]
+            }
+            0x8000..=0xFFFF => prg_rom[self.prg_address(prg_rom, address)],
+            _ => 0,
+        }
+    }
+    fn cpu_write(&mut self, _prg_rom: &[u8], prg_ram: &mut [u8], address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled() && !prg_ram.is_empty() {
+                    let prg_ram_size = prg_ram.len();
+                    prg_ram[(address as usize - 0x6000) % prg_ram_size] = value;
+                }
+                return;
+            }
+            //The sound chip decodes A4 and A5 itself
+            _ if address & 0xF030 == 0x9010 => {
+                self.audio_register = value;
+                return;
+            }
+            _ if address & 0xF030 == 0x9030 => {
+                if !self.audio_reset() {
+                    self.audio.write(self.audio_register, value);
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        match self.register(address) {
+            0x8000 => self.prg_banks[0] = value,
+            0x8010 => self.prg_banks[1] = value,
+            0x9000 => self.prg_banks[2] = value,
+            register @ 0xA000..=0xD010 => {
+                let bank = ((register as usize - 0xA000) >> 12) * 2 + ((register as usize >> 4) & 0x01);
+                self.chr_banks[bank] = value;
+            }
+            0xE000 => {
+                self.control = value;
+                if self.audio_reset() {
+                    self.audio = Opll::new();
+                }
+            }
+            0xE010 => self.irq.write_latch(value),
+            0xF000 => self.irq.write_control(value),
+            0xF010 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+    fn ppu_read(&mut self, chr_rom: &[u8], chr_ram: &[u8], vram: &[u8], address: u16) -> u8 {
+        board_ppu_read(chr_rom, chr_ram, vram, self.mirroring(), address, |address| self.chr_address(address))
+    }
+    fn ppu_write(&mut self, chr_rom: &[u8], chr_ram: &mut [u8], vram: &mut [u8], address: u16, value: u8) {
+        board_ppu_write(chr_rom, chr_ram, vram, self.mirroring(), address, value, |address| self.chr_address(address));
+    }
+    fn mirroring(&self) -> Nametable {
+        match self.control & 0x03 {
+            0 => Nametable::Vertical,
+            1 => Nametable::Horizontal,
+            2 => Nametable::SingleScreenLower,
+            _ => Nametable::SingleScreenUpper,
+        }
+    }
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+        if !self.audio_reset() {
+            self.audio.clock();
+        }
+    }
+    fn audio_output(&self) -> f32 {
+        match self.audio_reset() {
+            true => 0.0,
+            false => self.audio.output(),
+        }
+    }
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = self.prg_banks.to_vec();
+        state.extend_from_slice(&self.chr_banks);
+        state.extend_from_slice(&[self.control, self.audio_register]);
+        state.extend_from_slice(&self.irq.save_state());
+        state.extend_from_slice(&self.audio.save_state());
+        state
+    }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), MapperError> {
+        if state.len() != STATE_SIZE {
+            return Err(MapperError::InvalidState);
+        }
+        self.prg_banks.copy_from_slice(&state[0..3]);
+        self.chr_banks.copy_from_slice(&state[3..11]);
+        self.control = state[11];
+        self.audio_register = state[12];
+        self.irq.load_state(&state[13..18]);
+        self.audio.load_state(&state[18..]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::banked_rom;
+
+    #[test]
+    fn prg_banking_on_both_variants() {
+        let prg_rom = banked_rom(PRG_BANK_SIZE, 32);
+        for (submapper, second) in [(2, 0x8010), (1, 0x8008), (0, 0x8010), (0, 0x8008)] {
+            let mut board = Vrc7::new(submapper);
+            board.cpu_write(&[], &mut [], 0x8000, 4);
+            board.cpu_write(&[], &mut [], second, 5);
+            board.cpu_write(&[], &mut [], 0x9000, 6);
+            assert_eq!(board.cpu_read(&prg_rom, &[], 0x8000), 4);
+            assert_eq!(board.cpu_read(&prg_rom, &[], 0xA000), 5);
+            assert_eq!(board.cpu_read(&prg_rom, &[], 0xC000), 6);
+            assert_eq!(board.cpu_read(&prg_rom, &[], 0xE000), 31);
+        }
+    }
+
+    #[test]
+    fn chr_banking_and_mirroring() {
+        let mut board = Vrc7::new(2);
+        let chr_rom = banked_rom(CHR_BANK_SIZE, 256);
+        let vram = [0x00; 0x1000];
+
+        board.cpu_write(&[], &mut [], 0xA010, 0x21);
+        board.cpu_write(&[], &mut [], 0xD010, 0x87);
+        assert_eq!(board.ppu_read(&chr_rom, &[], &vram, 0x0400), 0x21);
+        assert_eq!(board.ppu_read(&chr_rom, &[], &vram, 0x1C00), 0x87);
+
+        board.cpu_write(&[], &mut [], 0xE000, 0x01);
+        assert_eq!(board.mirroring(), Nametable::Horizontal);
+    }
+
+    #[test]
+    fn irq_registers() {
+        let mut board = Vrc7::new(2);
+        board.cpu_write(&[], &mut [], 0xE010, 0xFF);
+        board.cpu_write(&[], &mut [], 0xF000, 0x06);
+        board.cpu_clock();
+        assert!(board.irq());
+        board.cpu_write(&[], &mut [], 0xF010, 0x00);
+        assert!(!board.irq());
+    }
+
+    #[test]
+    fn audio_ports_and_reset() {
+        let mut board = Vrc7::new(2);
+        //Instrument 3 at full volume, key on
+        for (register, value) in [(0x30, 0x30), (0x10, 0x80), (0x20, 0x18)] {
+            board.cpu_write(&[], &mut [], 0x9010, register);
+            board.cpu_write(&[], &mut [], 0x9030, value);
+        }
+        let mut heard = false;
+        for _ in 0..36 * 500 {
+            board.cpu_clock();
+            heard |= board.audio_output() != 0.0;
+        }
+        assert!(heard);
+
+        //Holding the chip in reset silences it
+        board.cpu_write(&[], &mut [], 0xE000, 0x40);
+        board.cpu_clock();
+        assert_eq!(board.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut board = Vrc7::new(1);
+        board.cpu_write(&[], &mut [], 0x8008, 0x03);
+        board.cpu_write(&[], &mut [], 0xC000, 0x44);
+        board.cpu_write(&[], &mut [], 0x9010, 0x30);
+        board.cpu_write(&[], &mut [], 0x9030, 0x51);
+        let state = board.save_state();
+
+        let mut restored = Vrc7::new(1);
+        assert_eq!(restored.load_state(&state), Ok(()));
+        assert_eq!(restored.save_state(), state);
+    }
+}
@@ -0,0 +1,444 @@
+use std::f32::consts::TAU;
+
+//The VRC7's sound core is a cut down YM2413 (OPLL): six two operator FM channels, 15 fixed
+//instruments and one user defined one. This is a floating point model of it rather than a
+//bit exact one. Envelope times, key scaling and the LFOs follow the documented curves
+//closely enough to sound right, but not to match sample for sample
+
+//CPU cycles per OPLL sample. The VRC7 is fed the CPU clock and divides it by 36
+const CLOCK_DIVIDER: u32 = 36;
+const SAMPLE_RATE: f32 = 1_789_773.0 / CLOCK_DIVIDER as f32;
+
+//Built in instruments 1-15 as dumped from the VRC7. Same layout as registers $00-$07
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+//Frequency multipliers. 11 and 13 repeat their neighbours, 14 reads as 15
+const MULTIPLIERS: [f32; 16] = [0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0];
+
+//Key scale attenuation in dB at block 7 by the top 4 bits of the frequency number. Drops by
+//6 dB per block below that, scaled by the patch's KSL setting
+const KSL_TABLE: [f32; 16] = [
+    0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625, 18.0, 18.75, 19.125, 19.5, 19.875, 20.25, 20.625, 21.0,
+];
+const KSL_SCALE: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+//The envelope covers 48 dB, anything quieter counts as silent
+const MAX_ATTENUATION: f32 = 48.0;
+//Seconds for a full decay and for an attack at rate 1, halving every rate step after that
+const DECAY_TIME: f32 = 10.0;
+const ATTACK_TIME: f32 = 2.0;
+
+//Tremolo and vibrato LFOs
+const AM_FREQUENCY: f32 = 3.7;
+const AM_DEPTH: f32 = 4.8;
+const VIBRATO_FREQUENCY: f32 = 6.4;
+const VIBRATO_CENTS: f32 = 14.0;
+
+//Phase shift in cycles a full scale modulator applies to its carrier
+const MODULATION_DEPTH: f32 = 2.0;
+
+//A full volume channel ends up about as loud as an APU pulse
+const LEVEL_SCALE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+#[derive(Clone, Copy)]
+struct Operator {
+    //Position within the current cycle, 0.0-1.0
+    phase: f32,
+    //Envelope level in dB below full scale
+    attenuation: f32,
+    state: EnvelopeState,
+    output: f32,
+    previous_output: f32,
+}
+impl Operator {
+    fn new() -> Self {
+        Self {
+            phase: 0.0,
+            attenuation: MAX_ATTENUATION,
+            state: EnvelopeState::Off,
+            output: 0.0,
+            previous_output: 0.0,
+        }
+    }
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.state = EnvelopeState::Attack;
+    }
+    fn key_off(&mut self) {
+        if self.state != EnvelopeState::Off {
+            self.state = EnvelopeState::Release;
+        }
+    }
+
+    //Advance the envelope by one sample. Rates are the chip's internal 0-63 values: the
+    //patch's 4 bit rate times 4 plus key scaling
+    fn clock_envelope(&mut self, attack: u8, decay: u8, sustain_level: f32, sustained: bool, release: u8) {
+        match self.state {
+            EnvelopeState::Attack if attack >= 60 => {
+                self.attenuation = 0.0;
+                self.state = EnvelopeState::Decay;
+            }
+            EnvelopeState::Attack => {
+                //Exponential approach to full volume
+                let samples = ATTACK_TIME / rate_scale(attack) * SAMPLE_RATE;
+                self.attenuation -= self.attenuation * 4.0 / samples + MAX_ATTENUATION / (samples * 32.0);
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.attenuation += decay_step(decay);
+                if self.attenuation >= sustain_level {
+                    self.attenuation = sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            //Sustained tones hold while the key is down, percussive ones keep fading
+            EnvelopeState::Sustain if sustained => {}
+            EnvelopeState::Sustain | EnvelopeState::Release => {
+                self.attenuation += decay_step(release);
+                if self.attenuation >= MAX_ATTENUATION {
+                    self.attenuation = MAX_ATTENUATION;
+                    self.state = EnvelopeState::Off;
+                }
+            }
+            EnvelopeState::Off => {}
+        }
+    }
+
+    //Sine or, with the patch's rectify bit, only its positive half
+    fn wave(phase: f32, half_sine: bool) -> f32 {
+        let value = (phase * TAU).sin();
+        match half_sine && value < 0.0 {
+            true => 0.0,
+            false => value,
+        }
+    }
+}
+
+//Speed up from rate 4 (the slowest usable one), doubling every 4 steps
+fn rate_scale(rate: u8) -> f32 {
+    2f32.powf((rate as f32 - 4.0) / 4.0)
+}
+//dB added per sample while decaying or releasing
+fn decay_step(rate: u8) -> f32 {
+    match rate {
+        0..=3 => 0.0,
+        _ => MAX_ATTENUATION * rate_scale(rate) / (DECAY_TIME * SAMPLE_RATE),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    frequency: u16,
+    block: u8,
+    key_on: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+impl Channel {
+    fn new() -> Self {
+        Self {
+            frequency: 0,
+            block: 0,
+            key_on: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+        }
+    }
+}
+
+pub struct Opll {
+    //Register $00-$07, the user defined instrument
+    custom_patch: [u8; 8],
+    channels: [Channel; 6],
+    divider: u32,
+    //Samples generated, drives the LFOs
+    samples: u64,
+    output: f32,
+    //Every register as last written, for save states
+    registers: [u8; 0x40],
+}
+impl Opll {
+    pub fn new() -> Self {
+        Self {
+            custom_patch: [0; 8],
+            channels: [Channel::new(); 6],
+            divider: 0,
+            samples: 0,
+            output: 0.0,
+            registers: [0; 0x40],
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = register & 0x3F;
+        self.registers[register as usize] = value;
+        let channel = (register & 0x0F) as usize;
+        match register {
+            0x00..=0x07 => self.custom_patch[register as usize] = value,
+            0x10..=0x15 => {
+                self.channels[channel].frequency = (self.channels[channel].frequency & 0x100) | value as u16;
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[channel];
+                channel.frequency = (channel.frequency & 0xFF) | (value as u16 & 0x01) << 8;
+                channel.block = (value >> 1) & 0x07;
+                channel.sustain = value & 0x20 != 0;
+                let key_on = value & 0x10 != 0;
+                if key_on && !channel.key_on {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !key_on && channel.key_on {
+                    channel.modulator.key_off();
+                    channel.carrier.key_off();
+                }
+                channel.key_on = key_on;
+            }
+            0x30..=0x35 => {
+                self.channels[channel].instrument = value >> 4;
+                self.channels[channel].volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    //Once per CPU cycle. Generates a new sample every 36
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider == CLOCK_DIVIDER {
+            self.divider = 0;
+            self.output = self.generate_sample();
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output * LEVEL_SCALE
+    }
+
+    fn patch(&self, instrument: u8) -> [u8; 8] {
+        match instrument {
+            0 => self.custom_patch,
+            _ => PATCHES[instrument as usize - 1],
+        }
+    }
+
+    fn generate_sample(&mut self) -> f32 {
+        let time = self.samples as f32 / SAMPLE_RATE;
+        self.samples += 1;
+        let tremolo = AM_DEPTH * (1.0 - (time * AM_FREQUENCY * TAU).cos()) / 2.0;
+        let vibrato = 2f32.powf(VIBRATO_CENTS / 1200.0 * (time * VIBRATO_FREQUENCY * TAU).sin());
+
+        let mut mix = 0.0;
+        for index in 0..self.channels.len() {
+            let patch = self.patch(self.channels[index].instrument);
+            mix += Self::clock_channel(&mut self.channels[index], &patch, tremolo, vibrato);
+        }
+        mix
+    }
+
+    fn clock_channel(channel: &mut Channel, patch: &[u8; 8], tremolo: f32, vibrato: f32) -> f32 {
+        let key_code = channel.block << 1 | (channel.frequency >> 8) as u8;
+        let ksl_base = (KSL_TABLE[(channel.frequency >> 5) as usize & 0x0F] - 6.0 * (7 - channel.block) as f32).max(0.0);
+
+        let mut levels = [0.0; 2];
+        for (slot, level) in levels.iter_mut().enumerate() {
+            let flags = patch[slot];
+            let operator = match slot {
+                0 => &mut channel.modulator,
+                _ => &mut channel.carrier,
+            };
+
+            //Phase
+            let mut increment = channel.frequency as f32 * (1 << channel.block) as f32 * MULTIPLIERS[flags as usize & 0x0F]
+                / (1 << 19) as f32;
+            if flags & 0x40 != 0 {
+                increment *= vibrato;
+            }
+            operator.phase = (operator.phase + increment).fract();
+
+            //Envelope, with key scaling speeding up higher notes
+            let key_scale = match flags & 0x10 {
+                0 => key_code >> 2,
+                _ => key_code,
+            };
+            let rate = |value: u8| match value {
+                0 => 0,
+                _ => (value * 4 + key_scale).min(63),
+            };
+            let sustained = flags & 0x20 != 0;
+            let release = match operator.state {
+                EnvelopeState::Release if channel.sustain => rate(5),
+                EnvelopeState::Release if !sustained => rate(7),
+                _ => rate(patch[6 + slot] & 0x0F),
+            };
+            operator.clock_envelope(
+                rate(patch[4 + slot] >> 4),
+                rate(patch[4 + slot] & 0x0F),
+                (patch[6 + slot] >> 4) as f32 * 3.0,
+                sustained,
+                release,
+            );
+
+            let mut attenuation = operator.attenuation + ksl_base * KSL_SCALE[(patch[2 + slot] >> 6) as usize];
+            attenuation += match slot {
+                0 => (patch[2] & 0x3F) as f32 * 0.75,
+                _ => channel.volume as f32 * 3.0,
+            };
+            if flags & 0x80 != 0 {
+                attenuation += tremolo;
+            }
+            *level = match operator.state {
+                EnvelopeState::Off => 0.0,
+                _ => 10f32.powf(-attenuation / 20.0),
+            };
+        }
+
+        //Modulator with self feedback, averaged over its last two outputs
+        let feedback = match patch[3] & 0x07 {
+            0 => 0.0,
+            shift => (channel.modulator.output + channel.modulator.previous_output) * 2f32.powi(shift as i32 - 8),
+        };
+        let modulator = Operator::wave(channel.modulator.phase + feedback, patch[3] & 0x08 != 0) * levels[0];
+        channel.modulator.previous_output = channel.modulator.output;
+        channel.modulator.output = modulator;
+
+        let carrier = Operator::wave(channel.carrier.phase + modulator * MODULATION_DEPTH, patch[3] & 0x10 != 0) * levels[1];
+        channel.carrier.output = carrier;
+        carrier
+    }
+
+    pub fn save_state(&self) -> [u8; 0x40] {
+        self.registers
+    }
+    //Replays the registers, so held notes restart from their attack
+    pub fn load_state(&mut self, state: &[u8]) {
+        *self = Opll::new();
+        for (register, &value) in state.iter().enumerate().take(0x40) {
+            self.write(register as u8, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_samples(opll: &mut Opll, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|_| {
+                for _ in 0..CLOCK_DIVIDER {
+                    opll.clock();
+                }
+                opll.output()
+            })
+            .collect()
+    }
+
+    //Custom instrument with a silent modulator and an instant, sustained carrier: a plain sine
+    fn sine_patch(opll: &mut Opll) {
+        for (register, value) in [0x01, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x0F, 0x0F].into_iter().enumerate() {
+            opll.write(register as u8, value);
+        }
+    }
+
+    #[test]
+    fn silent_until_key_on() {
+        let mut opll = Opll::new();
+        opll.write(0x30, 0x10);
+        opll.write(0x10, 0x20);
+        assert!(run_samples(&mut opll, 100).iter().all(|&sample| sample == 0.0));
+
+        opll.write(0x20, 0x18);
+        assert!(run_samples(&mut opll, 2000).iter().any(|&sample| sample.abs() > 0.01));
+    }
+
+    #[test]
+    fn frequency_follows_fnum_and_block() {
+        let mut opll = Opll::new();
+        sine_patch(&mut opll);
+        //F-num 288 in block 4 is about 437 Hz
+        opll.write(0x10, 0x20);
+        opll.write(0x20, 0x19);
+
+        let samples = run_samples(&mut opll, SAMPLE_RATE as usize);
+        let crossings = samples.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0).count();
+        assert!((430..=445).contains(&crossings), "{} crossings", crossings);
+    }
+
+    #[test]
+    fn volume_attenuates() {
+        let peak = |volume: u8| {
+            let mut opll = Opll::new();
+            sine_patch(&mut opll);
+            opll.write(0x30, volume);
+            opll.write(0x10, 0x20);
+            opll.write(0x20, 0x19);
+            run_samples(&mut opll, 500).iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+        //3 dB per step, so 2 steps is about half the amplitude
+        let ratio = peak(2) / peak(0);
+        assert!((0.45..0.55).contains(&ratio), "ratio {}", ratio);
+    }
+
+    #[test]
+    fn key_off_releases_to_silence() {
+        let mut opll = Opll::new();
+        sine_patch(&mut opll);
+        opll.write(0x10, 0x20);
+        opll.write(0x20, 0x19);
+        run_samples(&mut opll, 500);
+
+        //Release rate 15 takes a few milliseconds
+        opll.write(0x20, 0x09);
+        run_samples(&mut opll, 1000);
+        assert_eq!(opll.channels[0].carrier.state, EnvelopeState::Off);
+        assert_eq!(opll.output(), 0.0);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut opll = Opll::new();
+        opll.write(0x31, 0x52);
+        opll.write(0x11, 0x80);
+        opll.write(0x21, 0x1C);
+        let state = opll.save_state();
+
+        let mut restored = Opll::new();
+        restored.load_state(&state);
+        assert_eq!(restored.save_state(), state);
+        assert!(restored.channels[1].key_on);
+    }
+}
@@ -0,0 +1,179 @@
+//Prescaler reload. Scanline mode counts 341 PPU dots per line in steps of 3 per CPU cycle,
+//which averages out to 113 2/3 cycles a line
+const PRESCALER_PERIOD: i16 = 341;
+
+//IRQ counter shared by VRC4, VRC6 and VRC7. An 8 bit up counter that fires and reloads
+//from the latch when it overflows, clocked every CPU cycle or once per scanline through
+//the prescaler. The chip has no view of the PPU, the scanline mode is purely timed
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+impl VrcIrq {
+    pub fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+    //VRC4 splits the latch over two registers, one nibble each
+    pub fn write_latch_low(&mut self, value: u8) {
+        self.latch = (self.latch & 0xF0) | (value & 0x0F);
+    }
+    pub fn write_latch_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x0F) | (value << 4);
+    }
+    //Bit 0 re-enables on acknowledge, bit 1 enables, bit 2 selects cycle mode. Enabling
+    //reloads the counter and prescaler, any write clears a pending IRQ
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = value & 0x01 != 0;
+        self.enabled = value & 0x02 != 0;
+        self.cycle_mode = value & 0x04 != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+        self.pending = false;
+    }
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    //Once per CPU cycle
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock_counter();
+            }
+        }
+    }
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn save_state(&self) -> [u8; 5] {
+        let [prescaler_low, prescaler_high] = self.prescaler.to_le_bytes();
+        let flags = self.enabled as u8
+            | (self.enable_after_ack as u8) << 1
+            | (self.cycle_mode as u8) << 2
+            | (self.pending as u8) << 3;
+        [self.latch, self.counter, prescaler_low, prescaler_high, flags]
+    }
+    pub fn load_state(&mut self, state: &[u8]) {
+        self.latch = state[0];
+        self.counter = state[1];
+        self.prescaler = i16::from_le_bytes([state[2], state[3]]);
+        self.enabled = state[4] & 0x01 != 0;
+        self.enable_after_ack = state[4] & 0x02 != 0;
+        self.cycle_mode = state[4] & 0x04 != 0;
+        self.pending = state[4] & 0x08 != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_mode_fires_on_overflow() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFD);
+        irq.write_control(0x06);
+
+        //$FD -> $FE -> $FF -> overflow
+        irq.clock();
+        irq.clock();
+        assert!(!irq.pending());
+        irq.clock();
+        assert!(irq.pending());
+        assert_eq!(irq.counter, 0xFD);
+    }
+
+    #[test]
+    fn scanline_mode_uses_prescaler() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFE);
+        irq.write_control(0x02);
+
+        //Two scanlines is 682 dots, or 228 CPU cycles at 3 dots each
+        for _ in 0..227 {
+            irq.clock();
+        }
+        assert!(!irq.pending());
+        irq.clock();
+        assert!(irq.pending());
+    }
+
+    #[test]
+    fn acknowledge_restores_enable_after_ack() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0xFF);
+        irq.write_control(0x06);
+        irq.clock();
+        assert!(irq.pending());
+
+        //A clear, so acknowledging stops the counter
+        irq.acknowledge();
+        assert!(!irq.pending());
+        irq.clock();
+        assert!(!irq.pending());
+
+        irq.write_control(0x07);
+        irq.clock();
+        irq.acknowledge();
+        irq.clock();
+        assert!(irq.pending());
+    }
+
+    #[test]
+    fn nibble_latch_writes() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch_low(0x0C);
+        irq.write_latch_high(0x0A);
+        assert_eq!(irq.latch, 0xAC);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut irq = VrcIrq::new();
+        irq.write_latch(0x80);
+        irq.write_control(0x03);
+        irq.clock();
+        let state = irq.save_state();
+
+        let mut restored = VrcIrq::new();
+        restored.load_state(&state);
+        assert_eq!(restored.save_state(), state);
+    }
+}
@@ -0,0 +1,1048 @@
+use crate::ppu_bus::PpuBus;
+use crate::region::Region;
+
+//Scanlines 0-239 are visible on every region. The VBlank start and the pre-render line
+//(always the last one) come from the region, e.g. NTSC has VBlank on 241-260 and pre-renders on 261
+const DOTS_PER_SCANLINE: u16 = 341;
+const VISIBLE_SCANLINES: u16 = 240;
+
+//Number of dots between the NMI line going high and the CPU seeing the edge.
+//Dropping the line inside this window (PPUCTRL bit 7 cleared, PPUSTATUS read) cancels the NMI
+const NMI_DELAY_DOTS: u8 = 2;
+
+//Open bus bits fade to 0 roughly 600ms after they were last driven
+const OPEN_BUS_DECAY_FRAMES: u8 = 36;
+
+pub struct Ppu {
+    //CPU visible registers
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_addr: u8,
+
+    //Internal scroll/address registers. v is the current VRAM address, t the temporary
+    //address, x the fine X scroll and w the shared $2005/$2006 write toggle
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+
+    //PPUDATA reads below $3F00 return the previous contents of this buffer
+    read_buffer: u8,
+
+    //Data bus latch seen when reading write-only registers, with a decay timer per bit
+    io_latch: u8,
+    io_latch_decay: [u8; 8],
+
+    //Internal memory
+    palette: [u8; 32],
+    oam: [u8; 256],
+
+    //Sprites found for the next scanline. Unused slots stay $FF, which is what the
+    //hardware fetches for empty slots too
+    secondary_oam: [u8; 32],
+
+    //Nametable byte for the tile being fetched, needed to form the pattern address
+    nametable_latch: u8,
+
+    //Timing. cycle counts every dot since power on and timestamps bus activity
+    region: Region,
+    scanline: u16,
+    dot: u16,
+    odd_frame: bool,
+    cycle: u64,
+    frame: u64,
+
+    //NMI state
+    nmi_previous: bool,
+    nmi_delay: u8,
+    nmi_pending: bool,
+    suppress_vblank: bool,
+}
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: 0x00,
+            mask: 0x00,
+            status: 0x00,
+            oam_addr: 0x00,
+            v: 0x0000,
+            t: 0x0000,
+            x: 0x00,
+            w: false,
+            read_buffer: 0x00,
+            io_latch: 0x00,
+            io_latch_decay: [0; 8],
+            palette: [0x00; 32],
+            oam: [0x00; 256],
+            secondary_oam: [0xFF; 32],
+            nametable_latch: 0x00,
+            region: Region::Ntsc,
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            cycle: 0,
+            frame: 0,
+            nmi_previous: false,
+            nmi_delay: 0,
+            nmi_pending: false,
+            suppress_vblank: false,
+        }
+    }
+    //PPUCTRL flags
+    pub const CTRL_VRAM_INCREMENT: u8 = 0x04;
+    pub const CTRL_SPRITE_TABLE: u8 = 0x08;
+    pub const CTRL_BACKGROUND_TABLE: u8 = 0x10;
+    pub const CTRL_SPRITE_SIZE: u8 = 0x20;
+    pub const CTRL_NMI_ENABLE: u8 = 0x80;
+
+    //PPUMASK flags
+    pub const MASK_GRAYSCALE: u8 = 0x01;
+    pub const MASK_SHOW_BACKGROUND: u8 = 0x08;
+    pub const MASK_SHOW_SPRITES: u8 = 0x10;
+
+    //PPUSTATUS flags
+    pub const STATUS_SPRITE_OVERFLOW: u8 = 0x20;
+    pub const STATUS_SPRITE_ZERO_HIT: u8 = 0x40;
+    pub const STATUS_VBLANK: u8 = 0x80;
+
+    pub fn reset(&mut self) {
+        //Reset leaves PPUSTATUS, OAMADDR and v untouched, but clears the write toggle and buffer
+        self.ctrl = 0x00;
+        self.mask = 0x00;
+        self.t = 0x0000;
+        self.x = 0x00;
+        self.w = false;
+        self.read_buffer = 0x00;
+        self.scanline = 0;
+        self.dot = 0;
+        self.odd_frame = false;
+        self.nmi_previous = false;
+        self.nmi_delay = 0;
+        self.nmi_pending = false;
+        self.suppress_vblank = false;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        if self.scanline >= region.scanlines_per_frame() {
+            self.scanline = 0;
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    //Dots since power on
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    //Frames completed since power on. Ticks over when the pre-render line wraps to line 0
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask & (Self::MASK_SHOW_BACKGROUND | Self::MASK_SHOW_SPRITES) != 0
+    }
+
+    //The NMI line is the AND of the VBlank flag and PPUCTRL bit 7. The CPU only reacts to
+    //its rising edge, so enabling NMI during VBlank raises a second one
+    fn update_nmi(&mut self) {
+        let nmi_line = self.status & Self::STATUS_VBLANK != 0 && self.ctrl & Self::CTRL_NMI_ENABLE != 0;
+
+        if nmi_line && !self.nmi_previous {
+            self.nmi_delay = NMI_DELAY_DOTS;
+        }
+        if !nmi_line {
+            self.nmi_delay = 0;
+        }
+        self.nmi_previous = nmi_line;
+    }
+
+    //Returns true once per NMI edge. Used by the bus to forward the NMI to the CPU
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    //Every PPU memory access goes through here so the cartridge sees the address first
+    fn bus_read(&mut self, bus: &mut impl PpuBus, address: u16) -> u8 {
+        bus.address_driven(address, self.cycle);
+        bus.ppu_read(address)
+    }
+
+    //Advance the PPU by a single dot
+    pub fn clock(&mut self, bus: &mut impl PpuBus) {
+        if self.rendering_active() {
+            self.render_fetches(bus);
+        }
+
+        if self.scanline == self.region.vblank_scanline() && self.dot == 1 {
+            if !self.suppress_vblank {
+                self.status |= Self::STATUS_VBLANK;
+            }
+            self.suppress_vblank = false;
+            self.update_nmi();
+        }
+
+        if self.scanline == self.region.pre_render_scanline() && self.dot == 1 {
+            self.status &= !(Self::STATUS_VBLANK | Self::STATUS_SPRITE_ZERO_HIT | Self::STATUS_SPRITE_OVERFLOW);
+            self.update_nmi();
+        }
+
+        if self.nmi_delay > 0 {
+            self.nmi_delay -= 1;
+            if self.nmi_delay == 0 && self.nmi_previous {
+                self.nmi_pending = true;
+            }
+        }
+
+        self.advance_dot();
+        self.cycle += 1;
+    }
+
+    //Memory fetches and scroll updates made while rendering. Pixel output is not
+    //implemented yet, so the fetched tile data is discarded, but the addresses reach
+    //the cartridge on the same dots as on hardware
+    fn render_fetches(&mut self, bus: &mut impl PpuBus) {
+        let dot = self.dot;
+        match dot {
+            //Background tiles for this line, then the first two tiles of the next one
+            1..=256 | 321..=336 => {
+                self.background_fetch(bus, (dot - 1) % 8);
+                if dot.is_multiple_of(8) {
+                    self.increment_coarse_x();
+                }
+                if dot == 256 {
+                    self.increment_y();
+                }
+            }
+            //Sprite pattern fetches for the next line
+            257..=320 => {
+                if dot == 257 {
+                    self.copy_horizontal();
+                    if self.scanline < VISIBLE_SCANLINES {
+                        self.evaluate_sprites();
+                    }
+                }
+                self.sprite_fetch(bus, (dot - 257) / 8, (dot - 257) % 8);
+                if self.scanline == self.region.pre_render_scanline() && (280..=304).contains(&dot) {
+                    self.copy_vertical();
+                }
+            }
+            //Two unused nametable fetches end the line
+            337 | 339 => {
+                self.bus_read(bus, 0x2000 | (self.v & 0x0FFF));
+            }
+            _ => {}
+        }
+    }
+
+    fn background_fetch(&mut self, bus: &mut impl PpuBus, phase: u16) {
+        let fine_y = (self.v >> 12) & 0x07;
+        let table = if self.ctrl & Self::CTRL_BACKGROUND_TABLE != 0 { 0x1000 } else { 0x0000 };
+        match phase {
+            0 => self.nametable_latch = self.bus_read(bus, 0x2000 | (self.v & 0x0FFF)),
+            2 => {
+                let address = 0x23C0 | (self.v & 0x0C00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
+                self.bus_read(bus, address);
+            }
+            4 => {
+                self.bus_read(bus, table | (self.nametable_latch as u16) << 4 | fine_y);
+            }
+            6 => {
+                self.bus_read(bus, table | (self.nametable_latch as u16) << 4 | fine_y | 0x08);
+            }
+            _ => {}
+        }
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl & Self::CTRL_SPRITE_SIZE != 0 { 16 } else { 8 }
+    }
+
+    //Find up to 8 sprites covering the next scanline
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam = [0xFF; 32];
+        let height = self.sprite_height();
+
+        let mut found = 0;
+        for sprite in self.oam.chunks_exact(4) {
+            let row = self.scanline.wrapping_sub(sprite[0] as u16);
+            if row < height {
+                if found == 8 {
+                    self.status |= Self::STATUS_SPRITE_OVERFLOW;
+                    break;
+                }
+                self.secondary_oam[found * 4..found * 4 + 4].copy_from_slice(sprite);
+                found += 1;
+            }
+        }
+    }
+
+    fn sprite_fetch(&mut self, bus: &mut impl PpuBus, slot: u16, phase: u16) {
+        let sprite = &self.secondary_oam[slot as usize * 4..slot as usize * 4 + 4];
+        let (y, tile, attributes) = (sprite[0] as u16, sprite[1] as u16, sprite[2]);
+
+        //Empty slots still fetch tile $FF, which is what lets 8x16 sprites toggle A12
+        let height = self.sprite_height();
+        let mut row = self.scanline.wrapping_sub(y);
+        if row >= height {
+            row = 0;
+        } else if attributes & 0x80 != 0 {
+            row = height - 1 - row;
+        }
+        let address = if height == 16 {
+            let table = (tile & 0x01) << 12;
+            let tile = (tile & 0xFE) + (row >> 3);
+            table | tile << 4 | (row & 0x07)
+        } else {
+            let table = if self.ctrl & Self::CTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0x0000 };
+            table | tile << 4 | row
+        };
+
+        match phase {
+            //Garbage nametable fetches in place of the background's nametable/attribute
+            0 | 2 => {
+                self.bus_read(bus, 0x2000 | (self.v & 0x0FFF));
+            }
+            4 => {
+                self.bus_read(bus, address);
+            }
+            6 => {
+                self.bus_read(bus, address | 0x08);
+            }
+            _ => {}
+        }
+    }
+
+    fn copy_horizontal(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_vertical(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    fn advance_dot(&mut self) {
+        //NTSC odd frames skip the last dot of the pre-render line when rendering is on
+        if self.scanline == self.region.pre_render_scanline()
+            && self.dot == DOTS_PER_SCANLINE - 2
+            && self.odd_frame
+            && self.rendering_enabled()
+            && self.region.skips_odd_frame_dot()
+        {
+            self.dot = DOTS_PER_SCANLINE - 1;
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == self.region.scanlines_per_frame() {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+                self.frame += 1;
+                self.decay_io_latch();
+            }
+        }
+    }
+
+    //Drive the bits in drive_mask onto the data bus and restart their decay timers
+    fn refresh_io_latch(&mut self, value: u8, drive_mask: u8) {
+        self.io_latch = (self.io_latch & !drive_mask) | (value & drive_mask);
+        for (bit, decay) in self.io_latch_decay.iter_mut().enumerate() {
+            if drive_mask & (1 << bit) != 0 {
+                *decay = OPEN_BUS_DECAY_FRAMES;
+            }
+        }
+    }
+
+    fn decay_io_latch(&mut self) {
+        for (bit, decay) in self.io_latch_decay.iter_mut().enumerate() {
+            if *decay > 0 {
+                *decay -= 1;
+                if *decay == 0 {
+                    self.io_latch &= !(1 << bit);
+                }
+            }
+        }
+    }
+
+    fn palette_address(address: u16) -> usize {
+        //$3F10/$3F14/$3F18/$3F1C mirror the backdrop entries at $3F00/$3F04/$3F08/$3F0C
+        let index = (address & 0x001F) as usize;
+        if index & 0x13 == 0x10 { index & 0x0F } else { index }
+    }
+
+    fn read_palette(&self, address: u16) -> u8 {
+        let value = self.palette[Self::palette_address(address)];
+        if self.mask & Self::MASK_GRAYSCALE != 0 {
+            value & 0x30
+        } else {
+            value & 0x3F
+        }
+    }
+
+    fn rendering_active(&self) -> bool {
+        self.rendering_enabled()
+            && (self.scanline < VISIBLE_SCANLINES || self.scanline == self.region.pre_render_scanline())
+    }
+
+    fn increment_vram_address(&mut self) {
+        if self.rendering_active() {
+            //While rendering, PPUDATA access bumps coarse X and Y at the same time
+            self.increment_coarse_x();
+            self.increment_y();
+        } else if self.ctrl & Self::CTRL_VRAM_INCREMENT != 0 {
+            self.v = self.v.wrapping_add(32) & 0x7FFF;
+        } else {
+            self.v = self.v.wrapping_add(1) & 0x7FFF;
+        }
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+        } else {
+            self.v &= !0x7000;
+            let mut coarse_y = (self.v & 0x03E0) >> 5;
+            if coarse_y == 29 {
+                coarse_y = 0;
+                self.v ^= 0x0800;
+            } else if coarse_y == 31 {
+                coarse_y = 0;
+            } else {
+                coarse_y += 1;
+            }
+            self.v = (self.v & !0x03E0) | (coarse_y << 5);
+        }
+    }
+
+    fn read_status(&mut self) -> u8 {
+        //Reading one dot before VBlank starts returns the flag clear and stops it being set this frame
+        let vblank_scanline = self.region.vblank_scanline();
+        if self.scanline == vblank_scanline && self.dot == 1 {
+            self.suppress_vblank = true;
+        }
+
+        //Only the top 3 bits are driven, the rest come from open bus
+        let value = (self.status & 0xE0) | (self.io_latch & 0x1F);
+        self.status &= !Self::STATUS_VBLANK;
+        self.w = false;
+        self.update_nmi();
+
+        //Reading on the same dot VBlank is set, or the one after, returns it set but still
+        //swallows the NMI for this frame
+        if self.scanline == vblank_scanline && (self.dot == 2 || self.dot == 3) {
+            self.nmi_pending = false;
+        }
+
+        value
+    }
+
+    fn read_data(&mut self, bus: &mut impl PpuBus) -> u8 {
+        let address = self.v & 0x3FFF;
+        let value = if address >= 0x3F00 {
+            //Palette reads bypass the buffer, but the buffer still gets the nametable byte
+            //hidden underneath the palette
+            self.read_buffer = self.bus_read(bus, address - 0x1000);
+            let value = self.read_palette(address);
+            self.refresh_io_latch(value, 0x3F);
+            self.io_latch
+        } else {
+            let value = self.read_buffer;
+            self.read_buffer = self.bus_read(bus, address);
+            self.refresh_io_latch(value, 0xFF);
+            value
+        };
+
+        self.increment_vram_address();
+        self.drive_vram_address(bus);
+        value
+    }
+
+    fn write_data(&mut self, bus: &mut impl PpuBus, value: u8) {
+        let address = self.v & 0x3FFF;
+        if address >= 0x3F00 {
+            self.palette[Self::palette_address(address)] = value & 0x3F;
+        } else {
+            bus.address_driven(address, self.cycle);
+            bus.ppu_write(address, value);
+        }
+
+        self.increment_vram_address();
+        self.drive_vram_address(bus);
+    }
+
+    //Outside rendering the PPU leaves v on its address bus, so changing v through
+    //$2006/$2007 is visible to the cartridge
+    fn drive_vram_address(&mut self, bus: &mut impl PpuBus) {
+        if !self.rendering_active() {
+            bus.address_driven(self.v & 0x3FFF, self.cycle);
+        }
+    }
+
+    pub fn cpu_read(&mut self, bus: &mut impl PpuBus, address: u16) -> u8 {
+        //Registers are mirrored every 8 bytes from $2000-$3FFF
+        match address & 0x0007 {
+            0x0002 => {
+                let value = self.read_status();
+                self.refresh_io_latch(value, 0xE0);
+                value
+            }
+            0x0004 => {
+                //Attribute bytes have no storage for bits 2-4
+                let mut value = self.oam[self.oam_addr as usize];
+                if self.oam_addr & 0x03 == 0x02 {
+                    value &= 0xE3;
+                }
+                self.refresh_io_latch(value, 0xFF);
+                value
+            }
+            0x0007 => self.read_data(bus),
+            //Write only registers return whatever is left on the data bus
+            _ => self.io_latch,
+        }
+    }
+
+    pub fn cpu_write(&mut self, bus: &mut impl PpuBus, address: u16, value: u8) {
+        //Any write fully drives the data bus
+        self.refresh_io_latch(value, 0xFF);
+
+        match address & 0x0007 {
+            0x0000 => {
+                self.ctrl = value;
+                self.t = (self.t & 0xF3FF) | ((value as u16 & 0x03) << 10);
+                self.update_nmi();
+            }
+            0x0001 => self.mask = value,
+            0x0003 => self.oam_addr = value,
+            0x0004 => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x0005 => {
+                if !self.w {
+                    self.t = (self.t & 0xFFE0) | (value as u16 >> 3);
+                    self.x = value & 0x07;
+                } else {
+                    self.t = (self.t & 0x8C1F) | ((value as u16 & 0x07) << 12) | ((value as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            }
+            0x0006 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                    self.drive_vram_address(bus);
+                }
+                self.w = !self.w;
+            }
+            0x0007 => self.write_data(bus, value),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //NTSC frame layout
+    const VBLANK_SCANLINE: u16 = 241;
+    const PRE_RENDER_SCANLINE: u16 = 261;
+
+    struct MockPpuBus {
+        mem: [u8; 0x4000],
+        addresses: Vec<(u16, u64)>,
+    }
+    impl PpuBus for MockPpuBus {
+        fn ppu_read(&mut self, address: u16) -> u8 {
+            self.mem[(address & 0x3FFF) as usize]
+        }
+        fn ppu_write(&mut self, address: u16, value: u8) {
+            self.mem[(address & 0x3FFF) as usize] = value;
+        }
+        fn address_driven(&mut self, address: u16, ppu_cycle: u64) {
+            self.addresses.push((address, ppu_cycle));
+        }
+    }
+    impl MockPpuBus {
+        fn new() -> Self {
+            MockPpuBus {
+                mem: [0u8; 0x4000],
+                addresses: vec![],
+            }
+        }
+    }
+
+    fn set_vram_address(ppu: &mut Ppu, bus: &mut MockPpuBus, address: u16) {
+        ppu.cpu_write(bus, 0x2006, (address >> 8) as u8);
+        ppu.cpu_write(bus, 0x2006, address as u8);
+    }
+
+    //Clock the PPU until it is about to process the given dot
+    fn run_to(ppu: &mut Ppu, bus: &mut MockPpuBus, scanline: u16, dot: u16) {
+        while !(ppu.scanline == scanline && ppu.dot == dot) {
+            ppu.clock(bus);
+        }
+    }
+
+    #[test]
+    fn vblank_set_at_scanline_241_dot_1() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE, 1);
+        assert_eq!(ppu.status & Ppu::STATUS_VBLANK, 0);
+
+        ppu.clock(&mut bus);
+        assert_ne!(ppu.status & Ppu::STATUS_VBLANK, 0);
+
+        run_to(&mut ppu, &mut bus, PRE_RENDER_SCANLINE, 2);
+        assert_eq!(ppu.status & Ppu::STATUS_VBLANK, 0);
+    }
+
+    #[test]
+    fn nmi_raised_when_enabled() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE, 1 + NMI_DELAY_DOTS as u16);
+        assert!(ppu.poll_nmi());
+        //Edge triggered, so only one NMI per VBlank
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn no_nmi_when_disabled() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE + 1, 0);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn status_read_before_vblank_suppresses_flag_and_nmi() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE, 1);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2002) & Ppu::STATUS_VBLANK, 0);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE + 1, 0);
+        assert_eq!(ppu.status & Ppu::STATUS_VBLANK, 0);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn status_read_at_vblank_returns_set_and_suppresses_nmi() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE, 2);
+        assert_ne!(ppu.cpu_read(&mut bus, 0x2002) & Ppu::STATUS_VBLANK, 0);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE + 1, 0);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn status_read_clears_vblank() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE + 1, 0);
+        assert_ne!(ppu.cpu_read(&mut bus, 0x2002) & Ppu::STATUS_VBLANK, 0);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2002) & Ppu::STATUS_VBLANK, 0);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_triggers_nmi() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE + 1, 0);
+        assert!(!ppu.poll_nmi());
+
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+        for _ in 0..NMI_DELAY_DOTS {
+            ppu.clock(&mut bus);
+        }
+        assert!(ppu.poll_nmi());
+
+        //Writing the enable bit again while it is already set is not a new edge
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+        for _ in 0..NMI_DELAY_DOTS {
+            ppu.clock(&mut bus);
+        }
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn disabling_nmi_right_after_vblank_cancels_it() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE, 2);
+        ppu.cpu_write(&mut bus, 0x2000, 0x00);
+
+        run_to(&mut ppu, &mut bus, VBLANK_SCANLINE + 1, 0);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn odd_frame_skips_a_dot_when_rendering() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_BACKGROUND);
+
+        let mut even_frame_dots = 0;
+        run_to(&mut ppu, &mut bus, 0, 1);
+        while !(ppu.scanline == 0 && ppu.dot == 0) {
+            ppu.clock(&mut bus);
+            even_frame_dots += 1;
+        }
+        let mut odd_frame_dots = 0;
+        ppu.clock(&mut bus);
+        while !(ppu.scanline == 0 && ppu.dot == 0) {
+            ppu.clock(&mut bus);
+            odd_frame_dots += 1;
+        }
+
+        assert_eq!(even_frame_dots + 1, 341 * 262);
+        assert_eq!(odd_frame_dots + 1, 341 * 262 - 1);
+    }
+
+    #[test]
+    fn ppudata_read_is_buffered() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        bus.mem[0x2000] = 0x11;
+        bus.mem[0x2001] = 0x22;
+
+        set_vram_address(&mut ppu, &mut bus, 0x2000);
+        //First read returns the stale buffer, each later read lags by one
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2007), 0x00);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2007), 0x11);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2007), 0x22);
+    }
+
+    #[test]
+    fn palette_read_is_immediate_and_refills_buffer() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        bus.mem[0x2F05] = 0x99;
+
+        set_vram_address(&mut ppu, &mut bus, 0x3F05);
+        ppu.cpu_write(&mut bus, 0x2007, 0x2A);
+
+        set_vram_address(&mut ppu, &mut bus, 0x3F05);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2007) & 0x3F, 0x2A);
+        assert_eq!(ppu.read_buffer, 0x99);
+    }
+
+    #[test]
+    fn palette_read_top_bits_from_open_bus() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        set_vram_address(&mut ppu, &mut bus, 0x3F00);
+        ppu.cpu_write(&mut bus, 0x2007, 0x0F);
+        set_vram_address(&mut ppu, &mut bus, 0x3F00);
+
+        //Last write left $3F on the bus, but bits 6-7 come from the $C0 written here
+        ppu.cpu_write(&mut bus, 0x2003, 0xC0);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2007), 0xCF);
+    }
+
+    #[test]
+    fn palette_mirrors_backdrop() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        set_vram_address(&mut ppu, &mut bus, 0x3F10);
+        ppu.cpu_write(&mut bus, 0x2007, 0x21);
+
+        assert_eq!(ppu.palette[0x00], 0x21);
+    }
+
+    #[test]
+    fn ppudata_increment_1_or_32() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        set_vram_address(&mut ppu, &mut bus, 0x2000);
+        ppu.cpu_write(&mut bus, 0x2007, 0x01);
+        ppu.cpu_write(&mut bus, 0x2007, 0x02);
+        assert_eq!(bus.mem[0x2000], 0x01);
+        assert_eq!(bus.mem[0x2001], 0x02);
+        assert_eq!(ppu.v, 0x2002);
+
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_VRAM_INCREMENT);
+        set_vram_address(&mut ppu, &mut bus, 0x2000);
+        ppu.cpu_write(&mut bus, 0x2007, 0x03);
+        ppu.cpu_write(&mut bus, 0x2007, 0x04);
+        assert_eq!(bus.mem[0x2000], 0x03);
+        assert_eq!(bus.mem[0x2020], 0x04);
+        assert_eq!(ppu.v, 0x2040);
+    }
+
+    #[test]
+    fn write_only_registers_return_open_bus() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        ppu.cpu_write(&mut bus, 0x2003, 0x5A);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2000), 0x5A);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2005), 0x5A);
+        //Low 5 bits of PPUSTATUS are open bus too
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2002), 0x1A);
+        //Status read drove bits 5-7 low
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2001), 0x1A);
+    }
+
+    #[test]
+    fn open_bus_decays() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        ppu.cpu_write(&mut bus, 0x2003, 0xFF);
+        for _ in 0..(OPEN_BUS_DECAY_FRAMES as usize - 1) {
+            run_to(&mut ppu, &mut bus, 1, 0);
+            run_to(&mut ppu, &mut bus, 0, 0);
+        }
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2000), 0xFF);
+
+        run_to(&mut ppu, &mut bus, 1, 0);
+        run_to(&mut ppu, &mut bus, 0, 0);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2000), 0x00);
+    }
+
+    #[test]
+    fn oam_data_write_and_read() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        ppu.cpu_write(&mut bus, 0x2003, 0x01);
+        ppu.cpu_write(&mut bus, 0x2004, 0xFF);
+        ppu.cpu_write(&mut bus, 0x2004, 0xFF);
+
+        //Byte 2 of each sprite is attributes, bits 2-4 are unimplemented
+        ppu.cpu_write(&mut bus, 0x2003, 0x01);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2004), 0xFF);
+        ppu.cpu_write(&mut bus, 0x2003, 0x02);
+        assert_eq!(ppu.cpu_read(&mut bus, 0x2004), 0xE3);
+    }
+
+    #[test]
+    fn status_read_resets_write_toggle() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        ppu.cpu_write(&mut bus, 0x2006, 0x21);
+        ppu.cpu_read(&mut bus, 0x2002);
+        set_vram_address(&mut ppu, &mut bus, 0x2345);
+
+        assert_eq!(ppu.v, 0x2345);
+    }
+
+    //Dots on the given scanline where A12 went from low to high
+    fn a12_rises(bus: &MockPpuBus, ppu: &Ppu, scanline: u64) -> Vec<u64> {
+        let line_start = scanline * DOTS_PER_SCANLINE as u64;
+        let mut rises = vec![];
+        let mut last_a12 = false;
+        for &(address, cycle) in &bus.addresses {
+            let a12 = address & 0x1000 != 0;
+            if a12 && !last_a12 && cycle >= line_start && cycle < line_start + DOTS_PER_SCANLINE as u64 {
+                rises.push(cycle - line_start);
+            }
+            last_a12 = a12;
+        }
+        assert!(ppu.cycle > line_start);
+        rises
+    }
+
+    #[test]
+    fn no_fetches_with_rendering_disabled() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        run_to(&mut ppu, &mut bus, 240, 0);
+        assert!(bus.addresses.is_empty());
+    }
+
+    #[test]
+    fn a12_rises_once_per_line_with_sprites_on_1000() {
+        //Background at $0000 and sprites at $1000 is the layout MMC3 games use
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_SPRITE_TABLE);
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_BACKGROUND | Ppu::MASK_SHOW_SPRITES);
+        bus.addresses.clear();
+
+        run_to(&mut ppu, &mut bus, 11, 0);
+        //Each sprite slot fetches from $1000 after two garbage nametable fetches. Mappers
+        //filter these short dips out themselves, the PPU just reports the raw bus
+        let expected: Vec<u64> = (0..8).map(|slot| 261 + slot * 8).collect();
+        for scanline in 0..10 {
+            assert_eq!(a12_rises(&bus, &ppu, scanline), expected);
+        }
+    }
+
+    #[test]
+    fn a12_rises_with_background_on_1000() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_BACKGROUND_TABLE);
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_BACKGROUND);
+        bus.addresses.clear();
+
+        run_to(&mut ppu, &mut bus, 2, 0);
+        //Every background pattern fetch follows a nametable fetch, so A12 rises once per tile,
+        //then again for the two prefetched tiles of the next line
+        let rises = a12_rises(&bus, &ppu, 1);
+        assert_eq!(rises.len(), 32 + 2);
+        assert_eq!(rises[0], 5);
+        assert_eq!(rises[32], 325);
+    }
+
+    #[test]
+    fn tall_sprites_drive_a12_from_tile_index() {
+        //8x16 sprites pick the table from bit 0 of the tile, and empty slots fetch tile $FF
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.oam.fill(0xFF);
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_SPRITE_SIZE);
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_SPRITES);
+        bus.addresses.clear();
+
+        run_to(&mut ppu, &mut bus, 2, 0);
+        let expected: Vec<u64> = (0..8).map(|slot| 261 + slot * 8).collect();
+        assert_eq!(a12_rises(&bus, &ppu, 1), expected);
+    }
+
+    #[test]
+    fn sprite_fetch_addresses() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        //Sprite 0 at Y=0 using tile $42, vertically flipped
+        ppu.oam[0] = 0x00;
+        ppu.oam[1] = 0x42;
+        ppu.oam[2] = 0x80;
+        ppu.oam[4..].fill(0xF0);
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_SPRITES);
+        bus.addresses.clear();
+
+        run_to(&mut ppu, &mut bus, 3, 0);
+        let line_start = 2 * DOTS_PER_SCANLINE as u64;
+        let fetch = |dot: u64| bus.addresses.iter().find(|&&(_, cycle)| cycle == line_start + dot).unwrap().0;
+
+        //Row 2 of a flipped 8 pixel sprite is row 5 of the tile
+        assert_eq!(fetch(261), 0x0425);
+        assert_eq!(fetch(263), 0x042D);
+        //Second slot is empty
+        assert_eq!(fetch(269), 0x0FF0);
+    }
+
+    #[test]
+    fn sprite_overflow_on_ninth_sprite() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.oam.fill(0xF0);
+        for sprite in 0..9 {
+            ppu.oam[sprite * 4] = 0x10;
+        }
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_SPRITES);
+
+        run_to(&mut ppu, &mut bus, 0x10, 258);
+        assert_ne!(ppu.status & Ppu::STATUS_SPRITE_OVERFLOW, 0);
+    }
+
+    #[test]
+    fn ppuaddr_write_drives_bus() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+
+        set_vram_address(&mut ppu, &mut bus, 0x1234);
+        assert_eq!(bus.addresses.last().unwrap().0, 0x1234);
+
+        ppu.cpu_write(&mut bus, 0x2007, 0x00);
+        assert_eq!(bus.addresses.last().unwrap().0, 0x1235);
+    }
+
+    fn frame_dots(ppu: &mut Ppu, bus: &mut MockPpuBus) -> usize {
+        let mut dots = 1;
+        ppu.clock(bus);
+        while !(ppu.scanline == 0 && ppu.dot == 0) {
+            ppu.clock(bus);
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn pal_frame_is_312_lines_without_dot_skip() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.set_region(Region::Pal);
+        ppu.cpu_write(&mut bus, 0x2001, Ppu::MASK_SHOW_BACKGROUND);
+
+        assert_eq!(frame_dots(&mut ppu, &mut bus), 341 * 312);
+        assert_eq!(frame_dots(&mut ppu, &mut bus), 341 * 312);
+    }
+
+    #[test]
+    fn pal_nmi_at_241() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.set_region(Region::Pal);
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+
+        run_to(&mut ppu, &mut bus, 241, 1 + NMI_DELAY_DOTS as u16);
+        assert!(ppu.poll_nmi());
+
+        //Still in VBlank after NTSC would have left it
+        run_to(&mut ppu, &mut bus, 262, 0);
+        assert_ne!(ppu.status & Ppu::STATUS_VBLANK, 0);
+        run_to(&mut ppu, &mut bus, 311, 2);
+        assert_eq!(ppu.status & Ppu::STATUS_VBLANK, 0);
+    }
+
+    #[test]
+    fn dendy_vblank_starts_at_291() {
+        let mut ppu = Ppu::new();
+        let mut bus = MockPpuBus::new();
+        ppu.set_region(Region::Dendy);
+        ppu.cpu_write(&mut bus, 0x2000, Ppu::CTRL_NMI_ENABLE);
+
+        run_to(&mut ppu, &mut bus, 250, 0);
+        assert_eq!(ppu.status & Ppu::STATUS_VBLANK, 0);
+        assert!(!ppu.poll_nmi());
+
+        run_to(&mut ppu, &mut bus, 291, 1 + NMI_DELAY_DOTS as u16);
+        assert!(ppu.poll_nmi());
+
+        run_to(&mut ppu, &mut bus, 0, 0);
+        assert_eq!(frame_dots(&mut ppu, &mut bus), 341 * 312);
+    }
+}
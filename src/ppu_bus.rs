@@ -0,0 +1,77 @@
+use crate::mapper::Mapper;
+
+pub trait PpuBus {
+    fn ppu_read(&mut self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, value: u8);
+    //Called with every address the PPU puts on its bus, stamped with the PPU cycle it was
+    //driven on. Boards that watch the bus (e.g. A12 scanline counters) hook in here
+    fn address_driven(&mut self, _addr: u16, _ppu_cycle: u64) {}
+}
+
+//View of everything the PPU can see on its own address bus. Built on demand by NesBus
+//from its own fields so the PPU can borrow them while NesBus still owns them
+pub struct NesPpuBus<'a> {
+    pub mapper: &'a mut dyn Mapper,
+    pub chr_rom: &'a [u8],
+    pub chr_ram: &'a mut [u8],
+    pub vram: &'a mut [u8; 0x1000],
+}
+impl PpuBus for NesPpuBus<'_> {
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        //Pattern tables and nametables both live behind the cartridge, which decides
+        //where each fetch lands. Palette RAM is inside the PPU and never gets here
+        self.mapper.ppu_read(self.chr_rom, self.chr_ram, self.vram, address & 0x3FFF)
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.mapper.ppu_write(self.chr_rom, self.chr_ram, self.vram, address & 0x3FFF, value);
+    }
+
+    fn address_driven(&mut self, address: u16, ppu_cycle: u64) {
+        self.mapper.ppu_address(address & 0x3FFF, ppu_cycle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::new_mapper;
+    use crate::rom_loader::Nametable;
+
+    #[test]
+    fn nametable_write_read() {
+        let mut mapper = new_mapper(0, 0, Nametable::Vertical).unwrap();
+        let mut chr_ram = vec![0x00; 8 * 1024];
+        let mut vram = [0x00; 0x1000];
+        let mut ppu_bus = NesPpuBus { mapper: mapper.as_mut(), chr_rom: &[], chr_ram: &mut chr_ram, vram: &mut vram };
+
+        ppu_bus.ppu_write(0x2005, 0x67);
+        assert_eq!(ppu_bus.ppu_read(0x2005), 0x67);
+        //$3000-$3EFF mirrors $2000-$2EFF
+        assert_eq!(ppu_bus.ppu_read(0x3005), 0x67);
+    }
+
+    #[test]
+    fn pattern_table_goes_to_chr_ram() {
+        let mut mapper = new_mapper(0, 0, Nametable::Vertical).unwrap();
+        let mut chr_ram = vec![0x00; 8 * 1024];
+        let mut vram = [0x00; 0x1000];
+        let mut ppu_bus = NesPpuBus { mapper: mapper.as_mut(), chr_rom: &[], chr_ram: &mut chr_ram, vram: &mut vram };
+
+        ppu_bus.ppu_write(0x0010, 0x67);
+        assert_eq!(ppu_bus.ppu_read(0x0010), 0x67);
+        assert_eq!(chr_ram[0x0010], 0x67);
+        assert!(vram.iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn pattern_table_reads_chr_rom() {
+        let mut mapper = new_mapper(0, 0, Nametable::Vertical).unwrap();
+        let chr_rom = vec![0xEA; 8 * 1024];
+        let mut vram = [0x00; 0x1000];
+        let mut ppu_bus = NesPpuBus { mapper: mapper.as_mut(), chr_rom: &chr_rom, chr_ram: &mut [], vram: &mut vram };
+
+        ppu_bus.ppu_write(0x0010, 0x67);
+        assert_eq!(ppu_bus.ppu_read(0x0010), 0xEA);
+    }
+}
@@ -0,0 +1,92 @@
+//Console timing region. Decides the clock dividers and frame layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+impl Region {
+    //NES 2.0 byte 12 bits 0-1. Multi-region carts (2) run as NTSC
+    pub fn from_nes2_timing(value: u8) -> Self {
+        match value & 0x03 {
+            1 => Region::Pal,
+            3 => Region::Dendy,
+            _ => Region::Ntsc,
+        }
+    }
+
+    pub fn master_clock_hz(&self) -> f64 {
+        match self {
+            Region::Ntsc => 21_477_272.0,
+            Region::Pal | Region::Dendy => 26_601_712.0,
+        }
+    }
+
+    //Master clocks per CPU cycle
+    pub fn cpu_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    //Master clocks per PPU dot. PAL ends up with 3.2 dots per CPU cycle
+    pub fn ppu_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock_hz(&self) -> f64 {
+        self.master_clock_hz() / self.cpu_divider() as f64
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    //Scanline where the VBlank flag is raised (and NMI fires). Dendy keeps PAL's line count
+    //but holds off VBlank for 51 post-render lines so its timing stays close to NTSC games
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    pub fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame() - 1
+    }
+
+    //Only the NTSC 2C02 drops a dot on odd frames
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nes2_timing_byte() {
+        assert_eq!(Region::from_nes2_timing(0), Region::Ntsc);
+        assert_eq!(Region::from_nes2_timing(1), Region::Pal);
+        assert_eq!(Region::from_nes2_timing(2), Region::Ntsc);
+        assert_eq!(Region::from_nes2_timing(3), Region::Dendy);
+        //Upper bits of byte 12 are unused
+        assert_eq!(Region::from_nes2_timing(0xFD), Region::Pal);
+    }
+
+    #[test]
+    fn cpu_clock_rates() {
+        assert_eq!(Region::Ntsc.cpu_clock_hz().round(), 1_789_773.0);
+        assert_eq!(Region::Pal.cpu_clock_hz().round(), 1_662_607.0);
+        assert_eq!(Region::Dendy.cpu_clock_hz().round(), 1_773_447.0);
+    }
+}
@@ -1,12 +1,280 @@
+use std::fmt;
 use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::mapper::MapperError;
+
+use crate::region::Region;
 
 //Define an enum for the Nametable option
-enum Nametable {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nametable {
     Horizontal,
     Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+//NES 2.0 byte 12 bits 0-1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+//Byte 7 bits 0-1. Vs. System details come from byte 13, extended types keep its low nibble
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem { ppu: VsPpu, hardware: VsHardware },
+    Playchoice10,
+    Extended(u8),
+}
+//Byte 13 low nibble for Vs. System games, which PPU (and so which palette) the board has
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VsPpu {
+    Rp2c03b,
+    Rp2c03g,
+    Rp2c04_0001,
+    Rp2c04_0002,
+    Rp2c04_0003,
+    Rp2c04_0004,
+    Rc2c03b,
+    Rc2c03c,
+    Rc2c05_01,
+    Rc2c05_02,
+    Rc2c05_03,
+    Rc2c05_04,
+    Rc2c05_05,
+    Reserved(u8),
+}
+//Byte 13 high nibble for Vs. System games, mostly which protection the game expects
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VsHardware {
+    Unisystem,
+    RbiBaseball,
+    TkoBoxing,
+    SuperXevious,
+    IceClimberJapan,
+    DualSystem,
+    RaidOnBungelingBay,
+    Reserved(u8),
+}
+//Byte 15 bits 0-5, the controller or peripheral the game expects by default. Only the
+//common ones are named
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpansionDevice {
+    Unspecified,
+    StandardControllers,
+    FourScore,
+    FamicomFourPlayers,
+    VsSystem,
+    VsSystemReversed,
+    VsPinball,
+    VsZapper,
+    Zapper,
+    TwoZappers,
+    BandaiHyperShot,
+    PowerPadA,
+    PowerPadB,
+    FamilyTrainerA,
+    FamilyTrainerB,
+    ArkanoidNes,
+    ArkanoidFamicom,
+    Other(u8),
+}
+impl Timing {
+    fn from_nes2(value: u8) -> Self {
+        match value & 0x03 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        }
+    }
+}
+impl ConsoleType {
+    fn from_nes2(flags_7: u8, byte_13: u8) -> Self {
+        match flags_7 & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem {
+                ppu: VsPpu::from_nes2(byte_13 & 0x0F),
+                hardware: VsHardware::from_nes2(byte_13 >> 4),
+            },
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(byte_13 & 0x0F),
+        }
+    }
+}
+impl VsPpu {
+    fn from_nes2(value: u8) -> Self {
+        match value {
+            0x0 => VsPpu::Rp2c03b,
+            0x1 => VsPpu::Rp2c03g,
+            0x2 => VsPpu::Rp2c04_0001,
+            0x3 => VsPpu::Rp2c04_0002,
+            0x4 => VsPpu::Rp2c04_0003,
+            0x5 => VsPpu::Rp2c04_0004,
+            0x6 => VsPpu::Rc2c03b,
+            0x7 => VsPpu::Rc2c03c,
+            0x8 => VsPpu::Rc2c05_01,
+            0x9 => VsPpu::Rc2c05_02,
+            0xA => VsPpu::Rc2c05_03,
+            0xB => VsPpu::Rc2c05_04,
+            0xC => VsPpu::Rc2c05_05,
+            value => VsPpu::Reserved(value),
+        }
+    }
+}
+impl VsHardware {
+    fn from_nes2(value: u8) -> Self {
+        match value {
+            0x0 => VsHardware::Unisystem,
+            0x1 => VsHardware::RbiBaseball,
+            0x2 => VsHardware::TkoBoxing,
+            0x3 => VsHardware::SuperXevious,
+            0x4 => VsHardware::IceClimberJapan,
+            0x5 => VsHardware::DualSystem,
+            0x6 => VsHardware::RaidOnBungelingBay,
+            value => VsHardware::Reserved(value),
+        }
+    }
 }
+impl ExpansionDevice {
+    fn from_nes2(value: u8) -> Self {
+        match value & 0x3F {
+            0x00 => ExpansionDevice::Unspecified,
+            0x01 => ExpansionDevice::StandardControllers,
+            0x02 => ExpansionDevice::FourScore,
+            0x03 => ExpansionDevice::FamicomFourPlayers,
+            0x04 => ExpansionDevice::VsSystem,
+            0x05 => ExpansionDevice::VsSystemReversed,
+            0x06 => ExpansionDevice::VsPinball,
+            0x07 => ExpansionDevice::VsZapper,
+            0x08 => ExpansionDevice::Zapper,
+            0x09 => ExpansionDevice::TwoZappers,
+            0x0A => ExpansionDevice::BandaiHyperShot,
+            0x0B => ExpansionDevice::PowerPadA,
+            0x0C => ExpansionDevice::PowerPadB,
+            0x0D => ExpansionDevice::FamilyTrainerA,
+            0x0E => ExpansionDevice::FamilyTrainerB,
+            0x0F => ExpansionDevice::ArkanoidNes,
+            0x10 => ExpansionDevice::ArkanoidFamicom,
+            value => ExpansionDevice::Other(value),
+        }
+    }
+}
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    //Not even a full 16 byte header
+    TooSmall { size: usize },
+    BadMagic,
+    //The header promises more PRG/CHR than the file holds. Sizes in bytes
+    TruncatedPrg { expected: usize, actual: usize },
+    TruncatedChr { expected: usize, actual: usize },
+    //NES 2.0 sizes that can't be right: larger than the file, or an exponent past usize
+    Nes2SizeMismatch { expected: usize, actual: usize },
+    Nes2SizeOverflow,
+    //No board for the header's mapper/submapper
+    Mapper(MapperError),
+}
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "Failed to read ROM: {}", error),
+            RomError::TooSmall { size } => write!(f, "File size too small, invalid header ({} bytes)", size),
+            RomError::BadMagic => write!(f, "Invalid ROM header, missing NES<EOF> magic"),
+            RomError::TruncatedPrg { expected, actual } => {
+                write!(f, "ROM too small for PRG data, expected {} bytes but found {}", expected, actual)
+            }
+            RomError::TruncatedChr { expected, actual } => {
+                write!(f, "ROM too small for CHR data, expected {} bytes but found {}", expected, actual)
+            }
+            RomError::Nes2SizeMismatch { expected, actual } => write!(
+                f,
+                "NES 2.0 header describes a {} byte file but it is only {} bytes",
+                expected, actual
+            ),
+            RomError::Nes2SizeOverflow => write!(f, "NES 2.0 ROM size is too large"),
+            RomError::Mapper(error) => write!(f, "{}", error),
+        }
+    }
+}
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::Io(error) => Some(error),
+            RomError::Mapper(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+impl From<MapperError> for RomError {
+    fn from(error: MapperError) -> Self {
+        RomError::Mapper(error)
+    }
+}
+
+//Header fields the loader didn't trust and replaced with defaults
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderWarning {
+    //"DiskDude!" written over bytes 7-15 by an old dumping tool
+    DiskDude,
+    //Something else in bytes 12-15, which iNES 1.0 requires to be zero
+    DirtyHeader,
+    //Byte 7 flags from before iNES 1.0, where bytes 7-15 had no meaning yet
+    ArchaicHeader,
+    //No PRG RAM size in an iNES header, 8K given because of the battery or the mapper
+    AssumedPrgRam,
+}
+impl fmt::Display for HeaderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let garbage = match self {
+            HeaderWarning::AssumedPrgRam => return write!(f, "No PRG RAM size in the header, assumed 8K"),
+            HeaderWarning::DiskDude => "\"DiskDude!\" in the header",
+            HeaderWarning::DirtyHeader => "Garbage in header bytes 12-15",
+            HeaderWarning::ArchaicHeader => "Archaic iNES header",
+        };
+        write!(f, "{}, ignored bytes 7-15 (mapper high nibble, Vs. System, PRG RAM size, TV system)", garbage)
+    }
+}
+
+//NES 2.0 ROM size from the LSB in byte 4/5 and the MSB nibble in byte 9. An MSB of $F
+//switches to exponent-multiplier notation, 2^E * (MM * 2 + 1) bytes from EEEEEEMM
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> Result<usize, RomError> {
+    match msb {
+        0x0F => 1usize
+            .checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul((lsb as usize & 0x03) * 2 + 1))
+            .ok_or(RomError::Nes2SizeOverflow),
+        _ => Ok(((msb as usize) << 8 | lsb as usize) * unit),
+    }
+}
+//iNES dumps leave byte 8 at 0 even when the board has work RAM. These boards almost always
+//carry 8K at $6000-$7FFF: MMC1, MMC3, MMC5, Namco 163, VRC4/VRC6, NINA-001, FME-7 and VRC7
+fn ines_board_has_prg_ram(mapper: u16) -> bool {
+    matches!(mapper, 1 | 4 | 5 | 19 | 21 | 23 | 24 | 25 | 26 | 34 | 69 | 85)
+}
+//NES 2.0 RAM sizes are shift counts, 64 << n bytes with 0 meaning none
+fn nes2_ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        shift => 64 << shift,
+    }
+}
+
+//Which header format the file uses. Archaic covers pre-1.0 iNES and dirty headers
 #[allow(non_camel_case_types)]
-enum NESMode {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NESMode {
     iNES,
     NES2,
     iNESArch,
@@ -14,36 +282,42 @@ enum NESMode {
 pub struct Cartridge {
     //Cartridge struct used to define all flags and parameters in NES ROM header.
     //This struct will also store the raw bytes of the header and the PRG/CHR ROM
-    validated: bool,
-    prg_rom_banks: usize,
-    chr_rom_banks: usize,
     prg_size_bytes: usize,
     chr_size_bytes: usize,
     nametable_mirroring: Nametable, //This can be an enum?
     has_battery: bool,
     trainer_flag: bool,
     alt_nametable_flag: bool,
-    mapper: u8,
-    vs_unisystem: bool,
+    mapper: u16,
+    submapper: u8,
     prg_ram_size_bytes: usize,
+    //NES 2.0 only. Battery backed RAM sizes, and CHR RAM that is separate from the PRG RAM
+    prg_nvram_size_bytes: usize,
+    chr_ram_size_bytes: usize,
+    chr_nvram_size_bytes: usize,
     nes_mode: NESMode,
+    region: Region,
+    timing: Timing,
+    console_type: ConsoleType,
+    misc_roms: u8,
+    expansion_device: ExpansionDevice,
+    warnings: Vec<HeaderWarning>,
+    trainer_data: Option<Vec<u8>>,
     raw_header_bytes: Vec<u8>,
     prg_rom_data: Vec<u8>,
     chr_rom_data: Vec<u8>,
-    chr_ram_data: Vec<u8>,
 }
 impl Cartridge {
-    fn load(bytes: &[u8]) -> Result<Self, &'static str> {
+    fn load(bytes: &[u8]) -> Result<Self, RomError> {
         //Need to ingest bytes and put them into Self
 
         if bytes.len() < 16 {
-            return Err("File size too small, invalid header");
+            return Err(RomError::TooSmall { size: bytes.len() });
         }
 
         //Validate header
-        let validated = &bytes[0..4] == b"NES\x1A";
-        if !validated {
-            return Err("Invalid ROM header");
+        if &bytes[0..4] != b"NES\x1A" {
+            return Err(RomError::BadMagic);
         }
 
         let nametable_mirroring = (bytes[6] & 0x01) != 0;
@@ -52,10 +326,8 @@ impl Cartridge {
             false => Nametable::Horizontal,
         };
 
-        let mut prg_rom_banks = bytes[4] as usize;
-        let mut chr_rom_banks = bytes[5] as usize;
-        let mut prg_size_bytes = prg_rom_banks * 16 * 1024;
-        let mut chr_size_bytes = chr_rom_banks * 8 * 1024;
+        let mut prg_size_bytes = bytes[4] as usize * 16 * 1024;
+        let mut chr_size_bytes = bytes[5] as usize * 8 * 1024;
 
         //Flags 6
         let has_battery = (bytes[6] & 0x02) != 0; //second bit
@@ -69,15 +341,34 @@ impl Cartridge {
 
         //Flags 8
         let prg_ram_banks = bytes[8] as usize;
-        let prg_ram_size_bytes: usize;
-        if prg_ram_banks == 0 {
-            prg_ram_size_bytes = 8 * 1024;
-        } else {
-            prg_ram_size_bytes = prg_ram_banks * 8 * 1024;
-        }
+        let mut prg_ram_size_bytes = prg_ram_banks * 8 * 1024;
+        let mut prg_nvram_size_bytes = 0;
+        //iNES boards without CHR ROM get 8K of CHR RAM
+        let mut chr_ram_size_bytes = match chr_size_bytes {
+            0 => 8 * 1024,
+            _ => 0,
+        };
+        let mut chr_nvram_size_bytes = 0;
+
+        //Flags 9. TV system bit, rarely set in iNES dumps but honoured when it is
+        let mut region = match bytes[9] & 0x01 {
+            0 => Region::Ntsc,
+            _ => Region::Pal,
+        };
 
         //Calculate Mapper
-        let mapper = (upper_mapper_nybble << 4) | (lower_mapper_nybble);
+        let mut mapper = ((upper_mapper_nybble << 4) | (lower_mapper_nybble)) as u16;
+        let mut submapper = 0;
+        let mut timing = match region {
+            Region::Pal => Timing::Pal,
+            _ => Timing::Ntsc,
+        };
+        let mut console_type = match vs_unisystem {
+            true => ConsoleType::VsSystem { ppu: VsPpu::Rp2c03b, hardware: VsHardware::Unisystem },
+            false => ConsoleType::Nes,
+        };
+        let mut misc_roms = 0;
+        let mut expansion_device = ExpansionDevice::Unspecified;
 
         //Detect iNES mode
         let nes_mode: NESMode;
@@ -89,24 +380,55 @@ impl Cartridge {
             trainer_size = 512;
         }
 
-        if ines_compare == 0 && bytes[12..16].iter().all(|&x| x == 0) {
+        let mut warnings = vec![];
+        let dirty = bytes[12..16].iter().any(|&x| x != 0);
+
+        if ines_compare == 0 && !dirty {
             nes_mode = NESMode::iNES;
-        } else if ines_compare == 0x04 {
+        } else if ines_compare != 0x08 {
+            //Archaic iNES, or iNES with garbage left behind by old tools. Only flags 6 can be
+            //trusted, everything from byte 7 on falls back to the usual defaults
             nes_mode = NESMode::iNESArch;
-        } else if ines_compare == 0x08 {
-            prg_rom_banks = (bytes[4] as u16 | ((bytes[9] as u16 & 0x000F) << 8)) as usize;
-            chr_rom_banks = (bytes[5] as u16 | (bytes[9] as u16 & 0x00F0) << 4) as usize;
-            prg_size_bytes = prg_rom_banks * 16 * 1024;
-            chr_size_bytes = chr_rom_banks * 8 * 1024;
-            let expected_size = 16 + trainer_size + prg_size_bytes + chr_size_bytes;
+            warnings.push(match &bytes[7..16] {
+                b"DiskDude!" => HeaderWarning::DiskDude,
+                _ if dirty => HeaderWarning::DirtyHeader,
+                _ => HeaderWarning::ArchaicHeader,
+            });
+            mapper &= 0x0F;
+            console_type = ConsoleType::Nes;
+            prg_ram_size_bytes = 0;
+            region = Region::Ntsc;
+            timing = Timing::Ntsc;
+        } else {
+            prg_size_bytes = nes2_rom_size(bytes[4], bytes[9] & 0x0F, 16 * 1024)?;
+            chr_size_bytes = nes2_rom_size(bytes[5], bytes[9] >> 4, 8 * 1024)?;
+            let expected_size = (16 + trainer_size).saturating_add(prg_size_bytes).saturating_add(chr_size_bytes);
             //Compare expected size with actual file size
             if file_size >= expected_size {
                 nes_mode = NESMode::NES2;
+                //Byte 8: mapper bits 8-11 and the submapper
+                mapper |= (bytes[8] as u16 & 0x0F) << 8;
+                submapper = bytes[8] >> 4;
+                //Bytes 10 and 11: volatile RAM in the low nibbles, battery backed in the high
+                prg_ram_size_bytes = nes2_ram_size(bytes[10] & 0x0F);
+                prg_nvram_size_bytes = nes2_ram_size(bytes[10] >> 4);
+                chr_ram_size_bytes = nes2_ram_size(bytes[11] & 0x0F);
+                chr_nvram_size_bytes = nes2_ram_size(bytes[11] >> 4);
+                //Byte 12 holds the CPU/PPU timing region
+                region = Region::from_nes2_timing(bytes[12]);
+                timing = Timing::from_nes2(bytes[12]);
+                console_type = ConsoleType::from_nes2(bytes[7], bytes[13]);
+                misc_roms = bytes[14] & 0x03;
+                expansion_device = ExpansionDevice::from_nes2(bytes[15]);
             } else {
-                return Err("NES 2.0 header detected but file size is too small.");
+                return Err(RomError::Nes2SizeMismatch { expected: expected_size, actual: file_size });
             }
-        } else {
-            return Err("Invalid NES Mode");
+        }
+
+        //Without a size in the header only boards known to have RAM, or with save RAM, get 8K
+        if nes_mode != NESMode::NES2 && prg_ram_size_bytes == 0 && (has_battery || ines_board_has_prg_ram(mapper)) {
+            prg_ram_size_bytes = 8 * 1024;
+            warnings.push(HeaderWarning::AssumedPrgRam);
         }
 
         let raw_header_bytes = bytes[0..16].to_owned();
@@ -117,26 +439,33 @@ impl Cartridge {
 
         //Check if PRG ROM fits in file
         if prg_offset + prg_size_bytes > bytes.len() {
-            return Err("ROM too small for PRG data");
+            return Err(RomError::TruncatedPrg {
+                expected: prg_size_bytes,
+                actual: bytes.len().saturating_sub(prg_offset),
+            });
         }
         let prg_rom_data = bytes[prg_offset..prg_size_bytes + prg_offset].to_vec();
+        //The trainer sits between the header and PRG ROM, so it fits whenever PRG ROM does
+        let trainer_data = trainer_flag.then(|| bytes[16..prg_offset].to_vec());
 
         let mut chr_rom_data = vec![];
-        let mut chr_ram_data = vec![];
+        //A board needs something behind the pattern tables, NES 2.0 headers that declare
+        //neither CHR ROM nor CHR RAM get the usual 8K of RAM
+        if chr_size_bytes == 0 && chr_ram_size_bytes + chr_nvram_size_bytes == 0 {
+            chr_ram_size_bytes = 8 * 1024;
+        }
 
         //Check if CHR data fits in ROM
-        if chr_rom_banks == 0 {
-            chr_ram_data = vec![0; 8 * 1024];
-        } else {
+        if chr_size_bytes > 0 {
             if chr_offset + chr_size_bytes > bytes.len() {
-                return Err("ROM too small for CHR data");
+                return Err(RomError::TruncatedChr {
+                    expected: chr_size_bytes,
+                    actual: bytes.len().saturating_sub(chr_offset),
+                });
             }
             chr_rom_data = bytes[chr_offset..chr_size_bytes + chr_offset].to_vec();
         }
         Ok(Self {
-            validated,
-            prg_rom_banks,
-            chr_rom_banks,
             prg_size_bytes,
             chr_size_bytes,
             nametable_mirroring,
@@ -144,45 +473,198 @@ impl Cartridge {
             trainer_flag,
             alt_nametable_flag,
             mapper,
-            vs_unisystem,
+            submapper,
             prg_ram_size_bytes,
+            prg_nvram_size_bytes,
+            chr_ram_size_bytes,
+            chr_nvram_size_bytes,
             nes_mode,
+            region,
+            timing,
+            console_type,
+            misc_roms,
+            expansion_device,
+            warnings,
+            trainer_data,
             raw_header_bytes,
             prg_rom_data,
             chr_rom_data,
-            chr_ram_data,
         })
     }
-    // fn new() -> Self {
-    //     Self {
-    //         validated: false,
-    //         prg_rom_banks: 0,
-    //         chr_rom_banks: 0,
-    //         prg_size_bytes: 0,
-    //         chr_size_bytes: 0,
-    //         nametable_mirroring: Nametable::Horizontal,
-    //         has_battery: false,
-    //         trainer_flag: false,
-    //         alt_nametable_flag: false,
-    //         mapper: 0,
-    //         vs_unisystem: false,
-    //         prg_ram_size_bytes: 0,
-    //         nes_mode: NESMode::iNES,
-    //         raw_header_bytes: Vec::new(),
-    //         prg_rom_data: Vec::new(),
-    //         chr_rom_data: Vec::new(),
-    //         chr_ram_data: Vec::new(),
-    //     }
-    // }
+    //Parse an iNES/NES 2.0 image already in memory
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RomError> {
+        Self::load(bytes)
+    }
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, RomError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Self::load(&bytes)
+    }
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
+        //Read rom file into memory
+        let bytes = fs::read(path)?;
+        Self::load(&bytes)
+    }
+
+    //Timing region from the header. NES 2.0 byte 12, or iNES byte 9 bit 0
+    pub fn region(&self) -> Region {
+        self.region
+    }
+    //Mapper number, 12 bits on NES 2.0 and 8 on iNES
+    pub fn mapper(&self) -> u16 {
+        self.mapper
+    }
+    //NES 2.0 submapper, 0 for iNES
+    pub fn submapper(&self) -> u8 {
+        self.submapper
+    }
+    //Corrections applied to a dirty or archaic header, for the frontend to report
+    pub fn warnings(&self) -> &[HeaderWarning] {
+        &self.warnings
+    }
+    //Header mirroring. The four screen bit overrides the H/V bit
+    pub fn mirroring(&self) -> Nametable {
+        match self.alt_nametable_flag {
+            true => Nametable::FourScreen,
+            false => self.nametable_mirroring,
+        }
+    }
+    pub fn nes_mode(&self) -> NESMode {
+        self.nes_mode
+    }
+    //The 16 header bytes as they were in the file
+    pub fn header(&self) -> &[u8] {
+        &self.raw_header_bytes
+    }
+    //Battery backed save RAM (or other non-volatile memory) on the board
+    pub fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+    //512 bytes between the header and PRG ROM
+    pub fn has_trainer(&self) -> bool {
+        self.trainer_flag
+    }
+    //Loaded into PRG RAM at $7000-$71FF before reset
+    pub fn trainer(&self) -> Option<&[u8]> {
+        self.trainer_data.as_deref()
+    }
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+    pub fn console_type(&self) -> ConsoleType {
+        self.console_type
+    }
+    //NES 2.0 byte 14, extra ROM chips after CHR ROM
+    pub fn misc_roms(&self) -> u8 {
+        self.misc_roms
+    }
+    pub fn expansion_device(&self) -> ExpansionDevice {
+        self.expansion_device
+    }
+
+    //Sizes in bytes. RAM sizes are what the board should be given at power on: the header's
+    //value on NES 2.0, the iNES defaults otherwise
+    pub fn prg_rom_size(&self) -> usize {
+        self.prg_size_bytes
+    }
+    pub fn chr_rom_size(&self) -> usize {
+        self.chr_size_bytes
+    }
+    pub fn prg_ram_size(&self) -> usize {
+        self.prg_ram_size_bytes
+    }
+    pub fn prg_nvram_size(&self) -> usize {
+        self.prg_nvram_size_bytes
+    }
+    pub fn chr_ram_size(&self) -> usize {
+        self.chr_ram_size_bytes
+    }
+    pub fn chr_nvram_size(&self) -> usize {
+        self.chr_nvram_size_bytes
+    }
+    pub fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom_data
+    }
+    //Empty for boards with only CHR RAM
+    pub fn chr_rom(&self) -> &[u8] {
+        &self.chr_rom_data
+    }
 }
 
-//Eventually, this will take file name as an argument to allow for multiple ROMs to load
-pub fn load_rom() -> Result<Cartridge, &'static str> {
-    //Read rom file into memory
-    let bytes = fs::read("nestest.nes").expect("Failed to read ROM");
+//ROM contents are left out, they would bury the header fields
+impl fmt::Debug for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cartridge")
+            .field("nes_mode", &self.nes_mode())
+            .field("mapper", &self.mapper())
+            .field("submapper", &self.submapper())
+            .field("mirroring", &self.mirroring())
+            .field("has_battery", &self.has_battery())
+            .field("has_trainer", &self.has_trainer())
+            .field("prg_rom_size", &self.prg_rom_size())
+            .field("chr_rom_size", &self.chr_rom_size())
+            .field("prg_ram_size", &self.prg_ram_size())
+            .field("prg_nvram_size", &self.prg_nvram_size())
+            .field("chr_ram_size", &self.chr_ram_size())
+            .field("chr_nvram_size", &self.chr_nvram_size())
+            .field("timing", &self.timing())
+            .field("console_type", &self.console_type())
+            .field("misc_roms", &self.misc_roms())
+            .field("expansion_device", &self.expansion_device())
+            .field("warnings", &self.warnings())
+            .finish_non_exhaustive()
+    }
+}
+//One line summary, e.g. "iNES, mapper 4, 256K PRG ROM, 128K CHR ROM, 8K PRG RAM, battery,
+//vertical mirroring, NTSC"
+impl fmt::Display for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn size(bytes: usize) -> String {
+            match bytes % 1024 {
+                0 => format!("{}K", bytes / 1024),
+                _ => format!("{} bytes", bytes),
+            }
+        }
 
-    let cartridge = Cartridge::load(&bytes)?;
-    Ok(cartridge)
+        let format = match self.nes_mode {
+            NESMode::iNES => "iNES",
+            NESMode::NES2 => "NES 2.0",
+            NESMode::iNESArch => "archaic iNES",
+        };
+        write!(f, "{}, mapper {}", format, self.mapper)?;
+        if self.submapper != 0 {
+            write!(f, ".{}", self.submapper)?;
+        }
+        write!(f, ", {} PRG ROM", size(self.prg_size_bytes))?;
+        if self.chr_size_bytes > 0 {
+            write!(f, ", {} CHR ROM", size(self.chr_size_bytes))?;
+        }
+        if self.chr_ram_size_bytes + self.chr_nvram_size_bytes > 0 {
+            write!(f, ", {} CHR RAM", size(self.chr_ram_size_bytes + self.chr_nvram_size_bytes))?;
+        }
+        if self.prg_ram_size_bytes + self.prg_nvram_size_bytes > 0 {
+            write!(f, ", {} PRG RAM", size(self.prg_ram_size_bytes + self.prg_nvram_size_bytes))?;
+        }
+        if self.has_battery {
+            write!(f, ", battery")?;
+        }
+        if self.trainer_flag {
+            write!(f, ", trainer")?;
+        }
+        let mirroring = match self.mirroring() {
+            Nametable::Horizontal => "horizontal",
+            Nametable::Vertical => "vertical",
+            Nametable::SingleScreenLower | Nametable::SingleScreenUpper => "single screen",
+            Nametable::FourScreen => "four screen",
+        };
+        let timing = match self.timing() {
+            Timing::Ntsc => "NTSC",
+            Timing::Pal => "PAL",
+            Timing::MultiRegion => "multi-region",
+            Timing::Dendy => "Dendy",
+        };
+        write!(f, ", {} mirroring, {}", mirroring, timing)
+    }
 }
 
 #[cfg(test)]
@@ -221,6 +703,7 @@ mod tests {
         let cartridge = Cartridge::load(&rom_bytes);
 
         assert!(cartridge.is_err());
+        assert!(matches!(cartridge, Err(RomError::TooSmall { size: 15 })));
     }
 
     #[test]
@@ -229,6 +712,48 @@ mod tests {
         let cartridge = Cartridge::load(&rom_bytes);
 
         assert!(cartridge.is_err());
+        assert!(matches!(cartridge, Err(RomError::BadMagic)));
+    }
+
+    #[test]
+    fn truncated_rom_errors() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x02;
+        rom_bytes[5] = 0x01;
+        let error = Cartridge::load(&rom_bytes[..16 + 20 * 1024]).err().unwrap();
+        assert!(matches!(error, RomError::TruncatedPrg { expected: 0x8000, actual: 0x5000 }));
+        assert_eq!(error.to_string(), "ROM too small for PRG data, expected 32768 bytes but found 20480");
+
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x02;
+        let error = Cartridge::load(&rom_bytes).err().unwrap();
+        assert!(matches!(error, RomError::TruncatedChr { expected: 0x4000, actual: 0x2000 }));
+    }
+
+    #[test]
+    fn inconsistent_nes2_sizes() {
+        let mut rom_bytes = nes2_rom(16 * 1024, 8 * 1024);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x02;
+        let error = Cartridge::load(&rom_bytes).err().unwrap();
+        assert!(matches!(error, RomError::Nes2SizeMismatch { expected: 0x8010, actual: 0x6010 }));
+
+        //2^63 * 7 bytes of PRG
+        rom_bytes[4] = 0xFF;
+        rom_bytes[9] = 0x0F;
+        let error = Cartridge::load(&rom_bytes).err().unwrap();
+        assert!(matches!(error, RomError::Nes2SizeOverflow));
+    }
+
+    #[test]
+    fn io_and_mapper_errors_convert() {
+        let error = RomError::from(io::Error::new(io::ErrorKind::NotFound, "missing.nes"));
+        assert_eq!(error.to_string(), "Failed to read ROM: missing.nes");
+        assert!(std::error::Error::source(&error).is_some());
+
+        let error = RomError::from(MapperError::Unsupported { mapper: 255, submapper: 0 });
+        assert_eq!(error.to_string(), "Unsupported mapper 255");
     }
 
     #[test]
@@ -242,8 +767,7 @@ mod tests {
             Err(err) => panic!("{}", err),
         };
 
-        assert_eq!(cartridge.prg_rom_banks, 1);
-        assert_eq!(cartridge.prg_size_bytes, 16 * 1024);
+        assert_eq!(cartridge.prg_rom_size(), 16 * 1024);
     }
 
     #[test]
@@ -257,8 +781,7 @@ mod tests {
             Err(err) => panic!("{}", err),
         };
 
-        assert_eq!(cartridge.prg_rom_banks, 2);
-        assert_eq!(cartridge.prg_size_bytes, 32 * 1024);
+        assert_eq!(cartridge.prg_rom_size(), 32 * 1024);
     }
 
     #[test]
@@ -273,9 +796,8 @@ mod tests {
             Err(err) => panic!("{}", err),
         };
 
-        assert_eq!(cartridge.chr_rom_banks, 1);
-        assert_eq!(cartridge.chr_size_bytes, 8 * 1024);
-        assert_eq!(cartridge.chr_ram_data, vec![]);
+        assert_eq!(cartridge.chr_rom_size(), 8 * 1024);
+        assert_eq!(cartridge.chr_ram_size(), 0);
 
         //Set CHR banks to 0 and check ROM size is 0 and RAM is 8kiB
         rom_bytes[5] = 0;
@@ -285,8 +807,340 @@ mod tests {
             Err(err) => panic!("{}", err),
         };
 
-        assert_eq!(cartridge.chr_rom_banks, 0);
-        assert_eq!(cartridge.chr_size_bytes, 0);
-        assert_eq!(cartridge.chr_ram_data, vec![0; 8 * 1024]);
+        assert_eq!(cartridge.chr_rom_size(), 0);
+        assert_eq!(cartridge.chr_ram_size(), 8 * 1024);
+    }
+
+    #[test]
+    fn region_from_nes2_header() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[7] = 0x08;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.region(), Region::Ntsc);
+
+        rom_bytes[12] = 0x01;
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.region(), Region::Pal);
+
+        rom_bytes[12] = 0x03;
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.region(), Region::Dendy);
+    }
+
+    #[test]
+    fn region_from_ines_tv_system() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[9] = 0x01;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.region(), Region::Pal);
+    }
+
+    fn nes2_rom(prg_size: usize, chr_size: usize) -> Vec<u8> {
+        let mut rom_bytes = vec![0x00; 16 + prg_size + chr_size];
+        rom_bytes[0..4].copy_from_slice(b"NES\x1A");
+        rom_bytes[7] = 0x08;
+        rom_bytes
+    }
+
+    #[test]
+    fn nes2_mapper_and_submapper() {
+        let mut rom_bytes = nes2_rom(16 * 1024, 8 * 1024);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[6] = 0x50;
+        rom_bytes[7] = 0x18;
+        rom_bytes[8] = 0x21;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.mapper(), 0x115);
+        assert_eq!(cartridge.submapper(), 2);
+    }
+
+    #[test]
+    fn nes2_ram_sizes() {
+        let mut rom_bytes = nes2_rom(16 * 1024, 0);
+        rom_bytes[4] = 0x01;
+        //8K PRG RAM, 32K PRG NVRAM, 16K CHR RAM, no CHR NVRAM
+        rom_bytes[10] = 0x97;
+        rom_bytes[11] = 0x08;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.prg_ram_size(), 8 * 1024);
+        assert_eq!(cartridge.prg_nvram_size(), 32 * 1024);
+        assert_eq!(cartridge.chr_ram_size(), 16 * 1024);
+        assert_eq!(cartridge.chr_nvram_size(), 0);
+
+        //No PRG RAM at all is allowed on NES 2.0
+        rom_bytes[10] = 0x00;
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.prg_ram_size(), 0);
+    }
+
+    #[test]
+    fn nes2_exponent_multiplier_sizes() {
+        //PRG 2^13 * 3 = 24K, CHR 2^10 * 1 = 1K
+        let mut rom_bytes = nes2_rom(24 * 1024, 1024);
+        rom_bytes[4] = (13 << 2) | 0x01;
+        rom_bytes[5] = 10 << 2;
+        rom_bytes[9] = 0xFF;
+        rom_bytes[16 + 24 * 1024] = 0xC3;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.prg_rom_size(), 24 * 1024);
+        assert_eq!(cartridge.chr_rom_size(), 1024);
+        assert_eq!(cartridge.prg_rom_data.len(), 24 * 1024);
+        assert_eq!(cartridge.chr_rom_data[0], 0xC3);
+    }
+
+    #[test]
+    fn nes2_console_and_expansion() {
+        let mut rom_bytes = nes2_rom(16 * 1024, 8 * 1024);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[7] = 0x09;
+        rom_bytes[12] = 0x02;
+        rom_bytes[13] = 0x23;
+        rom_bytes[14] = 0x01;
+        rom_bytes[15] = 0x08;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.timing, Timing::MultiRegion);
+        assert_eq!(cartridge.region(), Region::Ntsc);
+        assert_eq!(
+            cartridge.console_type,
+            ConsoleType::VsSystem { ppu: VsPpu::Rp2c04_0002, hardware: VsHardware::TkoBoxing }
+        );
+        assert_eq!(cartridge.misc_roms, 1);
+        assert_eq!(cartridge.expansion_device, ExpansionDevice::Zapper);
+    }
+
+    #[test]
+    fn diskdude_header_masks_upper_mapper_nibble() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[6] = 0x21;
+        rom_bytes[7..16].copy_from_slice(b"DiskDude!");
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.mapper(), 2);
+        assert_eq!(cartridge.console_type(), ConsoleType::Nes);
+        assert_eq!(cartridge.mirroring(), Nametable::Vertical);
+        assert_eq!(cartridge.warnings(), [HeaderWarning::DiskDude]);
+    }
+
+    #[test]
+    fn dirty_header_falls_back_to_defaults() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[6] = 0x40;
+        rom_bytes[7] = 0x10;
+        rom_bytes[8] = 0x04;
+        rom_bytes[9] = 0x01;
+        rom_bytes[14] = 0x55;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.mapper(), 4);
+        assert_eq!(cartridge.prg_ram_size(), 8 * 1024);
+        assert_eq!(cartridge.region(), Region::Ntsc);
+        assert_eq!(cartridge.warnings(), [HeaderWarning::DirtyHeader, HeaderWarning::AssumedPrgRam]);
+
+        //The same upper nibble is trusted on a clean header
+        rom_bytes[14] = 0x00;
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.mapper(), 0x14);
+        assert_eq!(cartridge.prg_ram_size(), 32 * 1024);
+        assert!(cartridge.warnings().is_empty());
+    }
+
+    #[test]
+    fn prg_ram_defaults() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+
+        //iNES byte 8 of 0 on a board without RAM is left alone
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.prg_ram_size(), 0);
+        assert!(cartridge.warnings().is_empty());
+
+        //The battery flag or a board that normally has RAM gets 8K, with a warning
+        for flags_6 in [0x02, 0x10, 0x40] {
+            rom_bytes[6] = flags_6;
+            let cartridge = Cartridge::load(&rom_bytes).unwrap();
+            assert_eq!(cartridge.prg_ram_size(), 8 * 1024);
+            assert_eq!(cartridge.warnings(), [HeaderWarning::AssumedPrgRam]);
+        }
+        assert_eq!(HeaderWarning::AssumedPrgRam.to_string(), "No PRG RAM size in the header, assumed 8K");
+
+        //A size in byte 8 is used as is
+        rom_bytes[8] = 0x02;
+        let cartridge = Cartridge::load(&rom_bytes).unwrap();
+        assert_eq!(cartridge.prg_ram_size(), 16 * 1024);
+        assert!(cartridge.warnings().is_empty());
+        rom_bytes[6] = 0x00;
+        rom_bytes[8] = 0x00;
+
+        //NES 2.0 says exactly, 0 is none
+        rom_bytes[7] = 0x08;
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.prg_ram_size(), 0);
+    }
+
+    #[test]
+    fn archaic_header_warning() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[7] = 0x34;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.mapper(), 0);
+        assert_eq!(cartridge.warnings(), [HeaderWarning::ArchaicHeader]);
+        assert_eq!(
+            HeaderWarning::ArchaicHeader.to_string(),
+            "Archaic iNES header, ignored bytes 7-15 (mapper high nibble, Vs. System, PRG RAM size, TV system)"
+        );
+    }
+
+    #[test]
+    fn load_from_reader_and_path() {
+        let mut rom_bytes = create_test_rom(false, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[6] = 0x10;
+
+        let cartridge = match Cartridge::from_reader(io::Cursor::new(&rom_bytes)) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.mapper(), 1);
+
+        let path = std::env::temp_dir().join(format!("rom_loader_test_{}.nes", std::process::id()));
+        fs::write(&path, &rom_bytes).unwrap();
+        let cartridge = Cartridge::from_path(&path);
+        fs::remove_file(&path).unwrap();
+        match cartridge {
+            Ok(cartridge) => assert_eq!(cartridge.prg_rom_data, Cartridge::from_bytes(&rom_bytes).unwrap().prg_rom_data),
+            Err(err) => panic!("{}", err),
+        }
+
+        let missing = Cartridge::from_path(std::env::temp_dir().join("rom_loader_test_missing.nes"));
+        assert!(matches!(missing, Err(RomError::Io(_))));
+    }
+
+    #[test]
+    fn trainer_is_kept() {
+        let mut rom_bytes = create_test_rom(true, 1, true);
+        rom_bytes = set_magic_header(rom_bytes);
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        rom_bytes[6] = 0x04;
+
+        let cartridge = match Cartridge::load(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert!(cartridge.has_trainer());
+        assert_eq!(cartridge.trainer(), Some(&[0x04; 512][..]));
+        //PRG ROM still starts after the trainer
+        assert_eq!(cartridge.prg_rom(), &[0x01; 16 * 1024][..]);
+
+        let mut rom_bytes = set_magic_header(create_test_rom(false, 1, true));
+        rom_bytes[4] = 0x01;
+        rom_bytes[5] = 0x01;
+        let cartridge = Cartridge::load(&rom_bytes).unwrap();
+        assert_eq!(cartridge.trainer(), None);
+    }
+
+    #[test]
+    fn metadata_accessors_and_display() {
+        let mut rom_bytes = nes2_rom(32 * 1024, 0);
+        rom_bytes[4] = 0x02;
+        rom_bytes[6] = 0x43;
+        rom_bytes[8] = 0x10;
+        rom_bytes[10] = 0x70;
+        rom_bytes[11] = 0x07;
+
+        let cartridge = match Cartridge::from_bytes(&rom_bytes) {
+            Ok(cartridge) => cartridge,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(cartridge.nes_mode(), NESMode::NES2);
+        assert_eq!(cartridge.header(), &rom_bytes[0..16]);
+        assert!(cartridge.has_battery());
+        assert!(!cartridge.has_trainer());
+        assert_eq!(cartridge.prg_rom_size(), 32 * 1024);
+        assert_eq!(cartridge.prg_rom().len(), 32 * 1024);
+        assert!(cartridge.chr_rom().is_empty());
+        assert_eq!(cartridge.prg_nvram_size(), 8 * 1024);
+        assert_eq!(cartridge.chr_ram_size(), 8 * 1024);
+        assert_eq!(cartridge.console_type(), ConsoleType::Nes);
+        assert_eq!(
+            cartridge.to_string(),
+            "NES 2.0, mapper 4.1, 32K PRG ROM, 8K CHR RAM, 8K PRG RAM, battery, vertical mirroring, NTSC"
+        );
+        let debug = format!("{:?}", cartridge);
+        assert!(debug.starts_with("Cartridge { nes_mode: NES2, mapper: 4, submapper: 1"));
+        assert!(debug.ends_with(".. }"));
     }
 }
@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::apu::Channel;
+use crate::cpu::CPU;
+use crate::cpu_bus::NesBus;
+
+const HEADER_BYTES: u32 = 44;
+
+//Mono 16 bit PCM WAV writer. The RIFF and data sizes are left as 0 and patched in by
+//finish, so samples can be streamed without knowing the length up front
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_bytes: u32,
+}
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let channels: u16 = 1;
+        let bits_per_sample: u16 = 16;
+        let block_align = channels * bits_per_sample / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; //PCM
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&bits_per_sample.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter { writer, data_bytes: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_bytes += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    //Fill in the chunk sizes and hand back the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_BYTES - 8 + self.data_bytes).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+//Run the console for a number of frames and stream its mixed audio into a WAV. Output is
+//deterministic for a given ROM and input, so recordings can be hashed or diffed against
+//golden files in CI
+pub fn record_frames<W: Write + Seek>(cpu: &mut CPU<NesBus>, frames: u32, writer: W) -> io::Result<W> {
+    let mut wav = WavWriter::new(writer, cpu.bus().sample_rate())?;
+    //Drop anything generated before recording started
+    cpu.bus_mut().take_samples();
+
+    for _ in 0..frames {
+        cpu.run_frame();
+        wav.write_samples(&cpu.bus_mut().take_samples_i16())?;
+    }
+    wav.finish()
+}
+
+pub fn record_to_file(cpu: &mut CPU<NesBus>, frames: u32, path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    record_frames(cpu, frames, file)?;
+    Ok(())
+}
+
+//Same as record_frames but with one WAV per channel, each holding only that channel
+pub fn record_channels<W: Write + Seek>(
+    cpu: &mut CPU<NesBus>,
+    frames: u32,
+    writers: Vec<(Channel, W)>,
+) -> io::Result<Vec<W>> {
+    let sample_rate = cpu.bus().sample_rate();
+    let mut streams = vec![];
+    for (channel, writer) in writers {
+        cpu.bus_mut().apu_mut().set_capture(channel, true);
+        streams.push((channel, WavWriter::new(writer, sample_rate)?));
+    }
+
+    for _ in 0..frames {
+        cpu.run_frame();
+        for (channel, wav) in streams.iter_mut() {
+            wav.write_samples(&cpu.bus_mut().apu_mut().take_channel_samples_i16(*channel))?;
+        }
+    }
+
+    let mut finished = vec![];
+    for (channel, wav) in streams {
+        cpu.bus_mut().apu_mut().set_capture(channel, false);
+        finished.push(wav.finish()?);
+    }
+    Ok(finished)
+}
+
+//Writes <stem>_<channel>.wav next to path for every channel
+pub fn record_channels_to_files(cpu: &mut CPU<NesBus>, frames: u32, path: &Path) -> io::Result<()> {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("audio");
+    let mut writers = vec![];
+    for channel in Channel::ALL {
+        let channel_path = path.with_file_name(format!("{}_{}.wav", stem, channel.name()));
+        writers.push((channel, BufWriter::new(File::create(channel_path)?)));
+    }
+    record_channels(cpu, frames, writers)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_and_sizes() {
+        let mut wav = WavWriter::new(Cursor::new(vec![]), 44100).unwrap();
+        wav.write_samples(&[0x0102, -1, 0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &42u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[22..24], &1u16.to_le_bytes());
+        assert_eq!(&bytes[24..28], &44100u32.to_le_bytes());
+        assert_eq!(&bytes[28..32], &88200u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &6u32.to_le_bytes());
+        assert_eq!(&bytes[44..], &[0x02, 0x01, 0xFF, 0xFF, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn empty_recording() {
+        let wav = WavWriter::new(Cursor::new(vec![]), 48000).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44);
+        assert_eq!(&bytes[40..44], &0u32.to_le_bytes());
+    }
+}